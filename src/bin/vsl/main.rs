@@ -0,0 +1,458 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+mod publish;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use videostream::{
+    client::Client, fourcc::FourCC, frame::Frame, host::Host, latency::Histogram, y4m::Y4mWriter,
+};
+
+/// Inspect and debug videostream sockets.
+#[derive(Parser)]
+#[command(name = "vsl", version)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the resolution, format, frame rate and serial gaps of a socket.
+    Info {
+        /// Path of the videostream socket.
+        socket: String,
+        /// Number of frames to sample.
+        #[arg(short, long, default_value_t = 30)]
+        frames: usize,
+        /// Seconds to wait for each frame.
+        #[arg(short, long, default_value_t = 5.0)]
+        timeout: f32,
+    },
+    /// Save frames from a socket to a directory.
+    Dump {
+        /// Path of the videostream socket.
+        socket: String,
+        /// Directory receiving the frames.
+        output: PathBuf,
+        /// Number of frames to save.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+        /// Format of the saved frames.
+        #[arg(short, long, value_enum, default_value_t = DumpFormat::Raw)]
+        format: DumpFormat,
+        /// Seconds to wait for each frame.
+        #[arg(short, long, default_value_t = 5.0)]
+        timeout: f32,
+    },
+    /// Report the frame rate, latency and drops of a socket every second.
+    Stats {
+        /// Path of the videostream socket.
+        socket: String,
+        /// Seconds to run for, until interrupted when zero.
+        #[arg(short, long, default_value_t = 0)]
+        duration: u64,
+        /// Seconds to wait for each frame.
+        #[arg(short, long, default_value_t = 5.0)]
+        timeout: f32,
+    },
+    /// Publish a directory of PNG/JPEG images, a Y4M file or raw frames.
+    Publish {
+        /// Path of the videostream socket to create.
+        socket: String,
+        /// Image directory, Y4M file or raw file.
+        input: PathBuf,
+        /// Frames per second, defaults to the Y4M frame rate or 30.
+        #[arg(long)]
+        fps: Option<f64>,
+        /// Replay the input from the start once it ends.
+        #[arg(short, long = "loop")]
+        looping: bool,
+        /// Number of frames to publish, all by default.
+        #[arg(short = 'n', long)]
+        count: Option<u64>,
+        /// Milliseconds until published frames expire.
+        #[arg(long, default_value_t = 100)]
+        lifetime: u64,
+        /// Resolution of raw frames as WIDTHxHEIGHT.
+        #[arg(long, requires = "fourcc")]
+        size: Option<String>,
+        /// Fourcc of raw frames such as NV12, YUYV or RGB3.
+        #[arg(long, requires = "size")]
+        fourcc: Option<String>,
+    },
+    /// Print the versions of vsl and the VideoStream Library.
+    Version,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DumpFormat {
+    /// Raw frame buffers, one file per frame.
+    Raw,
+    /// PNG images, one file per frame.
+    Png,
+    /// A single YUV4MPEG2 video.
+    Y4m,
+}
+
+fn main() {
+    let args = Args::parse();
+    let result = match args.command {
+        Command::Info {
+            socket,
+            frames,
+            timeout,
+        } => info(&socket, frames, timeout),
+        Command::Dump {
+            socket,
+            output,
+            count,
+            format,
+            timeout,
+        } => dump(&socket, &output, count, format, timeout),
+        Command::Stats {
+            socket,
+            duration,
+            timeout,
+        } => stats(&socket, duration, timeout),
+        Command::Publish {
+            socket,
+            input,
+            fps,
+            looping,
+            count,
+            lifetime,
+            size,
+            fourcc,
+        } => publish(
+            &socket,
+            &input,
+            fps,
+            looping,
+            count,
+            Duration::from_millis(lifetime),
+            size.zip(fourcc),
+        ),
+        Command::Version => {
+            println!("vsl {}", env!("CARGO_PKG_VERSION"));
+            println!("VideoStream Library {}", videostream::version());
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("vsl: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn connect(socket: &str, timeout: f32) -> Result<Client, Box<dyn Error>> {
+    let client = Client::new(socket, false)
+        .map_err(|err| format!("failed to connect to {}: {}", socket, err))?;
+    client.set_timeout(timeout);
+    Ok(client)
+}
+
+/// Receives the next frame and locks it, frames which expire before they
+/// can be locked are counted as dropped.
+fn next_frame(client: &Client, dropped: &mut u64) -> Result<Frame, Box<dyn Error>> {
+    loop {
+        let frame = client.get_frame(0)?;
+        if frame.trylock().is_ok() {
+            return Ok(frame);
+        }
+        *dropped += 1;
+    }
+}
+
+/// Counts the serials skipped between consecutive frames.
+fn serial_gap(previous: &mut Option<i64>, serial: i64) -> u64 {
+    let gap = previous.map_or(0, |previous| (serial - previous - 1).max(0) as u64);
+    *previous = Some(serial);
+    gap
+}
+
+fn format_ns(ns: Option<i64>) -> String {
+    match ns {
+        Some(ns) => format!("{:.2}ms", ns as f64 / 1e6),
+        None => "-".to_owned(),
+    }
+}
+
+fn info(socket: &str, frames: usize, timeout: f32) -> Result<(), Box<dyn Error>> {
+    let client = connect(socket, timeout)?;
+    let mut dropped = 0;
+    let mut gaps = 0;
+    let mut serial = None;
+    let mut first = None;
+    let mut last = None;
+    let mut layout = None;
+
+    for _ in 0..frames.max(1) {
+        let frame = next_frame(&client, &mut dropped)?;
+        gaps += serial_gap(&mut serial, frame.serial());
+        first.get_or_insert((frame.serial(), frame.timestamp()));
+        last = Some((frame.serial(), frame.timestamp()));
+        layout.get_or_insert((
+            frame.width(),
+            frame.height(),
+            frame.stride(),
+            FourCC::from(frame.fourcc()),
+            frame.size(),
+            frame.path().map(str::to_owned),
+        ));
+        frame.unlock()?;
+    }
+
+    let (width, height, stride, fourcc, size, path) = layout.ok_or("no frames received")?;
+    println!("socket:     {}", socket);
+    println!("resolution: {}x{}", width, height);
+    println!("fourcc:     {}", fourcc);
+    println!("stride:     {}", stride);
+    println!("size:       {}", size);
+    if let Some(path) = path {
+        println!("buffer:     {}", path);
+    }
+    if let (Some((first_serial, first_time)), Some((last_serial, last_time))) = (first, last) {
+        if last_serial > first_serial && last_time > first_time {
+            let fps = (last_serial - first_serial) as f64 * 1e9 / (last_time - first_time) as f64;
+            println!("fps:        {:.2}", fps);
+        }
+        println!("serial:     {}..{}", first_serial, last_serial);
+    }
+    println!("gaps:       {}", gaps);
+    println!("dropped:    {}", dropped);
+    Ok(())
+}
+
+/// The pixels of a dumped frame, copied out before the frame is unlocked.
+enum Pixels {
+    Raw(Vec<u8>),
+    Rgb(image::RgbImage),
+}
+
+fn dump(
+    socket: &str,
+    output: &Path,
+    count: usize,
+    format: DumpFormat,
+    timeout: f32,
+) -> Result<(), Box<dyn Error>> {
+    let client = connect(socket, timeout)?;
+    fs::create_dir_all(output)?;
+    let mut dropped = 0;
+    let mut y4m: Option<Y4mWriter> = None;
+
+    for _ in 0..count {
+        let frame = next_frame(&client, &mut dropped)?;
+        if format == DumpFormat::Y4m {
+            let result = write_y4m(&mut y4m, output, &frame);
+            frame.unlock()?;
+            result?;
+            continue;
+        }
+        let serial = frame.serial();
+        let (width, height) = (frame.width(), frame.height());
+        let fourcc = FourCC::from(frame.fourcc());
+        // Copy the frame out so it is unlocked before writing to disk.
+        let pixels = match format {
+            DumpFormat::Raw => frame
+                .mmap()
+                .map(|data| Pixels::Raw(data.to_vec()))
+                .map_err(|_| "failed to map frame".into()),
+            _ => frame.to_rgb_image().map(Pixels::Rgb),
+        };
+        frame.unlock()?;
+
+        match pixels? {
+            Pixels::Raw(data) => {
+                let path = output.join(format!(
+                    "frame_{:06}_{}x{}.{}",
+                    serial,
+                    width,
+                    height,
+                    fourcc.to_string().to_lowercase()
+                ));
+                fs::write(&path, data)?;
+                println!("{}", path.display());
+            }
+            Pixels::Rgb(image) => {
+                let path = output.join(format!("frame_{:06}.png", serial));
+                image.save_with_format(&path, image::ImageFormat::Png)?;
+                println!("{}", path.display());
+            }
+        }
+    }
+
+    if let Some(writer) = y4m {
+        writer.into_inner()?;
+    }
+    if dropped > 0 {
+        eprintln!(
+            "dropped {} frames which expired before they were locked",
+            dropped
+        );
+    }
+    Ok(())
+}
+
+/// Appends the locked frame to the y4m video, creating it from the first
+/// frame with the frame rate given by its duration.
+fn write_y4m(
+    y4m: &mut Option<Y4mWriter>,
+    output: &Path,
+    frame: &Frame,
+) -> Result<(), Box<dyn Error>> {
+    let writer = match y4m {
+        Some(writer) => writer,
+        None => {
+            let path = output.join("frames.y4m");
+            let mut writer = Y4mWriter::create(&path)?;
+            let duration = frame.duration();
+            if duration > 0 {
+                let divisor = gcd(1_000_000_000, duration);
+                writer = writer.with_frame_rate(
+                    (1_000_000_000 / divisor) as u32,
+                    (duration / divisor) as u32,
+                );
+            }
+            println!("{}", path.display());
+            y4m.insert(writer)
+        }
+    };
+    writer.write_frame(frame)
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn publish(
+    socket: &str,
+    input: &Path,
+    fps: Option<f64>,
+    looping: bool,
+    count: Option<u64>,
+    lifetime: Duration,
+    raw: Option<(String, String)>,
+) -> Result<(), Box<dyn Error>> {
+    let raw = match raw {
+        Some((size, fourcc)) => {
+            let (width, height) = size
+                .split_once('x')
+                .ok_or("the size must be given as WIDTHxHEIGHT")?;
+            if fourcc.len() != 4 {
+                return Err("fourcc must be 4 character ascii code".into());
+            }
+            Some((
+                width.parse()?,
+                height.parse()?,
+                FourCC::from(fourcc.as_bytes()),
+            ))
+        }
+        None => None,
+    };
+    let mut source = publish::open(input, raw, fps, looping)?;
+    let host = Host::new(socket)?;
+    println!(
+        "publishing {} as {}x{} {} to {}",
+        input.display(),
+        source.width(),
+        source.height(),
+        source.format(),
+        socket
+    );
+    let published = publish::publish(&host, source.as_mut(), lifetime, count)?;
+    println!("published {} frames", published);
+    Ok(())
+}
+
+fn stats(socket: &str, duration: u64, timeout: f32) -> Result<(), Box<dyn Error>> {
+    let client = connect(socket, timeout)?;
+    let start = Instant::now();
+    let mut interval = Instant::now();
+    let mut serial = None;
+    let mut frames = 0;
+    let mut dropped = 0;
+    let mut latency = Histogram::new();
+    let mut total = Histogram::new();
+    let mut total_frames = 0;
+    let mut total_dropped = 0;
+
+    while duration == 0 || start.elapsed() < Duration::from_secs(duration) {
+        // Frames which expire before they are locked are counted by the
+        // serial gap they leave.
+        let frame = next_frame(&client, &mut 0)?;
+        latency.record(videostream::timestamp() - frame.timestamp());
+        dropped += serial_gap(&mut serial, frame.serial());
+        frames += 1;
+        frame.unlock()?;
+
+        let elapsed = interval.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            println!(
+                "fps {:6.2}  latency p50 {:>9} p99 {:>9}  drops {}",
+                frames as f64 / elapsed.as_secs_f64(),
+                format_ns(latency.p50()),
+                format_ns(latency.p99()),
+                dropped
+            );
+            total.merge(&latency);
+            total_frames += frames;
+            total_dropped += dropped;
+            latency = Histogram::new();
+            frames = 0;
+            dropped = 0;
+            interval = Instant::now();
+        }
+    }
+
+    total.merge(&latency);
+    total_frames += frames;
+    total_dropped += dropped;
+    println!(
+        "total: {} frames in {:.1}s, latency p50 {} p99 {} max {}, {} drops",
+        total_frames,
+        start.elapsed().as_secs_f64(),
+        format_ns(total.p50()),
+        format_ns(total.p99()),
+        format_ns(total.max()),
+        total_dropped
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_gap() {
+        let mut previous = None;
+        assert_eq!(serial_gap(&mut previous, 10), 0);
+        assert_eq!(serial_gap(&mut previous, 11), 0);
+        assert_eq!(serial_gap(&mut previous, 14), 2);
+        assert_eq!(previous, Some(14));
+        // Serials going backwards, such as after a host restart, are not gaps.
+        assert_eq!(serial_gap(&mut previous, 1), 0);
+        assert_eq!(serial_gap(&mut previous, 2), 0);
+    }
+
+    #[test]
+    fn test_gcd() {
+        assert_eq!(gcd(1920, 1080), 120);
+        assert_eq!(gcd(30000, 1001), 1);
+        assert_eq!(gcd(7, 0), 7);
+        assert_eq!(gcd(0, 5), 5);
+    }
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+    time::{Duration, Instant},
+};
+use videostream::{
+    fourcc::{FourCC, Layout},
+    frame::Frame,
+    host::Host,
+    source::{FileSource, FrameSource, ImageSource, SourceFrame},
+};
+
+/// Opens the input as a directory of PNG and JPEG images, a YUV4MPEG2 file
+/// or a raw file of frames of the given resolution and format.  Frames are
+/// read at the frame rate, which defaults to that of the YUV4MPEG2 file or
+/// 30 frames per second, and the input restarts once it ends when looping.
+pub fn open(
+    input: &Path,
+    raw: Option<(usize, usize, FourCC)>,
+    fps: Option<f64>,
+    looping: bool,
+) -> Result<Box<dyn FrameSource>, Box<dyn Error>> {
+    if input.is_dir() {
+        let mut images = ImageSource::open(input)?
+            .with_looping(looping)
+            .with_realtime(false);
+        if let Some(fps) = fps {
+            images = images.with_frame_rate(fps)?;
+        }
+        return Ok(Box::new(images));
+    }
+    if let Some((width, height, fourcc)) = raw {
+        let raw = Raw::new(input, width, height, fourcc, fps.unwrap_or(30.0), looping)?;
+        return Ok(Box::new(raw));
+    }
+    if input
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("y4m"))
+    {
+        let mut file = FileSource::open(input)
+            .map_err(|err| format!("{}: {}", input.display(), err))?
+            .with_looping(looping)
+            .with_realtime(false);
+        if let Some(fps) = fps {
+            file = file.with_frame_rate(fps)?;
+        }
+        return Ok(Box::new(file));
+    }
+    Err(format!(
+        "{} is not an image directory or y4m file, raw files need a resolution and fourcc",
+        input.display()
+    )
+    .into())
+}
+
+/// Raw frames of a fixed resolution and format stored back to back.
+pub struct Raw {
+    file: BufReader<File>,
+    layout: Layout,
+    duration: i64,
+    looping: bool,
+    index: u64,
+}
+
+impl Raw {
+    pub fn new(
+        path: &Path,
+        width: usize,
+        height: usize,
+        fourcc: FourCC,
+        fps: f64,
+        looping: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !(fps > 0.0 && fps.is_finite()) {
+            return Err("the frame rate must be positive".into());
+        }
+        Ok(Raw {
+            file: BufReader::new(File::open(path)?),
+            layout: Layout::new(fourcc, width, height, 0)?,
+            duration: (1e9 / fps) as i64,
+            looping,
+            index: 0,
+        })
+    }
+
+    /// Reads the next frame into the buffer, returning false at the end of
+    /// the file.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<bool, Box<dyn Error>> {
+        match self.file.read_exact(buffer) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl FrameSource for Raw {
+    fn width(&self) -> i32 {
+        self.layout.width as i32
+    }
+
+    fn height(&self) -> i32 {
+        self.layout.height as i32
+    }
+
+    fn format(&self) -> FourCC {
+        self.layout.fourcc
+    }
+
+    fn read_frame(&mut self) -> Result<Option<SourceFrame>, Box<dyn Error>> {
+        let layout = self.layout;
+        let frame = Frame::new(
+            layout.width as u32,
+            layout.height as u32,
+            layout.stride as u32,
+            &layout.fourcc.to_string(),
+        )?;
+        frame.alloc(None)?;
+        let buffer = frame.mmap_mut().map_err(|_| "failed to map frame")?;
+        layout.check(buffer)?;
+        let buffer = &mut buffer[..layout.size];
+        if !self.read(buffer)? {
+            if !self.looping || self.index == 0 {
+                return Ok(None);
+            }
+            self.file.seek(SeekFrom::Start(0))?;
+            if !self.read(buffer)? {
+                return Ok(None);
+            }
+        }
+
+        let timestamp = self.index as i64 * self.duration;
+        self.index += 1;
+        Ok(Some(SourceFrame {
+            frame,
+            timestamp,
+            duration: self.duration,
+        }))
+    }
+}
+
+/// Services the host clients until the deadline, polling at least once.
+fn service(host: &Host, deadline: Instant) -> Result<(), Box<dyn Error>> {
+    loop {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        if host.poll(wait.as_millis() as i64)? > 0 {
+            host.process()?;
+        }
+        if Instant::now() >= deadline {
+            return Ok(());
+        }
+    }
+}
+
+/// Posts the frames of the source to the host at the pace of their
+/// timestamps.  Frames expire after the lifetime and carry presentation
+/// timestamps counting up from zero.  Returns the number of frames published.
+pub fn publish(
+    host: &Host,
+    source: &mut dyn FrameSource,
+    lifetime: Duration,
+    count: Option<u64>,
+) -> Result<u64, Box<dyn Error>> {
+    let start = Instant::now();
+    let mut first = None;
+    let mut published = 0;
+
+    while count.is_none_or(|count| published < count) {
+        let Some(frame) = source.read_frame()? else {
+            break;
+        };
+        let pts = frame.timestamp - *first.get_or_insert(frame.timestamp);
+        service(host, start + Duration::from_nanos(pts.max(0) as u64))?;
+        let expires = videostream::timestamp() + lifetime.as_nanos() as i64;
+        host.post(frame.frame, expires, frame.duration, pts, pts)?;
+        published += 1;
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_y4m() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("vsl-publish-{}.y4m", std::process::id()));
+        let mut file = File::create(&path)?;
+        writeln!(file, "YUV4MPEG2 W4 H2 F25:1 Ip A1:1 C420jpeg")?;
+        for i in 0..3 {
+            file.write_all(b"FRAME\n")?;
+            file.write_all(&[i; 8])?;
+            file.write_all(&[10, 11, 20, 21])?;
+        }
+        drop(file);
+
+        let mut source = open(&path, None, None, false)?;
+        assert_eq!(source.format(), FourCC(*b"NV12"));
+        let mut frames = Vec::new();
+        while let Some(frame) = source.read_frame()? {
+            assert_eq!(frame.duration, 40_000_000);
+            frames.push(frame.frame);
+        }
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[2].mmap().unwrap(),
+            [2, 2, 2, 2, 2, 2, 2, 2, 10, 20, 11, 21]
+        );
+
+        let mut source = open(&path, None, Some(50.0), true)?;
+        for index in 0..4 {
+            let frame = source.read_frame()?.unwrap();
+            assert_eq!(frame.duration, 20_000_000);
+            assert_eq!(frame.frame.mmap().unwrap()[0], index % 3);
+        }
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("vsl-publish-{}.raw", std::process::id()));
+        let mut file = File::create(&path)?;
+        for i in 0..2 {
+            file.write_all(&[i; 2 * 2 * 3])?;
+        }
+        // A trailing partial frame is ignored.
+        file.write_all(&[9; 5])?;
+        drop(file);
+
+        let raw = Some((2, 2, FourCC(*b"RGB3")));
+        assert!(open(&path, None, None, false).is_err());
+        assert!(open(&path, raw, Some(0.0), false).is_err());
+
+        let mut source = open(&path, raw, None, true)?;
+        assert_eq!((source.width(), source.height()), (2, 2));
+        let mut timestamps = Vec::new();
+        for index in 0..3 {
+            let frame = source.read_frame()?.unwrap();
+            assert_eq!(frame.frame.mmap().unwrap(), [index % 2; 12]);
+            timestamps.push(frame.timestamp);
+        }
+        assert_eq!(timestamps, [0, 33_333_333, 66_666_666]);
+
+        let mut source = open(&path, raw, Some(10.0), false)?;
+        assert_eq!(source.read_frame()?.unwrap().duration, 100_000_000);
+        assert!(source.read_frame()?.is_some());
+        assert!(source.read_frame()?.is_none());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -1,20 +1,170 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright 2025 Au-Zone Technologies
 
-use crate::fourcc::FourCC;
+use crate::{fourcc::FourCC, v4l2};
 use dma_buf::DmaBuf;
 use std::{
+    cell::Cell,
     error::Error,
     ffi::{c_int, CString},
-    fmt, io,
-    os::fd::{BorrowedFd, FromRawFd, RawFd},
+    fmt,
+    fs::{File, OpenOptions},
+    io,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+        unix::fs::OpenOptionsExt,
+    },
+    path::Path,
+    slice, thread,
+    time::{Duration, Instant},
 };
 use unix_ts::Timestamp;
 use videostream_sys as ffi;
 
 type CameraFormats = Vec<FourCC>;
 
+pub const CID_BRIGHTNESS: u32 = 0x0098_0900;
+pub const CID_CONTRAST: u32 = 0x0098_0901;
+pub const CID_SATURATION: u32 = 0x0098_0902;
+pub const CID_HUE: u32 = 0x0098_0903;
+pub const CID_AUTO_WHITE_BALANCE: u32 = 0x0098_090c;
+pub const CID_EXPOSURE: u32 = 0x0098_0911;
+pub const CID_AUTOGAIN: u32 = 0x0098_0912;
+pub const CID_GAIN: u32 = 0x0098_0913;
+pub const CID_WHITE_BALANCE_TEMPERATURE: u32 = 0x0098_091a;
+pub const CID_SHARPNESS: u32 = 0x0098_091b;
+pub const CID_EXPOSURE_AUTO: u32 = 0x009a_0901;
+pub const CID_EXPOSURE_ABSOLUTE: u32 = 0x009a_0902;
+pub const CID_FOCUS_ABSOLUTE: u32 = 0x009a_090a;
+pub const CID_FOCUS_AUTO: u32 = 0x009a_090c;
+
+/// The capabilities of a camera, see [`Camera::capabilities`].
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub formats: Vec<FormatCapability>,
+    /// Whether the camera buffers can be exported as DMA buffers, None if
+    /// this could not be determined.
+    pub dmabuf: Option<bool>,
+}
+
+/// A pixel format supported by the camera along with its resolutions.
+#[derive(Debug, Clone)]
+pub struct FormatCapability {
+    pub fourcc: FourCC,
+    /// True if the format is captured through the multi-planar API.
+    pub multiplanar: bool,
+    pub sizes: FrameSizes,
+}
+
+#[derive(Debug, Clone)]
+pub enum FrameSizes {
+    /// The list of supported resolutions.
+    Discrete(Vec<Resolution>),
+    /// Any resolution from the minimum to the maximum in increments of the
+    /// step, as width and height, with the intervals at the maximum
+    /// resolution.
+    Stepwise {
+        min: (u32, u32),
+        max: (u32, u32),
+        step: (u32, u32),
+        intervals: FrameIntervals,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+    pub intervals: FrameIntervals,
+}
+
+/// The frame intervals of a resolution as fractions of seconds, such as 1/30.
+#[derive(Debug, Clone)]
+pub enum FrameIntervals {
+    Discrete(Vec<(u32, u32)>),
+    Stepwise {
+        min: (u32, u32),
+        max: (u32, u32),
+        step: (u32, u32),
+    },
+}
+
+/// The exposure modes of the [`CID_EXPOSURE_AUTO`] menu control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExposureAuto {
+    Auto = 0,
+    Manual = 1,
+    ShutterPriority = 2,
+    AperturePriority = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlType {
+    Integer,
+    Boolean,
+    Menu,
+    IntegerMenu,
+    Button,
+    Integer64,
+    String,
+    Bitmask,
+    Other(u32),
+}
+
+impl From<u32> for ControlType {
+    fn from(kind: u32) -> Self {
+        match kind {
+            1 => ControlType::Integer,
+            2 => ControlType::Boolean,
+            3 => ControlType::Menu,
+            4 => ControlType::Button,
+            5 => ControlType::Integer64,
+            7 => ControlType::String,
+            8 => ControlType::Bitmask,
+            9 => ControlType::IntegerMenu,
+            kind => ControlType::Other(kind),
+        }
+    }
+}
+
+/// A camera control as described by the driver.  Menu controls list their
+/// valid values along with the item names, the values of integer menus are
+/// given as their names.
+#[derive(Debug, Clone)]
+pub struct Control {
+    pub id: u32,
+    pub name: String,
+    pub kind: ControlType,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default: i32,
+    pub flags: u32,
+    pub menu: Vec<(i32, String)>,
+}
+
+impl Control {
+    pub fn is_disabled(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.flags & 0x04 != 0
+    }
+
+    /// Returns true if the control currently has no effect, such as a manual
+    /// exposure while auto exposure is enabled.
+    pub fn is_inactive(&self) -> bool {
+        self.flags & 0x10 != 0
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Mirror {
     #[default]
     None,
@@ -34,10 +184,232 @@ impl fmt::Display for Mirror {
     }
 }
 
+/// Selects the camera device by its path or by the V4L2 capabilities of the
+/// device.  Only video capture devices are considered when selecting by card,
+/// bus or driver, which skips the metadata devices of UVC cameras, and the
+/// first matching device in the order of its number is used.
+///
+/// From configuration files the path is given as a string, and the others as
+/// a table such as `{ card = "HD Pro Webcam C920" }` or
+/// `{ bus_info = "usb-xhci-hcd.1.auto-1" }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DeviceSelector {
+    /// The device file, such as `/dev/video0` or a `/dev/v4l/by-id` link.
+    Path(String),
+    /// The card name, such as `vivid`.
+    Card(String),
+    /// The bus information, such as `platform:vivid-000`.
+    BusInfo(String),
+    /// The driver name, such as `uvcvideo`.
+    Driver(String),
+}
+
+impl DeviceSelector {
+    /// Returns the path of the selected device.
+    pub fn resolve(&self) -> Result<String, Box<dyn Error>> {
+        let (field, expected): (fn(&v4l2::Capability) -> &[u8], &str) = match self {
+            DeviceSelector::Path(path) => return Ok(path.clone()),
+            DeviceSelector::Card(card) => (|cap| &cap.card, card),
+            DeviceSelector::BusInfo(bus_info) => (|cap| &cap.bus_info, bus_info),
+            DeviceSelector::Driver(driver) => (|cap| &cap.driver, driver),
+        };
+
+        let mut devices = std::fs::read_dir("/dev")?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_prefix("video")?.parse::<u32>().ok()
+            })
+            .collect::<Vec<_>>();
+        devices.sort_unstable();
+
+        for index in devices {
+            let path = format!("/dev/video{}", index);
+            let Ok(device) = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&path)
+            else {
+                continue;
+            };
+            let mut cap = v4l2::Capability::default();
+            if v4l2::ioctl(device.as_raw_fd(), v4l2::VIDIOC_QUERYCAP, &mut cap).is_err() {
+                continue;
+            }
+            let caps = match cap.capabilities & v4l2::CAP_DEVICE_CAPS {
+                0 => cap.capabilities,
+                _ => cap.device_caps,
+            };
+            if caps & (v4l2::CAP_VIDEO_CAPTURE | v4l2::CAP_VIDEO_CAPTURE_MPLANE) != 0
+                && v4l2::name(field(&cap)) == *expected
+            {
+                return Ok(path);
+            }
+        }
+
+        Err(Box::new(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no camera found for {:?}", self),
+        )))
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceSelector::Path(path) => write!(f, "{}", path),
+            DeviceSelector::Card(card) => write!(f, "card {}", card),
+            DeviceSelector::BusInfo(bus_info) => write!(f, "bus {}", bus_info),
+            DeviceSelector::Driver(driver) => write!(f, "driver {}", driver),
+        }
+    }
+}
+
+/// Identifies a control to set when the camera is opened, by its ID such as
+/// [`CID_BRIGHTNESS`] or by its name as reported by the driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlKey {
+    Id(u32),
+    Name(String),
+}
+
+impl From<u32> for ControlKey {
+    fn from(id: u32) -> ControlKey {
+        ControlKey::Id(id)
+    }
+}
+
+impl From<&str> for ControlKey {
+    /// Parses decimal or hexadecimal IDs, anything else is a name.
+    fn from(key: &str) -> ControlKey {
+        let id = match key.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => key.parse().ok(),
+        };
+        match id {
+            Some(id) => ControlKey::Id(id),
+            None => ControlKey::Name(key.to_owned()),
+        }
+    }
+}
+
+/// The camera settings as read from a configuration file, any of which may
+/// be left out to keep the default.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraConfig {
+    device: Option<DeviceConfig>,
+    resolution: Option<(i32, i32)>,
+    format: Option<String>,
+    mirror: Option<Mirror>,
+    buffers: Option<i32>,
+    #[serde(default, deserialize_with = "ordered_controls")]
+    controls: Vec<(String, i32)>,
+}
+
+/// Reads the controls table keeping the order of the file, as auto modes
+/// must be set before the manual values they enable.
+#[cfg(feature = "serde")]
+fn ordered_controls<'de, D>(deserializer: D) -> Result<Vec<(String, i32)>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct Controls;
+
+    impl<'de> serde::de::Visitor<'de> for Controls {
+        type Value = Vec<(String, i32)>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a table of control values")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>,
+        {
+            let mut controls = Vec::new();
+            while let Some(control) = map.next_entry()? {
+                controls.push(control);
+            }
+            Ok(controls)
+        }
+    }
+
+    deserializer.deserialize_map(Controls)
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum DeviceConfig {
+    Path(String),
+    Selector(DeviceSelector),
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CameraConfig> for Camera {
+    type Error = String;
+
+    fn try_from(config: CameraConfig) -> Result<Camera, String> {
+        let mut camera = Camera::default();
+        match config.device {
+            Some(DeviceConfig::Path(path)) => camera.device = DeviceSelector::Path(path),
+            Some(DeviceConfig::Selector(selector)) => camera.device = selector,
+            None => (),
+        }
+        if let Some((width, height)) = config.resolution {
+            camera = camera.with_resolution(width, height);
+        }
+        if let Some(format) = config.format {
+            let fourcc = <[u8; 4]>::try_from(format.as_bytes())
+                .map_err(|_| format!("invalid format {:?}, expected a fourcc", format))?;
+            camera = camera.with_format(FourCC(fourcc));
+        }
+        if let Some(mirror) = config.mirror {
+            camera = camera.with_mirror(mirror);
+        }
+        if let Some(buffers) = config.buffers {
+            camera = camera.with_buffers(buffers);
+        }
+        for (control, value) in config.controls {
+            camera = camera.with_control(control.as_str(), value);
+        }
+        Ok(camera)
+    }
+}
+
+/// The settings of a camera to open.  With the `serde` feature the settings
+/// can also be deserialized, such as from a TOML, JSON or YAML configuration
+/// file, with the keys `device`, `resolution`, `format`, `mirror`, `buffers`
+/// and `controls`.  Controls are given by name or ID and applied in the order
+/// of the file.
+///
+/// ```toml
+/// device = { card = "vivid" }
+/// resolution = [1280, 720]
+/// format = "NV12"
+/// mirror = "horizontal"
+/// buffers = 4
+///
+/// [controls]
+/// brightness = 128
+/// 0x009a0901 = 1
+/// ```
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "CameraConfig")
+)]
 pub struct Camera {
-    /// video device file for the camera
-    device: String,
+    /// video device file for the camera, or how to find it
+    device: DeviceSelector,
 
     /// request camera width, actual camera width may be different
     width: i32,
@@ -53,62 +425,56 @@ pub struct Camera {
 
     /// number of camera buffers to create
     num_buffers: i32,
+
+    /// controls to set once the camera is opened
+    controls: Vec<(ControlKey, i32)>,
 }
 
 impl Camera {
-    pub fn with_device(self, device: &str) -> Camera {
-        Camera {
-            device: device.to_owned(),
-            width: self.width,
-            height: self.height,
-            format: self.format,
-            mirror: self.mirror,
-            num_buffers: self.num_buffers,
-        }
+    pub fn with_device(mut self, device: &str) -> Camera {
+        self.device = DeviceSelector::Path(device.to_owned());
+        self
     }
 
-    pub fn with_resolution(self, width: i32, height: i32) -> Camera {
-        Camera {
-            device: self.device,
-            width,
-            height,
-            format: self.format,
-            mirror: self.mirror,
-            num_buffers: self.num_buffers,
-        }
+    /// Selects the camera by its V4L2 card name, bus or driver rather than
+    /// its device path, resolved when the camera is opened.
+    pub fn with_selector(mut self, selector: DeviceSelector) -> Camera {
+        self.device = selector;
+        self
     }
 
-    pub fn with_format(self, format: FourCC) -> Camera {
-        Camera {
-            device: self.device,
-            width: self.width,
-            height: self.height,
-            format,
-            mirror: self.mirror,
-            num_buffers: self.num_buffers,
-        }
+    pub fn with_resolution(mut self, width: i32, height: i32) -> Camera {
+        self.width = width;
+        self.height = height;
+        self
     }
 
-    pub fn with_mirror(self, mirror: Mirror) -> Camera {
-        Camera {
-            device: self.device,
-            width: self.width,
-            height: self.height,
-            format: self.format,
-            mirror,
-            num_buffers: self.num_buffers,
-        }
+    pub fn with_format(mut self, format: FourCC) -> Camera {
+        self.format = format;
+        self
     }
 
-    pub fn with_buffers(self, num_buffers: i32) -> Camera {
-        Camera {
-            device: self.device,
-            width: self.width,
-            height: self.height,
-            format: self.format,
-            mirror: self.mirror,
-            num_buffers,
-        }
+    pub fn with_mirror(mut self, mirror: Mirror) -> Camera {
+        self.mirror = mirror;
+        self
+    }
+
+    pub fn with_buffers(mut self, num_buffers: i32) -> Camera {
+        self.num_buffers = num_buffers;
+        self
+    }
+
+    /// Sets the control once the camera is opened, by its ID or by its name
+    /// as reported by the driver.
+    pub fn with_control<K: Into<ControlKey>>(mut self, control: K, value: i32) -> Camera {
+        self.controls.push((control.into(), value));
+        self
+    }
+
+    /// Returns the path of the camera's device, finding the device of the
+    /// selector.
+    pub fn device(&self) -> Result<String, Box<dyn Error>> {
+        self.device.resolve()
     }
 
     pub fn open(self) -> Result<CameraReader, Box<dyn Error>> {
@@ -116,47 +482,178 @@ impl Camera {
     }
 
     pub fn formats(self) -> Result<CameraFormats, Box<dyn Error>> {
-        let device_str_c = CString::new(self.device)?;
+        let device_str_c = CString::new(self.device()?)?;
         let ptr = unsafe { ffi::vsl_camera_open_device(device_str_c.as_ptr()) };
         if ptr.is_null() {
             let err = io::Error::last_os_error();
             return Err(Box::new(err));
         }
 
-        const MAX_FORMATS: usize = 20;
-        let mut formats: [u32; MAX_FORMATS] = [0; MAX_FORMATS];
-        let n_formats = match c_int::try_from(MAX_FORMATS) {
-            Ok(val) => val,
-            Err(err) => {
-                unsafe { ffi::vsl_camera_close_device(ptr) };
-                return Err(Box::new(err));
+        let fmts = enum_formats(ptr, false);
+        unsafe { ffi::vsl_camera_close_device(ptr) };
+        fmts
+    }
+
+    /// Discovers the single and multi-planar formats of the camera along with
+    /// their resolutions and frame intervals, and whether the camera can
+    /// export its buffers as DMA buffers.
+    ///
+    /// DMA support is probed by initializing the device with the requested
+    /// resolution, format and buffers, and is unknown when the device cannot
+    /// be initialized, such as while another reader is streaming.
+    pub fn capabilities(&self) -> Result<Capabilities, Box<dyn Error>> {
+        let path = self.device()?;
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)?;
+        let device_str_c = CString::new(path)?;
+        let ptr = unsafe { ffi::vsl_camera_open_device(device_str_c.as_ptr()) };
+        if ptr.is_null() {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+
+        let capabilities = self.probe(ptr, device.as_raw_fd());
+        unsafe { ffi::vsl_camera_close_device(ptr) };
+        capabilities
+    }
+
+    fn probe(&self, ptr: *mut ffi::vsl_camera, fd: RawFd) -> Result<Capabilities, Box<dyn Error>> {
+        let mut formats = Vec::new();
+        for multiplanar in [false, true] {
+            for fourcc in enum_formats(ptr, multiplanar)? {
+                formats.push(FormatCapability {
+                    fourcc,
+                    multiplanar,
+                    sizes: enum_frame_sizes(fd, fourcc)?,
+                });
             }
+        }
+
+        let mut width: c_int = self.width;
+        let mut height: c_int = self.height;
+        let mut num_buffers: c_int = self.num_buffers;
+        let mut format: u32 = self.format.into();
+        let dmabuf = match unsafe {
+            ffi::vsl_camera_init_device(ptr, &mut width, &mut height, &mut num_buffers, &mut format)
+        } {
+            0 => {
+                let supported = unsafe { ffi::vsl_camera_is_dmabuf_supported(ptr) } > 0;
+                unsafe { ffi::vsl_camera_uninit_device(ptr) };
+                Some(supported)
+            }
+            _ => None,
         };
 
-        let cnt = unsafe { ffi::vsl_camera_enum_fmts(ptr, formats.as_mut_ptr(), n_formats) };
-        unsafe { ffi::vsl_camera_close_device(ptr) };
+        Ok(Capabilities { formats, dmabuf })
+    }
+}
 
-        let u_cnt = usize::try_from(cnt)?;
-        let mut fmts: CameraFormats = CameraFormats::with_capacity(u_cnt);
+/// Lists the single or multi-planar formats of the opened camera.
+fn enum_formats(
+    ptr: *mut ffi::vsl_camera,
+    multiplanar: bool,
+) -> Result<CameraFormats, Box<dyn Error>> {
+    let mut formats: Vec<u32> = vec![0; 32];
+    loop {
+        let size = c_int::try_from(formats.len())?;
+        let cnt = unsafe {
+            match multiplanar {
+                false => ffi::vsl_camera_enum_fmts(ptr, formats.as_mut_ptr(), size),
+                true => ffi::vsl_camera_enum_mplane_fmts(ptr, formats.as_mut_ptr(), size),
+            }
+        };
+        if cnt < 0 {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+        // A full list may have been truncated, retry with a larger one.
+        if cnt < size {
+            formats.truncate(usize::try_from(cnt)?);
+            return Ok(formats.into_iter().map(FourCC::from).collect());
+        }
+        formats.resize(formats.len() * 2, 0);
+    }
+}
 
-        for i in 0..cnt {
-            let idx = usize::try_from(i)?;
-            fmts.push(FourCC::from(formats[idx]));
+fn enum_frame_sizes(fd: RawFd, fourcc: FourCC) -> Result<FrameSizes, Box<dyn Error>> {
+    let mut sizes = Vec::new();
+    for index in 0.. {
+        let mut size = v4l2::FrmSizeEnum {
+            index,
+            pixel_format: fourcc.into(),
+            ..Default::default()
+        };
+        match v4l2::ioctl(fd, v4l2::VIDIOC_ENUM_FRAMESIZES, &mut size) {
+            Ok(()) => (),
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(err) => return Err(Box::new(err)),
+        }
+        if size.kind != v4l2::FRMSIZE_TYPE_DISCRETE {
+            let [min_width, max_width, step_width, min_height, max_height, step_height] = size.size;
+            return Ok(FrameSizes::Stepwise {
+                min: (min_width, min_height),
+                max: (max_width, max_height),
+                step: (step_width, step_height),
+                intervals: enum_frame_intervals(fd, fourcc, max_width, max_height)?,
+            });
         }
+        let [width, height, ..] = size.size;
+        sizes.push(Resolution {
+            width,
+            height,
+            intervals: enum_frame_intervals(fd, fourcc, width, height)?,
+        });
+    }
+    Ok(FrameSizes::Discrete(sizes))
+}
 
-        Ok(fmts)
+fn enum_frame_intervals(
+    fd: RawFd,
+    fourcc: FourCC,
+    width: u32,
+    height: u32,
+) -> Result<FrameIntervals, Box<dyn Error>> {
+    let mut intervals = Vec::new();
+    for index in 0.. {
+        let mut interval = v4l2::FrmIvalEnum {
+            index,
+            pixel_format: fourcc.into(),
+            width,
+            height,
+            ..Default::default()
+        };
+        match v4l2::ioctl(fd, v4l2::VIDIOC_ENUM_FRAMEINTERVALS, &mut interval) {
+            Ok(()) => (),
+            // Drivers without interval enumeration report no intervals.
+            Err(err) if matches!(err.raw_os_error(), Some(libc::EINVAL | libc::ENOTTY)) => break,
+            Err(err) => return Err(Box::new(err)),
+        }
+        let [num, den, max_num, max_den, step_num, step_den] = interval.interval;
+        if interval.kind != v4l2::FRMIVAL_TYPE_DISCRETE {
+            return Ok(FrameIntervals::Stepwise {
+                min: (num, den),
+                max: (max_num, max_den),
+                step: (step_num, step_den),
+            });
+        }
+        intervals.push((num, den));
     }
+    Ok(FrameIntervals::Discrete(intervals))
 }
 
 impl Default for Camera {
     fn default() -> Camera {
         Camera {
-            device: "/dev/video0".to_owned(),
+            device: DeviceSelector::Path("/dev/video0".to_owned()),
             width: 1920,
             height: 1080,
             format: FourCC(*b"YUYV"),
             mirror: Mirror::None,
             num_buffers: 4,
+            controls: Vec::new(),
         }
     }
 }
@@ -168,15 +665,29 @@ pub fn create_camera() -> Camera {
 #[derive(Debug)]
 pub struct CameraReader {
     ptr: *mut ffi::vsl_camera,
+    /// second handle to the device for the controls and parameters which are
+    /// not wrapped by libvideostream
+    device: File,
     width: i32,
     height: i32,
     format: FourCC,
     mirror: Mirror,
+    /// time of the last frame read, or of the start of capture
+    last_frame: Cell<Option<Instant>>,
+    /// frame interval used to detect stalls
+    interval: Cell<Duration>,
+    stall_intervals: u32,
 }
 
 impl CameraReader {
     fn init(camera: Camera) -> Result<Self, Box<dyn Error>> {
-        let device_str_c = CString::new(camera.device)?;
+        let path = camera.device()?;
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)?;
+        let device_str_c = CString::new(path)?;
         let ptr = unsafe { ffi::vsl_camera_open_device(device_str_c.as_ptr()) };
         if ptr.is_null() {
             let err = io::Error::last_os_error();
@@ -192,16 +703,22 @@ impl CameraReader {
             ffi::vsl_camera_init_device(ptr, &mut width, &mut height, &mut num_buffers, &mut format)
         } != 0
         {
+            // Capture the error before closing the device can overwrite it.
             let err = io::Error::last_os_error();
+            unsafe { ffi::vsl_camera_close_device(ptr) };
             return Err(Box::new(err));
         }
 
         let cam = CameraReader {
             ptr,
+            device,
             width,
             height,
             format: FourCC::from(format),
             mirror: camera.mirror,
+            last_frame: Cell::new(None),
+            interval: Cell::new(Duration::from_secs(1) / 30),
+            stall_intervals: 10,
         };
 
         match cam.mirror {
@@ -223,6 +740,14 @@ impl CameraReader {
             }
         }
 
+        for (control, value) in &camera.controls {
+            let id = match control {
+                ControlKey::Id(id) => *id,
+                ControlKey::Name(name) => cam.find_control(name)?,
+            };
+            cam.set_control(id, *value)?;
+        }
+
         Ok(cam)
     }
 
@@ -232,6 +757,10 @@ impl CameraReader {
             return Err(Box::new(err));
         }
 
+        if let Ok((numerator, denominator)) = self.frame_interval() {
+            self.update_interval(numerator, denominator);
+        }
+        self.last_frame.set(Some(Instant::now()));
         Ok(())
     }
 
@@ -303,14 +832,254 @@ impl CameraReader {
         self.format
     }
 
+    /// Returns the number of buffers queued to the device for capture, reads
+    /// will time out once none remain queued.
+    pub fn queued_buffers(&self) -> i32 {
+        unsafe { ffi::vsl_camera_get_queued_buf_count(self.ptr) }
+    }
+
+    /// Lists the controls of the camera along with their ranges and menus.
+    pub fn controls(&self) -> Result<Vec<Control>, Box<dyn Error>> {
+        let fd = self.device.as_raw_fd();
+        let mut controls = Vec::new();
+        let mut query = v4l2::QueryCtrl {
+            id: v4l2::CTRL_FLAG_NEXT_CTRL,
+            ..Default::default()
+        };
+
+        loop {
+            match v4l2::ioctl(fd, v4l2::VIDIOC_QUERYCTRL, &mut query) {
+                Ok(()) => (),
+                Err(err) if err.raw_os_error() == Some(libc::EINVAL) => break,
+                Err(err) => return Err(Box::new(err)),
+            }
+            let id = query.id;
+            if query.kind != v4l2::CTRL_TYPE_CTRL_CLASS {
+                let kind = ControlType::from(query.kind);
+                let mut menu = Vec::new();
+                if matches!(kind, ControlType::Menu | ControlType::IntegerMenu) {
+                    for index in query.minimum..=query.maximum {
+                        let mut item = v4l2::QueryMenu {
+                            id,
+                            index: index as u32,
+                            ..Default::default()
+                        };
+                        // Menus may skip unsupported items.
+                        if v4l2::ioctl(fd, v4l2::VIDIOC_QUERYMENU, &mut item).is_err() {
+                            continue;
+                        }
+                        let name = match kind {
+                            ControlType::IntegerMenu => {
+                                let mut value = [0; 8];
+                                value.copy_from_slice(&item.name[..8]);
+                                i64::from_ne_bytes(value).to_string()
+                            }
+                            _ => v4l2::name(&item.name),
+                        };
+                        menu.push((index, name));
+                    }
+                }
+                controls.push(Control {
+                    id,
+                    name: v4l2::name(&query.name),
+                    kind,
+                    minimum: query.minimum,
+                    maximum: query.maximum,
+                    step: query.step,
+                    default: query.default_value,
+                    flags: query.flags,
+                    menu,
+                });
+            }
+            query = v4l2::QueryCtrl {
+                id: id | v4l2::CTRL_FLAG_NEXT_CTRL,
+                ..Default::default()
+            };
+        }
+
+        Ok(controls)
+    }
+
+    /// Returns the ID of the control by its name, ignoring case and treating
+    /// spaces and underscores alike.
+    fn find_control(&self, name: &str) -> Result<u32, Box<dyn Error>> {
+        let normalize = |name: &str| name.trim().to_lowercase().replace(' ', "_");
+        let name = normalize(name);
+        self.controls()?
+            .into_iter()
+            .find(|control| normalize(&control.name) == name)
+            .map(|control| control.id)
+            .ok_or_else(|| format!("camera has no control named {}", name).into())
+    }
+
+    pub fn control(&self, id: u32) -> Result<i32, Box<dyn Error>> {
+        let mut control = v4l2::Control { id, value: 0 };
+        v4l2::ioctl(self.device.as_raw_fd(), v4l2::VIDIOC_G_CTRL, &mut control)?;
+        Ok(control.value)
+    }
+
+    /// Sets the control, the driver may clamp the value to the control's
+    /// range.
+    pub fn set_control(&self, id: u32, value: i32) -> Result<(), Box<dyn Error>> {
+        let mut control = v4l2::Control { id, value };
+        v4l2::ioctl(self.device.as_raw_fd(), v4l2::VIDIOC_S_CTRL, &mut control)?;
+        Ok(())
+    }
+
+    pub fn set_exposure_auto(&self, mode: ExposureAuto) -> Result<(), Box<dyn Error>> {
+        self.set_control(CID_EXPOSURE_AUTO, mode as i32)
+    }
+
+    pub fn set_white_balance_auto(&self, enable: bool) -> Result<(), Box<dyn Error>> {
+        self.set_control(CID_AUTO_WHITE_BALANCE, enable as i32)
+    }
+
+    fn stream_parm(&self) -> Result<v4l2::StreamParm, Box<dyn Error>> {
+        let mut parm = v4l2::StreamParm {
+            kind: v4l2::BUF_TYPE_VIDEO_CAPTURE,
+            ..Default::default()
+        };
+        let fd = self.device.as_raw_fd();
+        if v4l2::ioctl(fd, v4l2::VIDIOC_G_PARM, &mut parm).is_err() {
+            parm.kind = v4l2::BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+            v4l2::ioctl(fd, v4l2::VIDIOC_G_PARM, &mut parm)?;
+        }
+        Ok(parm)
+    }
+
+    /// Returns the time per frame as a fraction of seconds, such as 1/30.
+    pub fn frame_interval(&self) -> Result<(u32, u32), Box<dyn Error>> {
+        let interval = self.stream_parm()?.capture.timeperframe;
+        Ok((interval.numerator, interval.denominator))
+    }
+
+    /// Requests the time per frame as a fraction of seconds and returns the
+    /// interval selected by the driver.
+    pub fn set_frame_interval(
+        &self,
+        numerator: u32,
+        denominator: u32,
+    ) -> Result<(u32, u32), Box<dyn Error>> {
+        let mut parm = self.stream_parm()?;
+        if parm.capture.capability & v4l2::CAP_TIMEPERFRAME == 0 {
+            return Err("camera does not support setting the frame interval".into());
+        }
+        parm.capture.timeperframe = v4l2::Fract {
+            numerator,
+            denominator,
+        };
+        v4l2::ioctl(self.device.as_raw_fd(), v4l2::VIDIOC_S_PARM, &mut parm)?;
+        let interval = parm.capture.timeperframe;
+        self.update_interval(interval.numerator, interval.denominator);
+        Ok((interval.numerator, interval.denominator))
+    }
+
+    /// Returns the frame interval of the camera, or 1/30s if unknown.
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval.get()
+    }
+
+    fn update_interval(&self, numerator: u32, denominator: u32) {
+        if numerator > 0 && denominator > 0 {
+            self.interval
+                .set(Duration::from_secs(numerator as u64) / denominator);
+        }
+    }
+
+    /// Sets the number of frame intervals without a frame after which the
+    /// camera is reported as stalled, the default is 10.
+    pub fn set_stall_intervals(&mut self, intervals: u32) {
+        self.stall_intervals = intervals;
+    }
+
+    /// Returns the time since the last frame was read, or since capture was
+    /// started, once it exceeds the stall intervals.  A stalled camera has
+    /// usually lost its sensor or been disconnected.
+    pub fn stalled(&self) -> Option<Duration> {
+        let elapsed = self.last_frame.get()?.elapsed();
+        (elapsed > self.interval.get() * self.stall_intervals).then_some(elapsed)
+    }
+
+    /// Waits for a frame to be captured for up to the timeout, polling for
+    /// less than a millisecond only checks if a frame is ready.
+    fn wait(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.device.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+        let deadline = Instant::now() + Duration::from_millis(timeout as u64);
+        loop {
+            let remaining = deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis();
+            match unsafe { libc::poll(&mut pollfd, 1, remaining as c_int) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(Box::new(err));
+                    }
+                }
+                0 if timeout == 0 => {
+                    return Err(Box::new(io::Error::from(io::ErrorKind::WouldBlock)))
+                }
+                0 => {
+                    let err = io::Error::new(io::ErrorKind::TimedOut, "camera read timed out");
+                    return Err(Box::new(err));
+                }
+                _ if pollfd.revents & libc::POLLERR != 0 => {
+                    let err = io::Error::new(io::ErrorKind::BrokenPipe, "camera is not capturing");
+                    return Err(Box::new(err));
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Reads the next frame, failing with a [`io::ErrorKind::TimedOut`] error
+    /// if none is captured within the timeout.
+    pub fn read_timeout(&self, timeout: Duration) -> Result<CameraBuffer<'_>, Box<dyn Error>> {
+        self.wait(timeout)?;
+        self.read()
+    }
+
+    /// Reads a frame if one is ready, otherwise fails with an
+    /// [`io::ErrorKind::WouldBlock`] error.  Use the camera's descriptor to
+    /// wait for frames in an event loop.
+    pub fn try_read(&self) -> Result<CameraBuffer<'_>, Box<dyn Error>> {
+        self.wait(Duration::ZERO)?;
+        self.read()
+    }
+
     pub fn read(&self) -> Result<CameraBuffer<'_>, Box<dyn Error>> {
+        let ptr = self.dequeue()?;
+        CameraBuffer::new(ptr, self)
+    }
+
+    fn dequeue(&self) -> Result<*mut ffi::vsl_camera_buffer, Box<dyn Error>> {
         let ptr = unsafe { ffi::vsl_camera_get_data(self.ptr) };
         if ptr.is_null() {
             let err = io::Error::last_os_error();
             return Err(Box::new(err));
         }
 
-        CameraBuffer::new(ptr, self)
+        self.last_frame.set(Some(Instant::now()));
+        Ok(ptr)
+    }
+}
+
+/// The camera's descriptor is readable once a frame has been captured, for
+/// use with poll, epoll or async runtimes.
+impl AsFd for CameraReader {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.device.as_fd()
+    }
+}
+
+impl AsRawFd for CameraReader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.device.as_raw_fd()
     }
 }
 
@@ -324,6 +1093,176 @@ impl Drop for CameraReader {
     }
 }
 
+type EventHandler = Box<dyn FnMut(&CameraEvent)>;
+
+/// An event reported by a [`SupervisedCamera`].
+#[derive(Debug, Clone)]
+pub enum CameraEvent {
+    /// The camera was lost, with the error which was reported.
+    Disconnected(String),
+    /// The camera was reopened and capture restarted.
+    Reconnected,
+}
+
+/// The SupervisedCamera reads from a camera which may be disconnected, such
+/// as a USB camera which re-enumerates.  When the camera reports the device
+/// is gone the reader is torn down, and the next read waits for the device
+/// node to return then reopens it with the same settings.
+///
+/// Use a stable device path, such as a `/dev/v4l/by-id` link, for cameras
+/// which may return under another video node.
+pub struct SupervisedCamera {
+    camera: Camera,
+    reader: Option<CameraReader>,
+    retry: Duration,
+    timeout: Option<Duration>,
+    handler: Option<EventHandler>,
+    opened: bool,
+}
+
+impl SupervisedCamera {
+    /// Creates the supervisor, the camera is opened and started by the first
+    /// read.
+    pub fn new(camera: Camera) -> Self {
+        SupervisedCamera {
+            camera,
+            reader: None,
+            retry: Duration::from_secs(1),
+            timeout: None,
+            handler: None,
+            opened: false,
+        }
+    }
+
+    /// Sets how often to check for the device to return, the default is one
+    /// second.
+    pub fn with_retry_interval(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Reopens the camera when no frame is captured within the timeout, for
+    /// devices which stall rather than report their removal.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Calls the handler when the camera is disconnected or reconnected.
+    pub fn with_handler<F: FnMut(&CameraEvent) + 'static>(mut self, handler: F) -> Self {
+        self.handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns the reader while the camera is connected, to access its
+    /// settings and controls.
+    pub fn reader(&self) -> Option<&CameraReader> {
+        self.reader.as_ref()
+    }
+
+    fn emit(&mut self, event: CameraEvent) {
+        if let Some(handler) = &mut self.handler {
+            handler(&event);
+        }
+    }
+
+    fn reopen(&mut self) -> Result<&CameraReader, Box<dyn Error>> {
+        let mut failures = 0;
+        loop {
+            // Selected cameras are looked up again as their path may change.
+            if self
+                .camera
+                .device()
+                .is_ok_and(|path| Path::new(&path).exists())
+            {
+                let reader = self.camera.clone().open().and_then(|reader| {
+                    reader.start()?;
+                    Ok(reader)
+                });
+                match reader {
+                    Ok(reader) => {
+                        if self.opened {
+                            self.emit(CameraEvent::Reconnected);
+                        }
+                        self.opened = true;
+                        return Ok(self.reader.insert(reader));
+                    }
+                    // The device node may return before the device is ready,
+                    // or go again before it is opened.
+                    Err(err) if failures < OPEN_RETRIES && is_reopen_retry(&*err) => {
+                        failures += 1;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            thread::sleep(self.retry);
+        }
+    }
+
+    /// Reads the next frame, blocking while the camera is disconnected until
+    /// it returns.  Errors other than the loss of the camera are returned, as
+    /// is the error of a returned camera which keeps failing to open, such as
+    /// one in use by another process.
+    pub fn read(&mut self) -> Result<CameraBuffer<'_>, Box<dyn Error>> {
+        let timeout = self.timeout;
+        let ptr = loop {
+            let reader = match self.reader {
+                Some(ref reader) => reader,
+                None => self.reopen()?,
+            };
+            let ptr = match timeout {
+                Some(timeout) => reader.wait(timeout).and_then(|_| reader.dequeue()),
+                None => reader.dequeue(),
+            };
+            match ptr {
+                Ok(ptr) => break ptr,
+                Err(err) if is_disconnect(&*err) => {
+                    self.reader = None;
+                    self.emit(CameraEvent::Disconnected(err.to_string()));
+                }
+                Err(err) => return Err(err),
+            }
+        };
+
+        match &self.reader {
+            Some(reader) => CameraBuffer::new(ptr, reader),
+            None => unreachable!(),
+        }
+    }
+}
+
+/// How many times a [`SupervisedCamera`] retries opening a device node which
+/// is present but fails to open, before returning the error.
+const OPEN_RETRIES: u32 = 10;
+
+/// Returns true if the error means the camera was removed or stopped
+/// delivering frames.  A camera in use by another process is not a
+/// disconnect.
+fn is_disconnect(err: &(dyn Error + 'static)) -> bool {
+    match err.downcast_ref::<io::Error>() {
+        Some(err) => {
+            matches!(
+                err.raw_os_error(),
+                Some(libc::ENODEV | libc::ENXIO | libc::EIO)
+            ) || matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::BrokenPipe
+            )
+        }
+        None => false,
+    }
+}
+
+/// Returns true if opening a returning camera may succeed when retried, such
+/// as when its device node is removed again or the device is still busy
+/// starting up.
+fn is_reopen_retry(err: &(dyn Error + 'static)) -> bool {
+    is_disconnect(err)
+        || err
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| matches!(err.raw_os_error(), Some(libc::ENOENT | libc::EBUSY)))
+}
+
 #[derive(Debug)]
 pub struct CameraBuffer<'a> {
     raw_fd: RawFd,
@@ -351,8 +1290,39 @@ impl CameraBuffer<'_> {
         unsafe { BorrowedFd::borrow_raw(self.raw_fd) }
     }
 
-    pub fn dmabuf(&self) -> DmaBuf {
-        unsafe { DmaBuf::from_raw_fd(self.raw_fd) }
+    /// Returns a DMA buffer holding a duplicate of the buffer's descriptor,
+    /// the driver's descriptor stays owned by the camera.
+    pub fn dmabuf(&self) -> Result<DmaBuf, Box<dyn Error>> {
+        if self.raw_fd < 0 {
+            return Err("camera does not support dma buffers".into());
+        }
+        Ok(DmaBuf::from(self.fd().try_clone_to_owned()?))
+    }
+
+    /// Returns the buffer memory mapped by the driver, None if the camera
+    /// only provides DMA buffers.
+    pub fn mmap(&self) -> Option<&[u8]> {
+        let ptr = unsafe { ffi::vsl_camera_buffer_mmap(self.ptr) };
+        if ptr.is_null() || self.length() == 0 {
+            return None;
+        }
+        // SAFETY: the mapping stays valid until the buffer is released back to
+        // the camera when dropped, which the borrow of self outlives.
+        Some(unsafe { slice::from_raw_parts(ptr as *const u8, self.length()) })
+    }
+
+    /// Returns the physical address of the buffer, None if the camera does
+    /// not provide physically contiguous buffers.
+    pub fn phys_addr(&self) -> Option<u64> {
+        match unsafe { ffi::vsl_camera_buffer_phys_addr(self.ptr) } {
+            0 => None,
+            addr => Some(addr),
+        }
+    }
+
+    /// Returns the pixel format of the buffer as reported by the driver.
+    pub fn fourcc(&self) -> FourCC {
+        FourCC::from(unsafe { ffi::vsl_camera_buffer_fourcc(self.ptr) })
     }
 
     pub fn rawfd(&self) -> RawFd {
@@ -410,7 +1380,6 @@ impl fmt::Display for CameraBuffer<'_> {
 mod tests {
     use super::*;
     use serial_test::serial;
-    use std::time::Instant;
 
     #[ignore = "test requires maivin 2 hardware (run with --include-ignored to enable)"]
     #[test]
@@ -472,7 +1441,7 @@ mod tests {
             let buf = cam.read()?;
 
             let now = Instant::now();
-            let dma = buf.dmabuf();
+            let dma = buf.dmabuf()?;
             let mem = dma.memory_map()?;
             let stats = mem.read(pixel_metrics, Some((buf.width(), buf.height())))?;
             let elapsed = now.elapsed();
@@ -486,6 +1455,264 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_config() -> Result<(), Box<dyn Error>> {
+        let camera: Camera = toml::from_str(
+            r#"
+            device = { bus_info = "platform:vivid-000" }
+            resolution = [1280, 720]
+            format = "NV12"
+            mirror = "both"
+
+            [controls]
+            exposure_auto = 1
+            brightness = 100
+            0x00980901 = 60
+            exposure_absolute = 300
+            "#,
+        )?;
+        assert_eq!(
+            camera.device,
+            DeviceSelector::BusInfo("platform:vivid-000".to_owned())
+        );
+        assert_eq!((camera.width, camera.height), (1280, 720));
+        assert_eq!(camera.format, FourCC(*b"NV12"));
+        assert!(matches!(camera.mirror, Mirror::Both));
+        assert_eq!(camera.num_buffers, 4);
+        assert_eq!(
+            camera.controls,
+            [
+                (ControlKey::Name("exposure_auto".to_owned()), 1),
+                (ControlKey::Name("brightness".to_owned()), 100),
+                (ControlKey::Id(CID_CONTRAST), 60),
+                (ControlKey::Name("exposure_absolute".to_owned()), 300),
+            ]
+        );
+
+        let camera: Camera = serde_json::from_str(r#"{"device": "/dev/video3", "buffers": 6}"#)?;
+        assert_eq!(
+            camera.device,
+            DeviceSelector::Path("/dev/video3".to_owned())
+        );
+        assert_eq!(camera.num_buffers, 6);
+        assert_eq!(camera.format, FourCC(*b"YUYV"));
+
+        assert!(serde_json::from_str::<Camera>(r#"{"format": "YUV"}"#).is_err());
+        assert!(serde_json::from_str::<Camera>(r#"{"fps": 30}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_selector() {
+        assert_eq!(
+            create_camera().with_device("/dev/video3").device().unwrap(),
+            "/dev/video3"
+        );
+        let camera =
+            create_camera().with_selector(DeviceSelector::Card("no such camera".to_owned()));
+        assert!(camera.device().is_err());
+        assert!(camera.open().is_err());
+        assert_eq!(
+            ControlKey::from("0x00980900"),
+            ControlKey::Id(CID_BRIGHTNESS)
+        );
+        assert_eq!(ControlKey::from("9963776"), ControlKey::Id(CID_BRIGHTNESS));
+        assert_eq!(
+            ControlKey::from("White Balance"),
+            ControlKey::Name("White Balance".to_owned())
+        );
+    }
+
+    /// Returns the capture device of the vivid virtual driver, loaded with
+    /// `modprobe vivid`.
+    fn vivid() -> Option<String> {
+        (0..64)
+            .map(|index| format!("video{}", index))
+            .find(|video| {
+                std::fs::read_to_string(format!("/sys/class/video4linux/{}/name", video))
+                    .is_ok_and(|name| name.trim().ends_with("vid-cap"))
+            })
+            .map(|video| format!("/dev/{}", video))
+    }
+
+    #[ignore = "test requires the vivid driver (run with --include-ignored to enable)"]
+    #[test]
+    #[serial]
+    fn test_controls() -> Result<(), Box<dyn Error>> {
+        let device = vivid().ok_or("vivid capture device not found")?;
+        let cam = create_camera()
+            .with_device(&device)
+            .with_resolution(640, 360)
+            .open()?;
+
+        let controls = cam.controls()?;
+        let brightness = controls
+            .iter()
+            .find(|control| control.id == CID_BRIGHTNESS)
+            .ok_or("missing brightness control")?;
+        assert_eq!(brightness.kind, ControlType::Integer);
+        assert!(brightness.maximum > brightness.minimum);
+        assert!(controls
+            .iter()
+            .any(|control| control.kind == ControlType::Menu && !control.menu.is_empty()));
+
+        cam.set_control(CID_BRIGHTNESS, brightness.minimum + 1)?;
+        assert_eq!(cam.control(CID_BRIGHTNESS)?, brightness.minimum + 1);
+        cam.set_control(CID_BRIGHTNESS, brightness.default)?;
+        assert!(cam.control(0).is_err());
+
+        let interval = cam.set_frame_interval(1, 15)?;
+        assert_eq!(cam.frame_interval()?, interval);
+
+        Ok(())
+    }
+
+    #[ignore = "test requires the vivid driver (run with --include-ignored to enable)"]
+    #[test]
+    #[serial]
+    fn test_capabilities() -> Result<(), Box<dyn Error>> {
+        let device = vivid().ok_or("vivid capture device not found")?;
+        let capabilities = create_camera()
+            .with_device(&device)
+            .with_resolution(640, 360)
+            .capabilities()?;
+        println!("camera capabilities: {:?}", capabilities);
+
+        let yuyv = capabilities
+            .formats
+            .iter()
+            .find(|format| format.fourcc == FourCC(*b"YUYV"))
+            .ok_or("missing YUYV format")?;
+        match &yuyv.sizes {
+            FrameSizes::Discrete(sizes) => {
+                assert!(!sizes.is_empty());
+                assert!(sizes.iter().all(|size| match &size.intervals {
+                    FrameIntervals::Discrete(intervals) => !intervals.is_empty(),
+                    FrameIntervals::Stepwise { .. } => true,
+                }));
+            }
+            FrameSizes::Stepwise { min, max, .. } => assert!(max >= min),
+        }
+        assert!(capabilities.dmabuf.is_some());
+
+        Ok(())
+    }
+
+    #[ignore = "test requires the vivid driver (run with --include-ignored to enable)"]
+    #[test]
+    #[serial]
+    fn test_buffers() -> Result<(), Box<dyn Error>> {
+        let device = vivid().ok_or("vivid capture device not found")?;
+        let cam = create_camera()
+            .with_device(&device)
+            .with_resolution(640, 360)
+            .open()?;
+        cam.start()?;
+
+        for _ in 0..4 {
+            let buf = cam.read()?;
+            assert_eq!(buf.fourcc(), cam.format());
+            if let Some(mem) = buf.mmap() {
+                assert_eq!(mem.len(), buf.length());
+            }
+            if buf.rawfd() >= 0 {
+                let dma = buf.dmabuf()?;
+                assert_ne!(dma.as_raw_fd(), buf.rawfd());
+            }
+        }
+
+        // The driver descriptors must survive the duplicates being closed.
+        let buf = cam.read()?;
+        assert!(buf.mmap().is_some() || buf.dmabuf().is_ok());
+
+        Ok(())
+    }
+
+    #[ignore = "test requires the vivid driver (run with --include-ignored to enable)"]
+    #[test]
+    #[serial]
+    fn test_read_timeout() -> Result<(), Box<dyn Error>> {
+        let device = vivid().ok_or("vivid capture device not found")?;
+        let mut cam = create_camera()
+            .with_device(&device)
+            .with_resolution(640, 360)
+            .open()?;
+        cam.set_stall_intervals(2);
+        cam.start()?;
+
+        let timeout = Duration::from_secs(1);
+        drop(cam.read_timeout(timeout)?);
+        assert!(cam.stalled().is_none());
+
+        // Draining the captured frames leaves none ready.
+        let err = loop {
+            match cam.try_read() {
+                Ok(buf) => drop(buf),
+                Err(err) => break err.downcast::<io::Error>()?,
+            }
+        };
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        let mut pollfd = libc::pollfd {
+            fd: cam.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 1000) }, 1);
+        drop(cam.try_read()?);
+
+        cam.stop()?;
+        assert!(cam.read_timeout(timeout).is_err());
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(cam.stalled().is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let removed = io::Error::from_raw_os_error(libc::ENODEV);
+        assert!(is_disconnect(&removed));
+        assert!(is_disconnect(&io::Error::from(io::ErrorKind::TimedOut)));
+        assert!(!is_disconnect(&io::Error::from_raw_os_error(libc::EINVAL)));
+        let err: Box<dyn Error> = "unsupported format".into();
+        assert!(!is_disconnect(&*err));
+
+        // A busy or missing device is only retried while reopening.
+        for errno in [libc::EBUSY, libc::ENOENT] {
+            let err = io::Error::from_raw_os_error(errno);
+            assert!(!is_disconnect(&err));
+            assert!(is_reopen_retry(&err));
+        }
+        assert!(is_reopen_retry(&removed));
+        assert!(!is_reopen_retry(&io::Error::from_raw_os_error(
+            libc::EACCES
+        )));
+    }
+
+    #[ignore = "test requires the vivid driver (run with --include-ignored to enable)"]
+    #[test]
+    #[serial]
+    fn test_supervised() -> Result<(), Box<dyn Error>> {
+        let device = vivid().ok_or("vivid capture device not found")?;
+        let camera = create_camera()
+            .with_device(&device)
+            .with_resolution(640, 360);
+        let mut supervised = SupervisedCamera::new(camera)
+            .with_timeout(Duration::from_secs(2))
+            .with_handler(|event| panic!("unexpected {:?}", event));
+        assert!(supervised.reader().is_none());
+
+        for _ in 0..10 {
+            let buf = supervised.read()?;
+            assert_eq!(buf.width(), 640);
+        }
+        assert!(supervised.reader().is_some());
+
+        Ok(())
+    }
+
     fn pixel_metrics(img: &[u8], dim: Option<(i32, i32)>) -> Result<(u8, u8, u8), Box<dyn Error>> {
         let width = dim.unwrap_or_default().0;
         let height = dim.unwrap_or_default().1;
@@ -511,4 +1738,26 @@ mod tests {
 
         Ok((y_min, y_max, y_avg as u8))
     }
+
+    #[ignore = "test requires the vivid driver (run with --include-ignored to enable)"]
+    #[test]
+    #[serial]
+    fn test_select_vivid() -> Result<(), Box<dyn Error>> {
+        let device = vivid().ok_or("vivid capture device not found")?;
+        let camera = create_camera()
+            .with_selector(DeviceSelector::Driver("vivid".to_owned()))
+            .with_resolution(640, 480)
+            .with_control("brightness", 100)
+            .with_control(CID_CONTRAST, 60);
+        assert_eq!(camera.device()?, device);
+
+        let cam = camera.open()?;
+        assert_eq!(cam.control(CID_BRIGHTNESS)?, 100);
+        assert_eq!(cam.control(CID_CONTRAST)?, 60);
+        drop(cam);
+
+        let camera = create_camera().with_control("no such control", 1);
+        assert!(camera.with_device(&device).open().is_err());
+        Ok(())
+    }
 }
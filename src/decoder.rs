@@ -1,8 +1,15 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright 2025 Au-Zone Technologies
 
-use crate::{encoder::VSLRect, frame::Frame, Error};
+use crate::{
+    encoder::VSLRect,
+    fourcc::FourCC,
+    frame::Frame,
+    nal::{complete_len, next_start_code},
+    Error,
+};
 use std::{
+    collections::VecDeque,
     ffi::{c_int, c_void},
     io,
     ptr::null_mut,
@@ -11,14 +18,25 @@ use videostream_sys::{
     self as ffi, vsl_frame, VSLDecoderRetCode_VSL_DEC_ERR, VSLDecoderRetCode_VSL_DEC_FRAME_DEC,
     VSLDecoderRetCode_VSL_DEC_INIT_INFO,
 };
+
+/// Output formats which the decoder can provide.  The hardware decoder
+/// produces NV12 natively, other formats are converted on output.
+const OUTPUT_FORMATS: [FourCC; 3] = [FourCC(*b"NV12"), FourCC(*b"YUYV"), FourCC(*b"RGBA")];
+
+/// Upper bound on the frames held by the decoder which are retrieved by
+/// [`Decoder::flush`], the largest decoded picture buffer of H.264 and HEVC.
+const MAX_FLUSH_FRAMES: usize = 16;
+
 pub struct Decoder {
     ptr: *mut ffi::VSLDecoder,
+    output_format: FourCC,
 }
 
+#[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DecoderInputCodec {
-    H264 = 0,
-    HEVC = 1,
+    H264 = ffi::VSLDecoderCodec_VSL_DEC_H264,
+    HEVC = ffi::VSLDecoderCodec_VSL_DEC_HEVC,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -29,10 +47,30 @@ pub enum DecodeReturnCode {
 }
 
 impl Decoder {
-    pub fn create(input_codec: DecoderInputCodec, fps: c_int) -> Self {
-        Decoder {
-            ptr: unsafe { ffi::vsl_decoder_create(input_codec as u32, fps) },
+    /// Creates a decoder for the codec which outputs frames in the requested
+    /// format, one of NV12, YUYV or RGBA.
+    pub fn new(
+        codec: DecoderInputCodec,
+        output_format: FourCC,
+        fps: c_int,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !OUTPUT_FORMATS.contains(&output_format) {
+            return Err(format!("unsupported decoder output format {}", output_format).into());
+        }
+
+        // The library takes the input codec and always decodes to NV12, the
+        // requested output format is applied by convert().
+        let ptr = unsafe { ffi::vsl_decoder_create(codec as ffi::VSLDecoderCodec, fps) };
+        if ptr.is_null() {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
         }
+
+        Ok(Decoder { ptr, output_format })
+    }
+
+    pub fn output_format(&self) -> FourCC {
+        self.output_format
     }
 
     pub fn width(&self) -> i32 {
@@ -81,8 +119,111 @@ impl Decoder {
             return_msg = DecodeReturnCode::Initialized;
         }
 
+        let output_frame = match output_frame {
+            Some(frame) => Some(self.convert(frame)?),
+            None => None,
+        };
+
         Ok((return_msg, bytes_used, output_frame))
     }
+
+    /// Signals the end of the stream to the decoder and returns the frames
+    /// it was still holding.
+    ///
+    /// The library has no dedicated end of stream call, instead decoding an
+    /// empty buffer outputs the next picture held for reordering until none
+    /// remain.  A decoder which keeps returning frames beyond the size of the
+    /// decoded picture buffer is reported as an error.
+    pub fn flush(&self) -> Result<Vec<Frame>, Box<dyn Error>> {
+        drain(|| Ok(self.decode_frame(&[])?.2))
+    }
+
+    /// Converts the natively decoded frame into the requested output format,
+    /// removing any padding outside of the crop region.
+    fn convert(&self, frame: Frame) -> Result<Frame, Box<dyn Error>> {
+        if FourCC::from(frame.fourcc()) == self.output_format {
+            return Ok(frame);
+        }
+
+        let crop = self.crop();
+        let output = Frame::new(
+            crop.get_width().try_into()?,
+            crop.get_height().try_into()?,
+            0,
+            self.output_format.to_string().as_str(),
+        )?;
+        output.alloc(None)?;
+        output.copy_from(&frame, Some(&crop))?;
+        Ok(output)
+    }
+}
+
+/// Collects the frames returned by `next` until it returns none, failing
+/// once more than [`MAX_FLUSH_FRAMES`] were returned.
+fn drain<F>(mut next: F) -> Result<Vec<Frame>, Box<dyn Error>>
+where
+    F: FnMut() -> Result<Option<Frame>, Box<dyn Error>>,
+{
+    let mut frames = Vec::new();
+    while let Some(frame) = next()? {
+        if frames.len() == MAX_FLUSH_FRAMES {
+            return Err(format!(
+                "decoder returned more than {} frames on flush",
+                MAX_FLUSH_FRAMES
+            )
+            .into());
+        }
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// The VideoDecoder trait abstracts the decoder backend so that code built on
+/// the decoder can run with either the hardware [`Decoder`] or a software
+/// implementation such as `software::SoftwareDecoder` of the `software`
+/// feature.
+pub trait VideoDecoder {
+    /// Decodes the Annex-B data, returning the decoder status, the number of
+    /// bytes consumed and the decoded frame if one was completed.
+    fn decode_frame(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(DecodeReturnCode, usize, Option<Frame>), Box<dyn Error>>;
+
+    /// Signals the end of the stream and returns the frames still held by the
+    /// decoder.
+    fn flush(&mut self) -> Result<Vec<Frame>, Box<dyn Error>>;
+
+    fn width(&self) -> i32;
+
+    fn height(&self) -> i32;
+
+    fn crop(&self) -> VSLRect;
+}
+
+impl VideoDecoder for Decoder {
+    fn decode_frame(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(DecodeReturnCode, usize, Option<Frame>), Box<dyn Error>> {
+        Decoder::decode_frame(self, data)
+    }
+
+    fn flush(&mut self) -> Result<Vec<Frame>, Box<dyn Error>> {
+        Decoder::flush(self)
+    }
+
+    fn width(&self) -> i32 {
+        Decoder::width(self)
+    }
+
+    fn height(&self) -> i32 {
+        Decoder::height(self)
+    }
+
+    fn crop(&self) -> VSLRect {
+        Decoder::crop(self)
+    }
 }
 
 impl Drop for Decoder {
@@ -92,3 +233,216 @@ impl Drop for Decoder {
         }
     }
 }
+
+/// Events produced by a [`StreamDecoder`] while consuming the bitstream.
+pub enum DecodeEvent {
+    /// The decoder parsed the stream headers and configured its output.
+    Initialized {
+        width: i32,
+        height: i32,
+        crop: VSLRect,
+    },
+
+    /// The stream changed resolution after the decoder was initialized.
+    /// Frames following this event use the new resolution.
+    ResolutionChanged {
+        width: i32,
+        height: i32,
+        crop: VSLRect,
+    },
+
+    /// A frame was decoded.
+    Frame(Frame),
+}
+
+/// The StreamDecoder wraps a [`VideoDecoder`] with an input buffer so that the
+/// bitstream can be pushed in arbitrary chunks, for example as read from a
+/// file or socket, without the caller tracking the bytes consumed by the
+/// decoder.
+///
+/// Data is only submitted to the decoder once complete NAL units are
+/// available, that is once the start code of the following NAL unit has been
+/// received.
+pub struct StreamDecoder<D: VideoDecoder = Decoder> {
+    decoder: D,
+    buffer: Vec<u8>,
+    pending: VecDeque<DecodeEvent>,
+    resolution: Option<(i32, i32)>,
+    eos: bool,
+}
+
+impl<D: VideoDecoder> StreamDecoder<D> {
+    pub fn new(decoder: D) -> Self {
+        StreamDecoder {
+            decoder,
+            buffer: Vec::new(),
+            pending: VecDeque::new(),
+            resolution: None,
+            eos: false,
+        }
+    }
+
+    /// Appends Annex-B encoded data to the input buffer.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+        self.eos = false;
+    }
+
+    /// Decodes all complete NAL units currently buffered, yielding the
+    /// resulting events.  Partial NAL units are kept until more data is
+    /// pushed.
+    pub fn drain(&mut self) -> impl Iterator<Item = Result<DecodeEvent, Box<dyn Error>>> + '_ {
+        std::iter::from_fn(move || self.next_event())
+    }
+
+    /// Marks the end of the stream and decodes the remaining buffered data,
+    /// including the final NAL unit, followed by the frames still held by the
+    /// decoder.  Further data may be pushed afterwards to start a new stream.
+    pub fn flush(&mut self) -> impl Iterator<Item = Result<DecodeEvent, Box<dyn Error>>> + '_ {
+        self.eos = true;
+        let mut flushed = false;
+        std::iter::from_fn(move || {
+            if let Some(event) = self.next_event() {
+                return Some(event);
+            }
+            if flushed {
+                return None;
+            }
+            flushed = true;
+            match self.decoder.flush() {
+                Ok(frames) => {
+                    self.pending
+                        .extend(frames.into_iter().map(DecodeEvent::Frame));
+                    self.pending.pop_front().map(Ok)
+                }
+                Err(err) => Some(Err(err)),
+            }
+        })
+    }
+
+    /// Number of bytes buffered but not yet consumed by the decoder.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn decoder(&self) -> &D {
+        &self.decoder
+    }
+
+    pub fn into_inner(self) -> D {
+        self.decoder
+    }
+
+    fn next_event(&mut self) -> Option<Result<DecodeEvent, Box<dyn Error>>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+
+            let end = if self.eos {
+                self.buffer.len()
+            } else {
+                complete_len(&self.buffer)
+            };
+            if end == 0 {
+                return None;
+            }
+
+            let (code, used, frame) = match self.decoder.decode_frame(&self.buffer[..end]) {
+                Ok(ret) => ret,
+                Err(err) => {
+                    // Skip the offending NAL unit so the next call can resync on
+                    // the following start code.
+                    let from = next_start_code(&self.buffer, 0).map_or(1, |i| i + 3);
+                    let skip = next_start_code(&self.buffer, from).unwrap_or(end);
+                    self.buffer.drain(..skip);
+                    return Some(Err(err));
+                }
+            };
+            self.buffer.drain(..used.min(self.buffer.len()));
+
+            if code == DecodeReturnCode::Initialized {
+                let width = self.decoder.width();
+                let height = self.decoder.height();
+                let crop = self.decoder.crop();
+                match self.resolution.replace((width, height)) {
+                    None => self.pending.push_back(DecodeEvent::Initialized {
+                        width,
+                        height,
+                        crop,
+                    }),
+                    Some(previous) if previous != (width, height) => {
+                        self.pending.push_back(DecodeEvent::ResolutionChanged {
+                            width,
+                            height,
+                            crop,
+                        })
+                    }
+                    Some(_) => (),
+                }
+            }
+            if let Some(frame) = frame {
+                self.pending.push_back(DecodeEvent::Frame(frame));
+            }
+
+            if used == 0 && self.pending.is_empty() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encoder::VideoEncoder, nal::tests::PcmEncoder};
+    use serial_test::serial;
+
+    #[test]
+    fn test_unsupported_output_format() {
+        assert!(Decoder::new(DecoderInputCodec::H264, FourCC(*b"MJPG"), 30).is_err());
+    }
+
+    #[test]
+    fn test_drain() -> Result<(), Box<dyn Error>> {
+        let frame = || Frame::new(16, 16, 0, "NV12");
+
+        let mut held = 3;
+        let frames = drain(|| {
+            if held == 0 {
+                return Ok(None);
+            }
+            held -= 1;
+            Ok(Some(frame()?))
+        })?;
+        assert_eq!(frames.len(), 3);
+
+        assert!(drain(|| Ok(Some(frame()?))).is_err());
+        assert!(drain(|| Err("decoder error".into())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    #[ignore = "test requires the hardware decoder (run with --include-ignored to enable)"]
+    fn test_flush() -> Result<(), Box<dyn Error>> {
+        let source = Frame::new(64, 48, 0, "NV12")?;
+        source.alloc(None)?;
+        let mut encoder = PcmEncoder::new();
+        let decoder = Decoder::new(DecoderInputCodec::H264, FourCC(*b"NV12"), 30)?;
+        let mut decoder = StreamDecoder::new(decoder);
+        for _ in 0..4 {
+            decoder.push(&encoder.encode(&source)?.data);
+        }
+
+        let mut frames = 0;
+        for event in decoder.flush() {
+            if let DecodeEvent::Frame(frame) = event? {
+                assert_eq!((frame.width(), frame.height()), (64, 48));
+                frames += 1;
+            }
+        }
+        assert_eq!(frames, 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,1400 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    decoder::{DecodeEvent, Decoder, DecoderInputCodec, StreamDecoder, VideoDecoder},
+    encoder::EncodedPacket,
+    fourcc::FourCC,
+    frame::Frame,
+    host::Host,
+    mkv::{
+        EBML_HEADER, MKV_BLOCK, MKV_BLOCK_DURATION, MKV_BLOCK_GROUP, MKV_CLUSTER, MKV_CODEC_H264,
+        MKV_CODEC_HEVC, MKV_CODEC_ID, MKV_CODEC_PRIVATE, MKV_DEFAULT_DURATION, MKV_INFO,
+        MKV_PIXEL_HEIGHT, MKV_PIXEL_WIDTH, MKV_REFERENCE_BLOCK, MKV_SEGMENT, MKV_SIMPLE_BLOCK,
+        MKV_TIMECODE, MKV_TIMECODE_SCALE, MKV_TRACKS, MKV_TRACK_ENTRY, MKV_TRACK_NUMBER,
+        MKV_TRACK_TYPE, MKV_VIDEO,
+    },
+    mp4::{is_hevc, parse_avc_config, parse_hevc_config, resolution, ParameterSets},
+};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    error::Error,
+    ffi::c_int,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Frame rate given to the decoder by [`FilePlayer::open`] when the track does
+/// not describe its frame duration.
+pub const DEFAULT_FRAME_RATE: c_int = 30;
+
+/// The Demuxer trait provides the video track of a container file as a
+/// sequence of encoded packets which can be fed to a decoder.
+pub trait Demuxer {
+    /// The codec of the video track, H264 or HEVC.
+    fn codec(&self) -> FourCC;
+
+    fn width(&self) -> u32;
+
+    fn height(&self) -> u32;
+
+    /// The nominal duration of a frame in nanoseconds, None when the track
+    /// timing does not describe it.
+    fn frame_duration(&self) -> Option<i64>;
+
+    /// Reads the next sample of the video track in decoding order, returning
+    /// None at the end of the file.  The sample is converted to Annex-B
+    /// format and keyframes are prefixed with the parameter sets so decoding
+    /// can start from any keyframe.  Timestamps are in nanoseconds.
+    fn read_packet(&mut self) -> Result<Option<EncodedPacket>, Box<dyn Error>>;
+}
+
+/// Opens the MP4 or Matroska file, detecting the format from its contents.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<dyn Demuxer + Send>, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    file.rewind()?;
+    let reader = BufReader::new(file);
+    if u32::from_be_bytes(magic) == EBML_HEADER {
+        Ok(Box::new(MkvReader::new(reader)?))
+    } else {
+        Ok(Box::new(Mp4Reader::new(reader)?))
+    }
+}
+
+/// The codec configuration of the video track.
+struct Track {
+    codec: FourCC,
+    width: u32,
+    height: u32,
+    length_size: usize,
+    params: ParameterSets,
+}
+
+impl Track {
+    fn new(codec: FourCC, config: &[u8], width: u32, height: u32) -> Result<Self, Box<dyn Error>> {
+        let (length_size, params) = if is_hevc(codec) {
+            parse_hevc_config(config)?
+        } else {
+            parse_avc_config(config)?
+        };
+        let (width, height) = match (width, height) {
+            (0, _) | (_, 0) => resolution(codec, &params)?,
+            size => size,
+        };
+        Ok(Track {
+            codec,
+            width,
+            height,
+            length_size,
+            params,
+        })
+    }
+
+    /// Converts the length-prefixed sample to Annex-B format.
+    fn to_annexb(&self, data: &[u8], keyframe: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut out = Vec::with_capacity(data.len() + 64);
+        if keyframe {
+            for nal in self
+                .params
+                .vps
+                .iter()
+                .chain(&self.params.sps)
+                .chain(&self.params.pps)
+            {
+                out.extend_from_slice(&[0, 0, 0, 1]);
+                out.extend_from_slice(nal);
+            }
+        }
+
+        let mut pos = 0;
+        while pos < data.len() {
+            let prefix = data
+                .get(pos..pos + self.length_size)
+                .ok_or("truncated sample")?;
+            let len = prefix.iter().fold(0, |len, &b| (len << 8) | b as usize);
+            pos += self.length_size;
+            let nal = data.get(pos..pos + len).ok_or("truncated sample")?;
+            out.extend_from_slice(&[0, 0, 0, 1]);
+            out.extend_from_slice(nal);
+            pos += len;
+        }
+        Ok(out)
+    }
+}
+
+/// Reads big-endian fields from the payload of a box.
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + count)
+            .ok_or("truncated mp4 box")?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), Box<dyn Error>> {
+        self.bytes(count).map(|_| ())
+    }
+
+    fn u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    /// Reads the version and flags of a full box.
+    fn version(&mut self) -> Result<(u8, u32), Box<dyn Error>> {
+        let value = self.u32()?;
+        Ok(((value >> 24) as u8, value & 0x00ff_ffff))
+    }
+}
+
+/// Iterates over the boxes contained in the payload, yielding the box type
+/// and payload of each.
+fn children(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let header = data.get(pos..pos + 8)?;
+        let kind = header[4..8].try_into().ok()?;
+        let (start, size) = match u32::from_be_bytes(header[..4].try_into().ok()?) {
+            0 => (pos + 8, data.len() - pos),
+            1 => (
+                pos + 16,
+                u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().ok()?) as usize,
+            ),
+            size => (pos + 8, size as usize),
+        };
+        let end = pos.checked_add(size)?;
+        let payload = data.get(start..end)?;
+        pos = end;
+        Some((kind, payload))
+    })
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    children(data)
+        .find(|(k, _)| k == kind)
+        .map(|(_, payload)| payload)
+}
+
+fn require<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Result<&'a [u8], Box<dyn Error>> {
+    path.iter().try_fold(data, |data, kind| {
+        child(data, kind)
+            .ok_or_else(|| format!("missing mp4 box {}", String::from_utf8_lossy(*kind)).into())
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SampleEntry {
+    offset: u64,
+    size: u32,
+    dts: i64,
+    cts: i32,
+    duration: u32,
+    keyframe: bool,
+}
+
+/// Default sample values of a track, from the trex box, used by fragments.
+#[derive(Debug, Clone, Copy, Default)]
+struct TrackDefaults {
+    duration: u32,
+    size: u32,
+    flags: u32,
+}
+
+/// The Mp4Reader demuxes the first H.264 or H.265 video track of an MP4
+/// file.  Both regular and fragmented files, such as those recorded by
+/// [`crate::mp4::Mp4Writer`], are supported.
+///
+/// The sample index is built when the file is opened, the samples are then
+/// read on demand.
+pub struct Mp4Reader<R: Read + Seek = BufReader<File>> {
+    reader: R,
+    track: Track,
+    timescale: u32,
+    samples: Vec<SampleEntry>,
+    index: usize,
+}
+
+impl Mp4Reader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Mp4Reader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> Mp4Reader<R> {
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let mut pos = 0;
+        let mut moov = None;
+        let mut moofs = Vec::new();
+        while pos + 8 <= len {
+            reader.seek(SeekFrom::Start(pos))?;
+            let mut header = [0; 8];
+            reader.read_exact(&mut header)?;
+            let mut header_len = 8;
+            let size = match u32::from_be_bytes(header[..4].try_into()?) {
+                0 => len - pos,
+                1 => {
+                    let mut size = [0; 8];
+                    reader.read_exact(&mut size)?;
+                    header_len = 16;
+                    u64::from_be_bytes(size)
+                }
+                size => size as u64,
+            };
+            if size < header_len {
+                return Err("invalid mp4 box size".into());
+            }
+            if size > len - pos {
+                return Err("mp4 box extends past the end of the file".into());
+            }
+            if &header[4..] == b"moov" || &header[4..] == b"moof" {
+                let mut payload = vec![0; (size - header_len) as usize];
+                reader.read_exact(&mut payload)?;
+                if &header[4..] == b"moov" {
+                    moov = Some(payload);
+                } else {
+                    moofs.push((pos, payload));
+                }
+            }
+            pos += size;
+        }
+
+        let moov = moov.ok_or("missing mp4 movie header")?;
+        let mut defaults = TrackDefaults::default();
+        let mut found = None;
+        for (kind, trak) in children(&moov) {
+            if &kind == b"trak" {
+                if let Some(track) = parse_trak(trak, len)? {
+                    found = Some(track);
+                    break;
+                }
+            }
+        }
+        let (track_id, timescale, track, mut samples) =
+            found.ok_or("no h.264 or h.265 video track")?;
+
+        if let Some(mvex) = child(&moov, b"mvex") {
+            for (kind, trex) in children(mvex) {
+                let mut r = ByteReader::new(trex);
+                if &kind == b"trex" {
+                    r.skip(4)?;
+                    if r.u32()? == track_id {
+                        r.skip(4)?;
+                        defaults = TrackDefaults {
+                            duration: r.u32()?,
+                            size: r.u32()?,
+                            flags: r.u32()?,
+                        };
+                    }
+                }
+            }
+        }
+
+        let mut next_dts = samples.last().map_or(0, |s| s.dts + s.duration as i64);
+        for (offset, moof) in &moofs {
+            parse_moof(
+                *offset,
+                moof,
+                len,
+                track_id,
+                &defaults,
+                &mut samples,
+                &mut next_dts,
+            )?;
+        }
+
+        Ok(Mp4Reader {
+            reader,
+            track,
+            timescale,
+            samples,
+            index: 0,
+        })
+    }
+
+    /// Number of samples in the video track.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    fn to_ns(&self, ticks: i64) -> i64 {
+        (ticks as i128 * 1_000_000_000 / self.timescale as i128) as i64
+    }
+}
+
+impl<R: Read + Seek> Demuxer for Mp4Reader<R> {
+    fn codec(&self) -> FourCC {
+        self.track.codec
+    }
+
+    fn width(&self) -> u32 {
+        self.track.width
+    }
+
+    fn height(&self) -> u32 {
+        self.track.height
+    }
+
+    fn frame_duration(&self) -> Option<i64> {
+        self.samples
+            .iter()
+            .find(|sample| sample.duration > 0)
+            .map(|sample| self.to_ns(sample.duration as i64))
+    }
+
+    fn read_packet(&mut self) -> Result<Option<EncodedPacket>, Box<dyn Error>> {
+        let Some(sample) = self.samples.get(self.index).copied() else {
+            return Ok(None);
+        };
+        self.index += 1;
+
+        let mut data = vec![0; sample.size as usize];
+        self.reader.seek(SeekFrom::Start(sample.offset))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(EncodedPacket {
+            data: self.track.to_annexb(&data, sample.keyframe)?,
+            pts: self.to_ns(sample.dts + sample.cts as i64),
+            dts: self.to_ns(sample.dts),
+            duration: self.to_ns(sample.duration as i64),
+            keyframe: sample.keyframe,
+        }))
+    }
+}
+
+/// Parses the track, returning None if it is not a supported video track.
+#[allow(clippy::type_complexity)]
+fn parse_trak(
+    trak: &[u8],
+    len: u64,
+) -> Result<Option<(u32, u32, Track, Vec<SampleEntry>)>, Box<dyn Error>> {
+    let mdia = require(trak, &[b"mdia"])?;
+    let mut r = ByteReader::new(require(mdia, &[b"hdlr"])?);
+    r.skip(8)?;
+    if r.bytes(4)? != b"vide" {
+        return Ok(None);
+    }
+
+    let stbl = require(mdia, &[b"minf", b"stbl"])?;
+    let mut r = ByteReader::new(require(stbl, &[b"stsd"])?);
+    r.skip(8)?;
+    let Some((kind, entry)) = children(&r.data[r.pos..]).next() else {
+        return Ok(None);
+    };
+    let (codec, config) = match &kind {
+        b"avc1" | b"avc3" => (FourCC(*b"H264"), b"avcC"),
+        b"hvc1" | b"hev1" => (FourCC(*b"HEVC"), b"hvcC"),
+        _ => return Ok(None),
+    };
+    let mut r = ByteReader::new(entry);
+    r.skip(24)?;
+    let width = r.u16()? as u32;
+    let height = r.u16()? as u32;
+    let config = child(entry.get(78..).ok_or("truncated sample entry")?, config)
+        .ok_or("missing decoder configuration")?;
+    let track = Track::new(codec, config, width, height)?;
+
+    let mut r = ByteReader::new(require(trak, &[b"tkhd"])?);
+    let (version, _) = r.version()?;
+    r.skip(if version == 1 { 16 } else { 8 })?;
+    let track_id = r.u32()?;
+
+    let mut r = ByteReader::new(require(mdia, &[b"mdhd"])?);
+    let (version, _) = r.version()?;
+    r.skip(if version == 1 { 16 } else { 8 })?;
+    let timescale = r.u32()?;
+    if timescale == 0 {
+        return Err("invalid mp4 timescale".into());
+    }
+
+    Ok(Some((track_id, timescale, track, parse_stbl(stbl, len)?)))
+}
+
+/// Builds the sample index from the sample table of a regular MP4 file of
+/// `len` bytes.
+fn parse_stbl(stbl: &[u8], len: u64) -> Result<Vec<SampleEntry>, Box<dyn Error>> {
+    let mut chunks = Vec::new();
+    if let Some(stco) = child(stbl, b"stco") {
+        let mut r = ByteReader::new(stco);
+        r.skip(4)?;
+        for _ in 0..r.u32()? {
+            chunks.push(r.u32()? as u64);
+        }
+    } else if let Some(co64) = child(stbl, b"co64") {
+        let mut r = ByteReader::new(co64);
+        r.skip(4)?;
+        for _ in 0..r.u32()? {
+            chunks.push(r.u64()?);
+        }
+    }
+
+    let mut stsc = Vec::new();
+    let mut r = ByteReader::new(require(stbl, &[b"stsc"])?);
+    r.skip(4)?;
+    for _ in 0..r.u32()? {
+        let first_chunk = r.u32()?;
+        let samples = r.u32()?;
+        r.skip(4)?;
+        stsc.push((first_chunk as usize, samples as usize));
+    }
+    let per_chunk = |index: usize| {
+        stsc.iter()
+            .rev()
+            .find(|(first, _)| *first <= index + 1)
+            .map_or(0, |(_, samples)| *samples)
+    };
+
+    let Some(stsz) = child(stbl, b"stsz") else {
+        return Ok(Vec::new());
+    };
+    let mut sizes = ByteReader::new(stsz);
+    sizes.skip(4)?;
+    let constant = sizes.u32()?;
+    let count = sizes.u32()? as usize;
+    let chunk_samples = (0..chunks.len())
+        .map(per_chunk)
+        .fold(0usize, usize::saturating_add);
+    if count > chunk_samples {
+        return Err("mp4 sample count exceeds the samples of the chunks".into());
+    }
+
+    // Samples are only indexed as they are found within the file as neither
+    // the count nor the chunk tables bound the table of a constant size.
+    let mut samples = Vec::new();
+    'chunks: for (index, &offset) in chunks.iter().enumerate() {
+        let mut offset = offset;
+        for _ in 0..per_chunk(index) {
+            if samples.len() == count {
+                break 'chunks;
+            }
+            let size = match constant {
+                0 => sizes.u32()?,
+                size => size,
+            };
+            if offset.saturating_add(size as u64) > len {
+                return Err("mp4 sample extends past the end of the file".into());
+            }
+            samples.push(SampleEntry {
+                offset,
+                size,
+                dts: 0,
+                cts: 0,
+                duration: 0,
+                keyframe: true,
+            });
+            offset += size as u64;
+        }
+    }
+
+    let mut r = ByteReader::new(require(stbl, &[b"stts"])?);
+    r.skip(4)?;
+    let mut entries = samples.iter_mut();
+    let mut dts = 0;
+    for _ in 0..r.u32()? {
+        let count = r.u32()?;
+        let delta = r.u32()?;
+        for sample in entries.by_ref().take(count as usize) {
+            sample.dts = dts;
+            sample.duration = delta;
+            dts += delta as i64;
+        }
+    }
+
+    if let Some(ctts) = child(stbl, b"ctts") {
+        let mut r = ByteReader::new(ctts);
+        r.skip(4)?;
+        let mut entries = samples.iter_mut();
+        for _ in 0..r.u32()? {
+            let count = r.u32()?;
+            let offset = r.u32()? as i32;
+            for sample in entries.by_ref().take(count as usize) {
+                sample.cts = offset;
+            }
+        }
+    }
+
+    if let Some(stss) = child(stbl, b"stss") {
+        samples.iter_mut().for_each(|s| s.keyframe = false);
+        let mut r = ByteReader::new(stss);
+        r.skip(4)?;
+        for _ in 0..r.u32()? {
+            if let Some(sample) = (r.u32()? as usize)
+                .checked_sub(1)
+                .and_then(|i| samples.get_mut(i))
+            {
+                sample.keyframe = true;
+            }
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Appends the samples of the track from the movie fragment to the index,
+/// the samples must lie within the file of `len` bytes.
+fn parse_moof(
+    moof_offset: u64,
+    moof: &[u8],
+    len: u64,
+    track_id: u32,
+    defaults: &TrackDefaults,
+    samples: &mut Vec<SampleEntry>,
+    next_dts: &mut i64,
+) -> Result<(), Box<dyn Error>> {
+    for (kind, traf) in children(moof) {
+        if &kind != b"traf" {
+            continue;
+        }
+
+        let mut r = ByteReader::new(require(traf, &[b"tfhd"])?);
+        let (_, flags) = r.version()?;
+        if r.u32()? != track_id {
+            continue;
+        }
+        let base = if flags & 0x01 != 0 {
+            r.u64()?
+        } else {
+            moof_offset
+        };
+        if flags & 0x02 != 0 {
+            r.skip(4)?;
+        }
+        let mut track = *defaults;
+        if flags & 0x08 != 0 {
+            track.duration = r.u32()?;
+        }
+        if flags & 0x10 != 0 {
+            track.size = r.u32()?;
+        }
+        if flags & 0x20 != 0 {
+            track.flags = r.u32()?;
+        }
+
+        if let Some(tfdt) = child(traf, b"tfdt") {
+            let mut r = ByteReader::new(tfdt);
+            let (version, _) = r.version()?;
+            *next_dts = if version == 1 {
+                r.u64()? as i64
+            } else {
+                r.u32()? as i64
+            };
+        }
+
+        let mut data_pos = base;
+        for (kind, trun) in children(traf) {
+            if &kind != b"trun" {
+                continue;
+            }
+            let mut r = ByteReader::new(trun);
+            let (_, flags) = r.version()?;
+            let count = r.u32()?;
+            if count as u64 > len {
+                return Err("mp4 track run has more samples than the file".into());
+            }
+            if flags & 0x01 != 0 {
+                data_pos = base.wrapping_add_signed(r.u32()? as i32 as i64);
+            }
+            let first_flags = if flags & 0x04 != 0 {
+                Some(r.u32()?)
+            } else {
+                None
+            };
+            for i in 0..count {
+                let duration = if flags & 0x100 != 0 {
+                    r.u32()?
+                } else {
+                    track.duration
+                };
+                let size = if flags & 0x200 != 0 {
+                    r.u32()?
+                } else {
+                    track.size
+                };
+                let mut sample_flags = if flags & 0x400 != 0 {
+                    r.u32()?
+                } else {
+                    track.flags
+                };
+                if i == 0 {
+                    sample_flags = first_flags.unwrap_or(sample_flags);
+                }
+                let cts = if flags & 0x800 != 0 {
+                    r.u32()? as i32
+                } else {
+                    0
+                };
+                if data_pos.saturating_add(size as u64) > len {
+                    return Err("mp4 sample extends past the end of the file".into());
+                }
+                samples.push(SampleEntry {
+                    offset: data_pos,
+                    size,
+                    dts: *next_dts,
+                    cts,
+                    duration,
+                    keyframe: sample_flags & 0x0001_0000 == 0,
+                });
+                data_pos += size as u64;
+                *next_dts += duration as i64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads an EBML variable length integer, returning the value and its length
+/// in bytes.  Element IDs keep their length marker while sizes have it
+/// removed, an unknown size is returned as None.
+fn read_vint<R: Read>(
+    reader: &mut R,
+    marker: bool,
+) -> Result<(Option<u64>, usize), Box<dyn Error>> {
+    let mut first = [0; 1];
+    reader.read_exact(&mut first)?;
+    let len = first[0].leading_zeros() as usize + 1;
+    if len > 8 {
+        return Err("invalid ebml variable length integer".into());
+    }
+    let mut value = if marker {
+        first[0] as u64
+    } else {
+        (first[0] as u64) & (0xff >> len)
+    };
+    let mut all_ones = value == (0xff >> len);
+    let mut rest = [0; 7];
+    reader.read_exact(&mut rest[..len - 1])?;
+    for &byte in &rest[..len - 1] {
+        value = (value << 8) | byte as u64;
+        all_ones &= byte == 0xff;
+    }
+    if !marker && all_ones {
+        return Ok((None, len));
+    }
+    Ok((Some(value), len))
+}
+
+/// Reads an element header, returning the ID, size and header length.
+fn read_element<R: Read>(reader: &mut R) -> Result<(u32, Option<u64>, u64), Box<dyn Error>> {
+    let (id, id_len) = read_vint(reader, true)?;
+    let (size, size_len) = read_vint(reader, false)?;
+    Ok((id.unwrap_or(0) as u32, size, (id_len + size_len) as u64))
+}
+
+/// Iterates over the child elements contained in the payload, yielding the
+/// ID and payload of each.
+/// Returns the size of the element whose payload starts at pos, which must be
+/// known and within the end of its parent before the payload is allocated.
+fn element_size(size: Option<u64>, pos: u64, end: u64) -> Result<u64, Box<dyn Error>> {
+    let size = size.ok_or("unknown size matroska element")?;
+    if size > end.saturating_sub(pos) {
+        return Err("matroska element extends past the end of the file".into());
+    }
+    Ok(size)
+}
+
+pub(crate) fn ebml_children(data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let mut reader = data.get(pos..)?;
+        let available = reader.len() as u64;
+        let (id, size, header) = read_element(&mut reader).ok()?;
+        let size = size.unwrap_or(available - header).min(available - header);
+        let start = pos + header as usize;
+        let payload = &data[start..start + size as usize];
+        pos = start + size as usize;
+        Some((id, payload))
+    })
+}
+
+pub(crate) fn ebml_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, &b| (value << 8) | b as u64)
+}
+
+/// The MkvReader demuxes the first H.264 or H.265 video track of a Matroska
+/// or WebM file.
+///
+/// The file is read sequentially, Matroska does not store decoding
+/// timestamps so the dts of the packets is set to the pts of the block.
+pub struct MkvReader<R: Read + Seek = BufReader<File>> {
+    reader: R,
+    track: Track,
+    track_number: u64,
+    timecode_scale: i64,
+    default_duration: i64,
+    cluster_time: i64,
+    pos: u64,
+    end: u64,
+}
+
+impl MkvReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        MkvReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> MkvReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let (id, size, header) = read_element(&mut reader)?;
+        if id != EBML_HEADER {
+            return Err("not a matroska file".into());
+        }
+        let mut pos = header + size.ok_or("invalid ebml header")?;
+        reader.seek(SeekFrom::Start(pos))?;
+        let (id, size, header) = read_element(&mut reader)?;
+        if id != MKV_SEGMENT {
+            return Err("missing matroska segment".into());
+        }
+        pos += header;
+        let end = size.map_or(len, |size| (pos + size).min(len));
+
+        let mut timecode_scale = 1_000_000;
+        let mut found = None;
+        while pos < end {
+            reader.seek(SeekFrom::Start(pos))?;
+            let (id, size, header) = read_element(&mut reader)?;
+            match id {
+                MKV_CLUSTER => break,
+                MKV_INFO | MKV_TRACKS => {
+                    let size = element_size(size, pos + header, end)?;
+                    let mut payload = vec![0; size as usize];
+                    reader.read_exact(&mut payload)?;
+                    if id == MKV_INFO {
+                        if let Some((_, scale)) =
+                            ebml_children(&payload).find(|(id, _)| *id == MKV_TIMECODE_SCALE)
+                        {
+                            timecode_scale = ebml_uint(scale) as i64;
+                        }
+                    } else if found.is_none() {
+                        found = parse_tracks(&payload)?;
+                    }
+                }
+                _ => (),
+            }
+            pos += header + size.ok_or("unknown size matroska element")?;
+        }
+        let (track_number, default_duration, track) =
+            found.ok_or("no h.264 or h.265 video track")?;
+
+        Ok(MkvReader {
+            reader,
+            track,
+            track_number,
+            timecode_scale,
+            default_duration,
+            cluster_time: 0,
+            pos,
+            end,
+        })
+    }
+
+    /// Parses the block, returning the packet if it belongs to the video
+    /// track.  The keyframe flag is given for blocks of a block group.
+    fn parse_block(
+        &self,
+        block: &[u8],
+        keyframe: Option<bool>,
+        duration: Option<i64>,
+    ) -> Result<Option<EncodedPacket>, Box<dyn Error>> {
+        let mut reader = block;
+        let (track, _) = read_vint(&mut reader, false)?;
+        if track != Some(self.track_number) {
+            return Ok(None);
+        }
+        if reader.len() < 3 {
+            return Err("truncated matroska block".into());
+        }
+        let timecode = i16::from_be_bytes([reader[0], reader[1]]) as i64;
+        let flags = reader[2];
+        if flags & 0x06 != 0 {
+            return Err("laced matroska blocks are not supported".into());
+        }
+        let keyframe = keyframe.unwrap_or(flags & 0x80 != 0);
+        let pts = (self.cluster_time + timecode) * self.timecode_scale;
+        Ok(Some(EncodedPacket {
+            data: self.track.to_annexb(&reader[3..], keyframe)?,
+            pts,
+            dts: pts,
+            duration: duration.map_or(self.default_duration, |d| d * self.timecode_scale),
+            keyframe,
+        }))
+    }
+}
+
+/// Parses the tracks element, returning the number, default duration and
+/// configuration of the first supported video track.
+#[allow(clippy::type_complexity)]
+fn parse_tracks(tracks: &[u8]) -> Result<Option<(u64, i64, Track)>, Box<dyn Error>> {
+    for (id, entry) in ebml_children(tracks) {
+        if id != MKV_TRACK_ENTRY {
+            continue;
+        }
+        let mut number = 0;
+        let mut kind = 0;
+        let mut codec = None;
+        let mut config: &[u8] = &[];
+        let mut duration = 0;
+        let mut width = 0;
+        let mut height = 0;
+        for (id, value) in ebml_children(entry) {
+            match id {
+                MKV_TRACK_NUMBER => number = ebml_uint(value),
+                MKV_TRACK_TYPE => kind = ebml_uint(value),
+                MKV_CODEC_ID => {
+                    codec = match std::str::from_utf8(value)?.trim_end_matches('\0') {
+                        MKV_CODEC_H264 => Some(FourCC(*b"H264")),
+                        MKV_CODEC_HEVC => Some(FourCC(*b"HEVC")),
+                        _ => None,
+                    }
+                }
+                MKV_CODEC_PRIVATE => config = value,
+                MKV_DEFAULT_DURATION => duration = ebml_uint(value) as i64,
+                MKV_VIDEO => {
+                    for (id, value) in ebml_children(value) {
+                        match id {
+                            MKV_PIXEL_WIDTH => width = ebml_uint(value) as u32,
+                            MKV_PIXEL_HEIGHT => height = ebml_uint(value) as u32,
+                            _ => (),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        if let (1, Some(codec)) = (kind, codec) {
+            return Ok(Some((
+                number,
+                duration,
+                Track::new(codec, config, width, height)?,
+            )));
+        }
+    }
+    Ok(None)
+}
+
+impl<R: Read + Seek> Demuxer for MkvReader<R> {
+    fn codec(&self) -> FourCC {
+        self.track.codec
+    }
+
+    fn width(&self) -> u32 {
+        self.track.width
+    }
+
+    fn height(&self) -> u32 {
+        self.track.height
+    }
+
+    fn frame_duration(&self) -> Option<i64> {
+        (self.default_duration > 0).then_some(self.default_duration)
+    }
+
+    fn read_packet(&mut self) -> Result<Option<EncodedPacket>, Box<dyn Error>> {
+        while self.pos < self.end {
+            self.reader.seek(SeekFrom::Start(self.pos))?;
+            let (id, size, header) = read_element(&mut self.reader)?;
+            self.pos += header;
+
+            // Clusters are entered rather than read whole as they may be of
+            // unknown size when written by a live recorder.
+            if id == MKV_CLUSTER {
+                continue;
+            }
+            if !matches!(id, MKV_TIMECODE | MKV_SIMPLE_BLOCK | MKV_BLOCK_GROUP) {
+                self.pos += size.ok_or("unknown size matroska element")?;
+                continue;
+            }
+            let size = element_size(size, self.pos, self.end)?;
+            self.pos += size;
+
+            let mut payload = vec![0; size as usize];
+            self.reader.read_exact(&mut payload)?;
+            let packet = match id {
+                MKV_TIMECODE => {
+                    self.cluster_time = ebml_uint(&payload) as i64;
+                    None
+                }
+                MKV_SIMPLE_BLOCK => self.parse_block(&payload, None, None)?,
+                _ => {
+                    let mut block = None;
+                    let mut keyframe = true;
+                    let mut duration = None;
+                    for (id, value) in ebml_children(&payload) {
+                        match id {
+                            MKV_BLOCK => block = Some(value),
+                            MKV_REFERENCE_BLOCK => keyframe = false,
+                            MKV_BLOCK_DURATION => duration = Some(ebml_uint(value) as i64),
+                            _ => (),
+                        }
+                    }
+                    match block {
+                        Some(block) => self.parse_block(block, Some(keyframe), duration)?,
+                        None => None,
+                    }
+                }
+            };
+            if packet.is_some() {
+                return Ok(packet);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Pacing of the frames published by the [`FilePlayer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    /// Frames are published according to their presentation timestamps.
+    RealTime,
+
+    /// Frames are published as soon as they are decoded.
+    AsFastAsPossible,
+}
+
+/// A frame decoded by the [`FilePlayer`] along with its timing from the file,
+/// in nanoseconds.
+pub struct PlayerFrame {
+    pub frame: Frame,
+    pub pts: i64,
+    pub duration: i64,
+}
+
+/// The FilePlayer decodes the video track of a recorded file and publishes
+/// the frames to a [`Host`], allowing recordings to be replayed through the
+/// applications subscribed to the host as if they were a live camera.
+pub struct FilePlayer<D: VideoDecoder = Decoder> {
+    demuxer: Box<dyn Demuxer + Send>,
+    decoder: StreamDecoder<D>,
+    pace: Pace,
+    lifetime: i64,
+    timestamps: BinaryHeap<Reverse<(i64, i64)>>,
+    frames: VecDeque<Frame>,
+    start: Option<(Instant, i64)>,
+    eos: bool,
+}
+
+impl FilePlayer<Decoder> {
+    /// Opens the MP4 or Matroska file and creates a hardware decoder for its
+    /// video track which outputs frames in the requested format, one of NV12,
+    /// YUYV or RGBA.  The decoder frame rate is that of the track, or
+    /// [`DEFAULT_FRAME_RATE`] when the track timing does not describe it.
+    pub fn open<P: AsRef<Path>>(path: P, output_format: FourCC) -> Result<Self, Box<dyn Error>> {
+        let demuxer = open(path)?;
+        let codec = if is_hevc(demuxer.codec()) {
+            DecoderInputCodec::HEVC
+        } else {
+            DecoderInputCodec::H264
+        };
+        let fps = demuxer
+            .frame_duration()
+            .map_or(DEFAULT_FRAME_RATE, |duration| {
+                (1_000_000_000.0 / duration as f64).round().max(1.0) as c_int
+            });
+        let decoder = Decoder::new(codec, output_format, fps)?;
+        Ok(FilePlayer::new(demuxer, decoder))
+    }
+}
+
+impl<D: VideoDecoder> FilePlayer<D> {
+    pub fn new(demuxer: Box<dyn Demuxer + Send>, decoder: D) -> Self {
+        FilePlayer {
+            demuxer,
+            decoder: StreamDecoder::new(decoder),
+            pace: Pace::RealTime,
+            lifetime: 100_000_000,
+            timestamps: BinaryHeap::new(),
+            frames: VecDeque::new(),
+            start: None,
+            eos: false,
+        }
+    }
+
+    /// Sets the pacing of the published frames, the default is real-time.
+    pub fn with_pace(mut self, pace: Pace) -> Self {
+        self.pace = pace;
+        self
+    }
+
+    /// Sets how long published frames remain available to clients, the
+    /// default is 100ms.
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime.as_nanos() as i64;
+        self
+    }
+
+    pub fn demuxer(&self) -> &dyn Demuxer {
+        self.demuxer.as_ref()
+    }
+
+    /// Decodes the next frame of the file, returning None at the end of the
+    /// file.
+    pub fn next_frame(&mut self) -> Result<Option<PlayerFrame>, Box<dyn Error>> {
+        loop {
+            if let Some(frame) = self.frames.pop_front() {
+                // Frames are output in presentation order so take the lowest
+                // pending presentation timestamp.
+                let Reverse((pts, duration)) = self.timestamps.pop().unwrap_or_default();
+                return Ok(Some(PlayerFrame {
+                    frame,
+                    pts,
+                    duration,
+                }));
+            }
+            if self.eos {
+                return Ok(None);
+            }
+
+            let events: Vec<_> = match self.demuxer.read_packet()? {
+                Some(packet) => {
+                    self.timestamps.push(Reverse((packet.pts, packet.duration)));
+                    self.decoder.push(&packet.data);
+                    self.decoder.drain().collect()
+                }
+                None => {
+                    self.eos = true;
+                    self.decoder.flush().collect()
+                }
+            };
+            let mut error = None;
+            for event in events {
+                match event {
+                    Ok(DecodeEvent::Frame(frame)) => self.frames.push_back(frame),
+                    Ok(_) => (),
+                    Err(err) => {
+                        // The failed access unit produces no frame, drop the
+                        // oldest pending timestamp so the following frames
+                        // keep their own.
+                        self.timestamps.pop();
+                        error = error.or(Some(err));
+                    }
+                }
+            }
+            if let Some(err) = error {
+                return Err(err);
+            }
+        }
+    }
+
+    /// Plays the remainder of the file, publishing the frames to the host
+    /// while servicing its clients.  Returns the number of frames published.
+    pub fn play(&mut self, host: &Host) -> Result<u64, Box<dyn Error>> {
+        let mut count = 0;
+        while let Some(decoded) = self.next_frame()? {
+            let deadline = match self.pace {
+                Pace::RealTime => {
+                    let (start, first) = *self.start.get_or_insert((Instant::now(), decoded.pts));
+                    start + Duration::from_nanos((decoded.pts - first).max(0) as u64)
+                }
+                Pace::AsFastAsPossible => Instant::now(),
+            };
+            service(host, deadline)?;
+
+            let expires = crate::timestamp() + self.lifetime;
+            host.post(
+                decoded.frame,
+                expires,
+                decoded.duration,
+                decoded.pts,
+                decoded.pts,
+            )?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Services the host clients until the deadline, polling at least once.
+fn service(host: &Host, deadline: Instant) -> Result<(), Box<dyn Error>> {
+    loop {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        if host.poll(wait.as_millis() as i64)? > 0 {
+            // Client errors such as a disconnect are not fatal to playback.
+            let _ = host.process();
+        }
+        if Instant::now() >= deadline {
+            return Ok(());
+        }
+        if wait < Duration::from_millis(1) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encoder::VideoEncoder,
+        mkv::element,
+        mp4::Mp4Writer,
+        nal::tests::{packet, pcm_packets, PcmDecoder, PcmEncoder, PPS, SPS},
+    };
+    use std::io::Cursor;
+
+    fn avcc() -> Vec<u8> {
+        let mut config = vec![1, 0x42, 0xc0, 0x1f, 0xff, 0xe1];
+        config.extend_from_slice(&(SPS.len() as u16).to_be_bytes());
+        config.extend_from_slice(&SPS);
+        config.push(1);
+        config.extend_from_slice(&(PPS.len() as u16).to_be_bytes());
+        config.extend_from_slice(&PPS);
+        config
+    }
+
+    fn block(timecode: i16, keyframe: bool, nal: &[u8]) -> Vec<u8> {
+        let mut block = vec![0x81];
+        block.extend_from_slice(&timecode.to_be_bytes());
+        block.push(if keyframe { 0x80 } else { 0 });
+        block.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        block.extend_from_slice(nal);
+        block
+    }
+
+    /// Builds a matroska file of an H.264 track and the cluster.
+    fn mkv_file(cluster: &[u8]) -> Vec<u8> {
+        let video = [
+            element(MKV_PIXEL_WIDTH, &[0x05, 0x00]),
+            element(MKV_PIXEL_HEIGHT, &[0x02, 0xd0]),
+        ]
+        .concat();
+        let track = [
+            element(MKV_TRACK_NUMBER, &[1]),
+            element(MKV_TRACK_TYPE, &[1]),
+            element(MKV_CODEC_ID, MKV_CODEC_H264.as_bytes()),
+            element(MKV_CODEC_PRIVATE, &avcc()),
+            element(MKV_DEFAULT_DURATION, &33_333_333u32.to_be_bytes()),
+            element(MKV_VIDEO, &video),
+        ]
+        .concat();
+        let segment = [
+            element(MKV_INFO, &element(MKV_TIMECODE_SCALE, &[0x0f, 0x42, 0x40])),
+            element(MKV_TRACKS, &element(MKV_TRACK_ENTRY, &track)),
+            element(MKV_CLUSTER, cluster),
+        ]
+        .concat();
+        [
+            element(EBML_HEADER, &element(0x4282, b"matroska")),
+            element(MKV_SEGMENT, &segment),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_mkv() {
+        let cluster = [
+            element(MKV_TIMECODE, &[0x03, 0xe8]),
+            element(MKV_SIMPLE_BLOCK, &block(0, true, &[0x65, 0x88])),
+            element(
+                MKV_BLOCK_GROUP,
+                &[
+                    element(MKV_BLOCK, &block(33, false, &[0x41, 0x9a])),
+                    element(MKV_REFERENCE_BLOCK, &[0xdf]),
+                ]
+                .concat(),
+            ),
+        ]
+        .concat();
+        let file = mkv_file(&cluster);
+
+        let mut mkv = MkvReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(mkv.codec(), FourCC(*b"H264"));
+        assert_eq!((mkv.width(), mkv.height()), (1280, 720));
+        assert_eq!(mkv.frame_duration(), Some(33_333_333));
+
+        let packet = mkv.read_packet().unwrap().unwrap();
+        assert!(packet.keyframe);
+        assert_eq!(packet.pts, 1_000_000_000);
+        let mut expected = Vec::new();
+        for nal in [&SPS[..], &PPS[..], &[0x65, 0x88]] {
+            expected.extend_from_slice(&[0, 0, 0, 1]);
+            expected.extend_from_slice(nal);
+        }
+        assert_eq!(packet.data, expected);
+
+        let packet = mkv.read_packet().unwrap().unwrap();
+        assert!(!packet.keyframe);
+        assert_eq!(packet.pts, 1_033_000_000);
+        assert_eq!(packet.data, [0, 0, 0, 1, 0x41, 0x9a]);
+        assert!(mkv.read_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_mp4_roundtrip() {
+        let mut out = Vec::new();
+        {
+            let mut mp4 = Mp4Writer::new(&mut out, FourCC(*b"H264"))
+                .unwrap()
+                .with_fragment_duration(Duration::from_millis(100));
+            for i in 0..10 {
+                mp4.write(&packet(i, i % 5 == 0, 0, 40_000_000)).unwrap();
+            }
+        }
+
+        let mut mp4 = Mp4Reader::new(Cursor::new(out)).unwrap();
+        assert_eq!(mp4.len(), 10);
+        assert_eq!((mp4.width(), mp4.height()), (1280, 720));
+        assert_eq!(mp4.frame_duration(), Some(40_000_000));
+        for i in 0..10 {
+            let packet = mp4.read_packet().unwrap().unwrap();
+            assert_eq!(packet.keyframe, i % 5 == 0);
+            assert_eq!(packet.pts, i as i64 * 40_000_000);
+            assert_eq!(packet.duration, 40_000_000);
+            assert_eq!(packet.data.last(), Some(&(0x80 | i)));
+            assert_eq!(
+                packet.data.starts_with(&[0, 0, 0, 1, 0x67]),
+                packet.keyframe
+            );
+        }
+        assert!(mp4.read_packet().unwrap().is_none());
+    }
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_mp4_limits() {
+        // A movie header claiming more bytes than the file holds.
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend_from_slice(&0x7fff_ffffu32.to_be_bytes());
+        data.extend_from_slice(b"moov");
+        assert!(Mp4Reader::new(Cursor::new(data)).is_err());
+
+        // A single chunk of one sample with a constant sample size.
+        let stbl = |count: u32, size: u32| {
+            let mut stbl = mp4_box(b"stco", &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+            stbl.extend(mp4_box(
+                b"stsc",
+                &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1],
+            ));
+            let mut stsz = vec![0; 4];
+            stsz.extend_from_slice(&size.to_be_bytes());
+            stsz.extend_from_slice(&count.to_be_bytes());
+            stbl.extend(mp4_box(b"stsz", &stsz));
+            stbl.extend(mp4_box(b"stts", &[0, 0, 0, 0, 0, 0, 0, 0]));
+            stbl
+        };
+        assert_eq!(parse_stbl(&stbl(1, 100), 100).unwrap().len(), 1);
+        assert!(parse_stbl(&stbl(u32::MAX, 100), 100).is_err());
+        assert!(parse_stbl(&stbl(1, 101), 100).is_err());
+    }
+
+    #[test]
+    fn test_mkv_limits() {
+        // An element claiming 2^48 bytes, far more than the file holds.
+        let oversized = |id: u32| {
+            let mut element = element(id, &[]);
+            element.pop();
+            element.extend_from_slice(&[0x01, 0x01, 0, 0, 0, 0, 0, 0]);
+            element.extend_from_slice(&[0; 16]);
+            element
+        };
+        let file = [
+            element(EBML_HEADER, &element(0x4282, b"matroska")),
+            element(MKV_SEGMENT, &oversized(MKV_TRACKS)),
+        ]
+        .concat();
+        assert!(MkvReader::new(Cursor::new(file)).is_err());
+
+        let cluster = [
+            element(MKV_TIMECODE, &[0]),
+            element(MKV_SIMPLE_BLOCK, &block(0, true, &[0x65, 0x88])),
+            oversized(MKV_SIMPLE_BLOCK),
+        ]
+        .concat();
+        let mut mkv = MkvReader::new(Cursor::new(mkv_file(&cluster))).unwrap();
+        assert!(mkv.read_packet().unwrap().is_some());
+        assert!(mkv.read_packet().is_err());
+
+        // A block cut short by the end of the file.
+        let mut file = mkv_file(&element(MKV_SIMPLE_BLOCK, &block(0, true, &[0x65, 0x88])));
+        file.truncate(file.len() - 2);
+        let mut mkv = MkvReader::new(Cursor::new(file)).unwrap();
+        assert!(mkv.read_packet().is_err());
+    }
+
+    #[test]
+    fn test_player() -> Result<(), Box<dyn Error>> {
+        let mut encoder = PcmEncoder::new();
+        let mut out = Vec::new();
+        {
+            let mut mp4 = Mp4Writer::new(&mut out, FourCC(*b"H264"))?;
+            for i in 0..3 {
+                let frame = Frame::new(32, 16, 0, "NV12")?;
+                frame.alloc(None)?;
+                frame.mmap_mut().unwrap().fill(i * 50);
+                let mut packet = encoder.encode(&frame)?;
+                packet.pts = i as i64 * 20_000_000;
+                packet.dts = packet.pts;
+                mp4.write(&packet)?;
+            }
+        }
+
+        let demuxer = Box::new(Mp4Reader::new(Cursor::new(out))?);
+        let mut player = FilePlayer::new(demuxer, PcmDecoder::new());
+        for i in 0..3 {
+            let decoded = player.next_frame()?.unwrap();
+            assert_eq!(decoded.pts, i * 20_000_000);
+            assert_eq!(decoded.frame.width(), 32);
+            assert_eq!(decoded.frame.mmap().unwrap()[0], i as u8 * 50);
+        }
+        assert!(player.next_frame()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_player_decode_error() -> Result<(), Box<dyn Error>> {
+        let mut packets = pcm_packets(3, 1)?;
+        // An IDR slice which does not start at the first macroblock, which the
+        // decoder rejects.
+        packets[1].data = vec![0, 0, 0, 1, 0x65, 0x40];
+
+        let mut out = Vec::new();
+        {
+            let mut mp4 = Mp4Writer::new(&mut out, FourCC(*b"H264"))?;
+            for packet in &packets {
+                mp4.write(packet)?;
+            }
+        }
+
+        let demuxer = Box::new(Mp4Reader::new(Cursor::new(out))?);
+        let mut player = FilePlayer::new(demuxer, PcmDecoder::new());
+        assert_eq!(player.next_frame()?.unwrap().pts, packets[0].pts);
+        assert!(player.next_frame().is_err());
+        assert_eq!(player.next_frame()?.unwrap().pts, packets[2].pts);
+        assert!(player.next_frame()?.is_none());
+        Ok(())
+    }
+}
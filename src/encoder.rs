@@ -2,7 +2,7 @@
 // Copyright 2025 Au-Zone Technologies
 
 use crate::{frame, NullStringError};
-use std::{error::Error, os::raw::c_int};
+use std::{error::Error, io, os::raw::c_int};
 use videostream_sys as ffi;
 
 pub struct Encoder {
@@ -13,6 +13,7 @@ pub struct VSLEncoderProfile {
     _profile: ffi::VSLEncoderProfile,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct VSLRect {
     pub(crate) rect: ffi::vsl_rect,
 }
@@ -27,6 +28,45 @@ pub enum VSLEncoderProfileEnum {
     Kbps100000 = ffi::vsl_encode_profile_VSL_ENCODE_PROFILE_100000_KBPS,
 }
 
+/// An encoded access unit in Annex-B format along with its timing, as
+/// produced by a [`VideoEncoder`].  Timestamps are in nanoseconds.
+#[derive(Debug, Clone, Default)]
+pub struct EncodedPacket {
+    pub data: Vec<u8>,
+    pub pts: i64,
+    pub dts: i64,
+    pub duration: i64,
+    pub keyframe: bool,
+}
+
+impl EncodedPacket {
+    /// Creates a packet from a frame holding an encoded bitstream, such as
+    /// the output frame of the [`Encoder`] or a frame received from a host
+    /// publishing encoded video, taking the timestamps from the frame.
+    pub fn from_frame(frame: &frame::Frame, keyframe: bool) -> Result<Self, Box<dyn Error>> {
+        let data = frame
+            .mmap()
+            .map_err(|_| io::Error::other("failed to map encoded frame"))?;
+        Ok(EncodedPacket {
+            data: data.to_vec(),
+            pts: frame.pts(),
+            dts: frame.dts(),
+            duration: frame.duration(),
+            keyframe,
+        })
+    }
+}
+
+/// The VideoEncoder trait abstracts the encoder backend so that code built on
+/// the encoder can run with either the hardware [`Encoder`] or a software
+/// implementation such as `software::SoftwareEncoder` of the `software`
+/// feature.
+pub trait VideoEncoder {
+    /// Encodes the source frame into a packet which carries over the pts, dts
+    /// and duration of the source frame.
+    fn encode(&mut self, source: &frame::Frame) -> Result<EncodedPacket, Box<dyn Error>>;
+}
+
 impl VSLRect {
     pub fn new(x: c_int, y: c_int, width: c_int, height: c_int) -> Self {
         VSLRect {
@@ -105,6 +145,30 @@ impl Encoder {
     }
 }
 
+impl VideoEncoder for Encoder {
+    fn encode(&mut self, source: &frame::Frame) -> Result<EncodedPacket, Box<dyn Error>> {
+        let destination = self.new_output_frame(
+            source.width(),
+            source.height(),
+            source.duration(),
+            source.pts(),
+            source.dts(),
+        )?;
+        let crop = VSLRect::new(0, 0, source.width(), source.height());
+        let mut keyframe: c_int = 0;
+        if unsafe { self.frame(source, &destination, &crop, &mut keyframe) } != 0 {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+
+        let mut packet = EncodedPacket::from_frame(&destination, keyframe != 0)?;
+        packet.pts = source.pts();
+        packet.dts = source.dts();
+        packet.duration = source.duration();
+        Ok(packet)
+    }
+}
+
 impl Drop for Encoder {
     fn drop(&mut self) {
         unsafe { ffi::vsl_encoder_release(self.ptr) }
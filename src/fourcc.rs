@@ -5,6 +5,7 @@
 #![forbid(unsafe_code)]
 
 use core::{fmt, result::Result};
+use std::error::Error;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(C, packed)]
@@ -27,6 +28,260 @@ impl FourCC {
                 | ((self.0[3] as u32) & 0x000000ff)
         }
     }
+
+    /// Returns the bytes per pixel of packed formats, or per luma sample of
+    /// the planar YUV formats, None for unsupported formats.
+    pub fn bytes_per_pixel(self) -> Option<usize> {
+        match &self.0 {
+            b"GREY" | b"NV12" | b"I420" | b"YU12" => Some(1),
+            b"YUYV" => Some(2),
+            b"RGB3" | b"BGR3" => Some(3),
+            b"RGBA" | b"RGBX" | b"BGRA" | b"BGRX" => Some(4),
+            _ => None,
+        }
+    }
+}
+
+/// The Layout describes where the planes and pixels of a frame of one of the
+/// uncompressed formats are in memory, and converts its pixels to and from
+/// RGB and YUV.
+///
+/// The chroma planes follow the luma plane, NV12 with the interleaved chroma
+/// rows at the luma stride and I420 with each chroma plane at half the luma
+/// stride.  YUV formats are converted as limited range BT.601.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub fourcc: FourCC,
+    pub width: usize,
+    pub height: usize,
+    /// The bytes per row of the packed or luma plane.
+    pub stride: usize,
+    /// The bytes per row of the chroma planes, zero without chroma planes.
+    pub chroma_stride: usize,
+    /// The total size of the planes in bytes.
+    pub size: usize,
+}
+
+impl Layout {
+    /// Creates the layout of the format, the stride is that of the packed rows
+    /// when zero.
+    pub fn new(
+        fourcc: FourCC,
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let bytes_per_pixel = fourcc
+            .bytes_per_pixel()
+            .ok_or_else(|| format!("unsupported format {} for conversion", fourcc))?;
+        let stride = match stride {
+            0 => width * bytes_per_pixel,
+            stride => stride,
+        };
+        let chroma_height = height.div_ceil(2);
+        let (chroma_stride, size) = match &fourcc.0 {
+            b"NV12" => (stride, stride * (height + chroma_height)),
+            b"I420" | b"YU12" => {
+                let chroma_stride = stride.div_ceil(2);
+                (
+                    chroma_stride,
+                    stride * height + 2 * chroma_stride * chroma_height,
+                )
+            }
+            _ => (0, stride * height),
+        };
+        Ok(Layout {
+            fourcc,
+            width,
+            height,
+            stride,
+            chroma_stride,
+            size,
+        })
+    }
+
+    /// Returns an error if the buffer is too small to hold the planes.
+    pub fn check(&self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if data.len() < self.size {
+            return Err(format!(
+                "frame buffer of {} bytes is smaller than {}",
+                data.len(),
+                self.size
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Copies the planes row by row into a frame of the same format and
+    /// resolution whose rows may be padded differently.
+    pub fn copy_to(
+        &self,
+        data: &[u8],
+        target: &Layout,
+        output: &mut [u8],
+    ) -> Result<(), Box<dyn Error>> {
+        if (self.fourcc, self.width, self.height) != (target.fourcc, target.width, target.height) {
+            return Err(format!(
+                "cannot copy a {}x{} {} frame into a {}x{} {} frame",
+                self.width, self.height, self.fourcc, target.width, target.height, target.fourcc
+            )
+            .into());
+        }
+        self.check(data)?;
+        target.check(output)?;
+        for ((offset, stride, rows, len), (target_offset, target_stride, ..)) in
+            self.planes().into_iter().zip(target.planes())
+        {
+            for row in 0..rows {
+                let from = offset + row * stride;
+                let to = target_offset + row * target_stride;
+                output[to..to + len].copy_from_slice(&data[from..from + len]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the offset, stride, number of rows and bytes per row of each
+    /// plane.
+    fn planes(&self) -> Vec<(usize, usize, usize, usize)> {
+        let luma = self.stride * self.height;
+        let chroma_height = self.height.div_ceil(2);
+        let chroma_width = self.width.div_ceil(2);
+        match &self.fourcc.0 {
+            b"NV12" => vec![
+                (0, self.stride, self.height, self.width),
+                (luma, self.chroma_stride, chroma_height, chroma_width * 2),
+            ],
+            b"I420" | b"YU12" => {
+                let plane = self.chroma_stride * chroma_height;
+                vec![
+                    (0, self.stride, self.height, self.width),
+                    (luma, self.chroma_stride, chroma_height, chroma_width),
+                    (
+                        luma + plane,
+                        self.chroma_stride,
+                        chroma_height,
+                        chroma_width,
+                    ),
+                ]
+            }
+            _ => {
+                let len = self.width * self.fourcc.bytes_per_pixel().unwrap_or(0);
+                vec![(0, self.stride, self.height, len)]
+            }
+        }
+    }
+
+    /// Returns the offset of the U (0) or V (1) sample of the pixel in a
+    /// planar frame.
+    fn chroma(&self, x: usize, y: usize, plane: usize) -> usize {
+        let base = self.stride * self.height + y / 2 * self.chroma_stride;
+        match &self.fourcc.0 {
+            b"NV12" => base + x / 2 * 2 + plane,
+            _ => base + plane * self.chroma_stride * self.height.div_ceil(2) + x / 2,
+        }
+    }
+
+    /// Returns the RGB of the pixel, GREY pixels are returned as is.
+    pub fn rgb(&self, data: &[u8], x: usize, y: usize) -> [u8; 3] {
+        let row = &data[y * self.stride..];
+        match &self.fourcc.0 {
+            b"RGB3" => [row[x * 3], row[x * 3 + 1], row[x * 3 + 2]],
+            b"BGR3" => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3]],
+            b"RGBA" | b"RGBX" => [row[x * 4], row[x * 4 + 1], row[x * 4 + 2]],
+            b"BGRA" | b"BGRX" => [row[x * 4 + 2], row[x * 4 + 1], row[x * 4]],
+            b"GREY" => [row[x]; 3],
+            _ => {
+                let [y, u, v] = self.yuv(data, x, y);
+                yuv_to_rgb(y, u, v)
+            }
+        }
+    }
+
+    /// Returns the YUV of the pixel, with neutral chroma for GREY pixels.
+    pub fn yuv(&self, data: &[u8], x: usize, y: usize) -> [u8; 3] {
+        let row = &data[y * self.stride..];
+        match &self.fourcc.0 {
+            b"GREY" => [row[x], 128, 128],
+            b"YUYV" => {
+                let pair = &row[x / 2 * 4..];
+                [row[x * 2], pair[1], pair[3]]
+            }
+            b"NV12" | b"I420" | b"YU12" => [
+                row[x],
+                data[self.chroma(x, y, 0)],
+                data[self.chroma(x, y, 1)],
+            ],
+            _ => {
+                let [r, g, b] = self.rgb(data, x, y);
+                rgb_to_yuv(r, g, b)
+            }
+        }
+    }
+
+    /// Writes the RGB pixel, the chroma of the subsampled formats is that of
+    /// the top left pixel of each block.
+    pub fn set_rgb(&self, data: &mut [u8], x: usize, y: usize, rgb: [u8; 3]) {
+        let [r, g, b] = rgb;
+        let row = &mut data[y * self.stride..];
+        match &self.fourcc.0 {
+            b"RGB3" => row[x * 3..x * 3 + 3].copy_from_slice(&rgb),
+            b"BGR3" => row[x * 3..x * 3 + 3].copy_from_slice(&[b, g, r]),
+            b"RGBA" | b"RGBX" => row[x * 4..x * 4 + 4].copy_from_slice(&[r, g, b, 255]),
+            b"BGRA" | b"BGRX" => row[x * 4..x * 4 + 4].copy_from_slice(&[b, g, r, 255]),
+            _ => self.set_yuv(data, x, y, rgb_to_yuv(r, g, b)),
+        }
+    }
+
+    /// Writes the YUV pixel, the chroma of the subsampled formats is that of
+    /// the top left pixel of each block.  Packed RGB pixels are converted.
+    pub fn set_yuv(&self, data: &mut [u8], x: usize, y: usize, yuv: [u8; 3]) {
+        let row = &mut data[y * self.stride..];
+        match &self.fourcc.0 {
+            b"GREY" => row[x] = yuv[0],
+            b"YUYV" => {
+                row[x * 2] = yuv[0];
+                if x.is_multiple_of(2) {
+                    row[x * 2 + 1] = yuv[1];
+                    if let Some(v) = row.get_mut(x * 2 + 3) {
+                        *v = yuv[2];
+                    }
+                }
+            }
+            b"NV12" | b"I420" | b"YU12" => {
+                row[x] = yuv[0];
+                if x.is_multiple_of(2) && y.is_multiple_of(2) {
+                    data[self.chroma(x, y, 0)] = yuv[1];
+                    data[self.chroma(x, y, 1)] = yuv[2];
+                }
+            }
+            _ => self.set_rgb(data, x, y, yuv_to_rgb(yuv[0], yuv[1], yuv[2])),
+        }
+    }
+}
+
+/// Converts limited range BT.601 YUV to RGB.
+pub fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = (y as i32 - 16).max(0) * 298;
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+    let clamp = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+    [
+        clamp(c + 409 * e),
+        clamp(c - 100 * d - 208 * e),
+        clamp(c + 516 * d),
+    ]
+}
+
+/// Converts RGB to limited range BT.601 YUV.
+pub fn rgb_to_yuv(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    [
+        (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8,
+        (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8,
+        (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8,
+    ]
 }
 
 impl From<&[u8; 4]> for FourCC {
@@ -102,3 +357,54 @@ impl fmt::Debug for FourCC {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() -> Result<(), Box<dyn Error>> {
+        let layout = Layout::new(FourCC(*b"I420"), 4, 3, 6)?;
+        assert_eq!((layout.stride, layout.chroma_stride), (6, 3));
+        assert_eq!(layout.size, 6 * 3 + 2 * 3 * 2);
+        assert!(layout.check(&[0; 29]).is_err());
+        assert_eq!(Layout::new(FourCC(*b"BGRA"), 4, 3, 0)?.size, 48);
+        assert!(Layout::new(FourCC(*b"H264"), 4, 3, 0).is_err());
+
+        for fourcc in [b"NV12", b"I420", b"YUYV", b"RGB3", b"BGRX"] {
+            let layout = Layout::new(FourCC(*fourcc), 4, 2, 0)?;
+            let mut data = vec![0; layout.size];
+            layout.set_rgb(&mut data, 2, 0, [255, 0, 0]);
+            layout.set_rgb(&mut data, 3, 1, [0, 0, 255]);
+            let red = layout.rgb(&data, 2, 0);
+            assert!(red[0] > 250 && red[1] < 5 && red[2] < 5, "{:?}", red);
+            // The chroma of subsampled formats is that of the top left pixel.
+            if layout.chroma_stride > 0 || fourcc == b"YUYV" {
+                assert_eq!(layout.yuv(&data, 3, 0)[1..], layout.yuv(&data, 2, 0)[1..]);
+            }
+        }
+
+        let layout = Layout::new(FourCC(*b"NV12"), 2, 2, 0)?;
+        let data = [16, 235, 235, 16, 128, 128];
+        assert_eq!(layout.rgb(&data, 0, 0), [0, 0, 0]);
+        assert_eq!(layout.rgb(&data, 1, 1), [0, 0, 0]);
+        assert_eq!(layout.rgb(&data, 0, 1), [255, 255, 255]);
+
+        // Padded rows are copied into packed rows.
+        let padded = Layout::new(FourCC(*b"I420"), 3, 3, 4)?;
+        let packed = Layout::new(FourCC(*b"I420"), 3, 3, 0)?;
+        let data: Vec<u8> = (0..padded.size as u8).collect();
+        let mut output = vec![0; packed.size];
+        padded.copy_to(&data, &packed, &mut output)?;
+        for (x, y) in [(0, 0), (2, 0), (1, 1), (2, 2)] {
+            assert_eq!(packed.yuv(&output, x, y), padded.yuv(&data, x, y));
+        }
+        assert!(packed
+            .copy_to(&output[1..], &padded, &mut vec![0; padded.size])
+            .is_err());
+        assert!(packed
+            .copy_to(&output, &layout, &mut vec![0; layout.size])
+            .is_err());
+        Ok(())
+    }
+}
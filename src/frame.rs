@@ -1,7 +1,14 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright 2025 Au-Zone Technologies
 
-use crate::{camera::CameraBuffer, client};
+use crate::{
+    camera::CameraBuffer,
+    client,
+    encoder::VSLRect,
+    fourcc::{FourCC, Layout},
+};
+#[cfg(feature = "ndarray")]
+use ndarray::ShapeBuilder;
 use std::{
     error::Error,
     ffi::{CStr, CString},
@@ -10,6 +17,8 @@ use std::{
     path::Path,
     ptr, slice,
 };
+#[cfg(feature = "image")]
+use std::{fs::File, io::BufWriter};
 use videostream_sys as ffi;
 
 /// The Frame structure handles the frame and underlying framebuffer.  A frame
@@ -152,11 +161,9 @@ impl Frame {
         unsafe { ffi::vsl_frame_size(self.ptr) as i32 } //Needs work
     }
 
-    /*
     pub fn stride(&self) -> i32 {
-        return unsafe { ffi::vsl_frame_stride(self.ptr) as i32};
+        unsafe { ffi::vsl_frame_stride(self.ptr) }
     }
-    */
 
     pub fn handle(&self) -> i32 {
         let handle: std::os::raw::c_int = unsafe { ffi::vsl_frame_handle(self.ptr) };
@@ -225,11 +232,184 @@ impl Frame {
         Ok(())
     }
 
+    /// Copies the source frame into this frame, converting the format and
+    /// rescaling as required.  The optional crop is applied to the source
+    /// before rescaling.  Returns the number of bytes copied.
+    pub fn copy_from(
+        &self,
+        source: &Frame,
+        crop: Option<&VSLRect>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let crop_ptr = crop.map_or(ptr::null(), |crop| &crop.rect as *const ffi::vsl_rect);
+        let ret = unsafe { ffi::vsl_frame_copy(self.ptr, source.ptr, crop_ptr) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+        Ok(ret as usize)
+    }
+
+    /// Returns the layout of the frame's planes for the uncompressed formats.
+    pub fn layout(&self) -> Result<Layout, Box<dyn Error>> {
+        Layout::new(
+            FourCC::from(self.fourcc()),
+            usize::try_from(self.width())?,
+            usize::try_from(self.height())?,
+            usize::try_from(self.stride())?,
+        )
+    }
+
     pub fn get_ptr(&self) -> *mut ffi::VSLFrame {
         self.ptr
     }
 }
 
+#[cfg(feature = "image")]
+impl Frame {
+    /// Converts the frame to an 8-bit RGB image, the frame must be locked if
+    /// received from a client.  Packed RGB and BGR frames with or without
+    /// alpha, GREY, NV12, I420 and YUYV frames are supported, the YUV formats
+    /// being converted as limited range BT.601.
+    pub fn to_rgb_image(&self) -> Result<image::RgbImage, Box<dyn Error>> {
+        let layout = self.layout()?;
+        let data = self.mmap().map_err(|_| "failed to map frame")?;
+        layout.check(data)?;
+
+        let mut image = image::RgbImage::new(layout.width as u32, layout.height as u32);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            pixel.0 = layout.rgb(data, x as usize, y as usize);
+        }
+        Ok(image)
+    }
+
+    /// Allocates a frame holding the image, as GREY for grayscale images,
+    /// RGBA for images with alpha and RGB3 otherwise.
+    pub fn from_image(image: &image::DynamicImage) -> Result<Self, Box<dyn Error>> {
+        let (fourcc, channels, pixels) = match image.color() {
+            image::ColorType::L8 | image::ColorType::L16 => {
+                ("GREY", 1, image.to_luma8().into_raw())
+            }
+            color if color.has_alpha() => ("RGBA", 4, image.to_rgba8().into_raw()),
+            _ => ("RGB3", 3, image.to_rgb8().into_raw()),
+        };
+        let frame = Frame::new(image.width(), image.height(), 0, fourcc)?;
+        frame.alloc(None)?;
+
+        let row = image.width() as usize * channels;
+        let stride = frame.layout()?.stride;
+        let data = frame.mmap_mut().map_err(|_| "failed to map frame")?;
+        for (y, pixels) in pixels.chunks_exact(row).enumerate() {
+            data[y * stride..y * stride + row].copy_from_slice(pixels);
+        }
+        Ok(frame)
+    }
+
+    /// Saves the frame as a PNG image, see [`Frame::to_rgb_image`].
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        self.to_rgb_image()?
+            .save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+
+    /// Saves the frame as a JPEG image of the quality from 1 to 100, see
+    /// [`Frame::to_rgb_image`].
+    pub fn save_jpeg<P: AsRef<Path>>(&self, path: P, quality: u8) -> Result<(), Box<dyn Error>> {
+        let image = self.to_rgb_image()?;
+        let writer = BufWriter::new(File::create(path)?);
+        image::codecs::jpeg::JpegEncoder::new_with_quality(writer, quality).encode_image(&image)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl Frame {
+    /// Returns the channel order of packed RGB formats, as the index of the
+    /// red, green and blue samples, along with the number of channels.
+    fn packed_rgb(fourcc: FourCC) -> Result<([usize; 3], usize), Box<dyn Error>> {
+        match &fourcc.0 {
+            b"RGB3" => Ok(([0, 1, 2], 3)),
+            b"BGR3" => Ok(([2, 1, 0], 3)),
+            b"RGBA" | b"RGBX" => Ok(([0, 1, 2], 4)),
+            b"BGRA" | b"BGRX" => Ok(([2, 1, 0], 4)),
+            _ => Err(format!("{} is not a packed rgb format", fourcc).into()),
+        }
+    }
+
+    /// Returns a zero-copy height, width, channel view of a packed RGB, BGR,
+    /// RGBA or BGRA frame, the channels are in the order of the format.  The
+    /// frame must be locked if received from a client.
+    pub fn as_hwc(&self) -> Result<ndarray::ArrayView3<'_, u8>, Box<dyn Error>> {
+        let (_, channels) = Self::packed_rgb(FourCC::from(self.fourcc()))?;
+        let layout = self.layout()?;
+        let data = self.mmap().map_err(|_| "failed to map frame")?;
+        let shape = (layout.height, layout.width, channels).strides((layout.stride, channels, 1));
+        Ok(ndarray::ArrayView3::from_shape(shape, data)?)
+    }
+
+    /// Returns a zero-copy view of the Y (0), U (1) or V (2) plane of an NV12
+    /// or I420 frame, or of the single plane of a GREY frame.  The chroma
+    /// planes of NV12 frames are strided views of the interleaved samples.
+    pub fn plane(&self, index: usize) -> Result<ndarray::ArrayView2<'_, u8>, Box<dyn Error>> {
+        let fourcc = FourCC::from(self.fourcc());
+        let Layout {
+            width,
+            height,
+            stride,
+            chroma_stride,
+            ..
+        } = self.layout()?;
+        let chroma_width = width.div_ceil(2);
+        let chroma_height = height.div_ceil(2);
+        let (offset, shape, strides) = match (&fourcc.0, index) {
+            (b"NV12" | b"I420" | b"YU12" | b"GREY", 0) => (0, (height, width), (stride, 1)),
+            (b"NV12", 1 | 2) => (
+                stride * height + index - 1,
+                (chroma_height, chroma_width),
+                (chroma_stride, 2),
+            ),
+            (b"I420" | b"YU12", 1 | 2) => (
+                stride * height + (index - 1) * chroma_stride * chroma_height,
+                (chroma_height, chroma_width),
+                (chroma_stride, 1),
+            ),
+            _ => return Err(format!("{} has no plane {}", fourcc, index).into()),
+        };
+        let data = self.mmap().map_err(|_| "failed to map frame")?;
+        let data = data
+            .get(offset..)
+            .ok_or("frame buffer is smaller than its planes")?;
+        Ok(ndarray::ArrayView2::from_shape(
+            shape.strides(strides),
+            data,
+        )?)
+    }
+
+    /// Converts a packed RGB, BGR, RGBA or BGRA frame to a 1x3xHxW tensor of
+    /// RGB samples scaled to 0..1 then normalized with the per-channel mean
+    /// and standard deviation, such as the ImageNet mean [0.485, 0.456,
+    /// 0.406] and std [0.229, 0.224, 0.225].
+    pub fn to_nchw(
+        &self,
+        mean: [f32; 3],
+        std: [f32; 3],
+    ) -> Result<ndarray::Array4<f32>, Box<dyn Error>> {
+        let (order, _) = Self::packed_rgb(FourCC::from(self.fourcc()))?;
+        let hwc = self.as_hwc()?;
+        let (height, width, _) = hwc.dim();
+        let mut tensor = ndarray::Array4::zeros((1, 3, height, width));
+        for (channel, &index) in order.iter().enumerate() {
+            let scale = 1.0 / (255.0 * std[channel]);
+            let offset = mean[channel] / std[channel];
+            tensor
+                .slice_mut(ndarray::s![0, channel, .., ..])
+                .zip_mut_with(&hwc.index_axis(ndarray::Axis(2), index), |out, &sample| {
+                    *out = sample as f32 * scale - offset
+                });
+        }
+        Ok(tensor)
+    }
+}
+
 impl TryFrom<*mut ffi::VSLFrame> for Frame {
     type Error = ();
 
@@ -367,6 +547,88 @@ mod tests {
         };
     }
 
+    #[test]
+    #[cfg(feature = "image")]
+    fn rgb_image() -> Result<(), Box<dyn Error>> {
+        let frame = Frame::new(2, 2, 0, "NV12")?;
+        frame.alloc(None)?;
+        frame.mmap_mut().unwrap()[..6].copy_from_slice(&[16, 235, 235, 16, 128, 128]);
+        let rgb = frame.to_rgb_image()?;
+        assert_eq!(
+            rgb.as_raw(),
+            &[0, 0, 0, 255, 255, 255, 255, 255, 255, 0, 0, 0]
+        );
+
+        let frame = Frame::new(2, 1, 0, "YUYV")?;
+        frame.alloc(None)?;
+        frame.mmap_mut().unwrap()[..4].copy_from_slice(&[81, 90, 145, 240]);
+        let rgb = frame.to_rgb_image()?;
+        assert_eq!(rgb.get_pixel(0, 0).0, [255, 0, 0]);
+        assert!(Frame::new(2, 2, 0, "H264")?.to_rgb_image().is_err());
+
+        let source = image::RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8, y as u8, 7]));
+        let frame = Frame::from_image(&source.clone().into())?;
+        assert_eq!(frame.fourcc(), 0x33424752);
+        assert_eq!(frame.to_rgb_image()?, source);
+
+        let gray = image::GrayImage::from_pixel(3, 2, image::Luma([9]));
+        let frame = Frame::from_image(&gray.into())?;
+        assert_eq!(FourCC::from(frame.fourcc()), FourCC(*b"GREY"));
+
+        let path = std::env::temp_dir().join(format!("videostream-{}.png", std::process::id()));
+        Frame::from_image(&source.clone().into())?.save_png(&path)?;
+        assert_eq!(image::open(&path)?.to_rgb8(), source);
+        fs::remove_file(&path)?;
+
+        let path = path.with_extension("jpg");
+        frame.save_jpeg(&path, 90)?;
+        assert_eq!(image::open(&path)?.to_rgb8().get_pixel(1, 1).0[0], 9);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "ndarray")]
+    fn tensor() -> Result<(), Box<dyn Error>> {
+        let frame = Frame::new(2, 2, 8, "BGR3")?;
+        frame.alloc(None)?;
+        for (i, byte) in frame.mmap_mut().unwrap().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let hwc = frame.as_hwc()?;
+        assert_eq!(hwc.dim(), (2, 2, 3));
+        assert_eq!(hwc[[1, 1, 0]], 11);
+        assert_eq!(hwc.as_ptr(), frame.mmap().unwrap().as_ptr());
+
+        let tensor = frame.to_nchw([0.0; 3], [1.0; 3])?;
+        assert_eq!(tensor.dim(), (1, 3, 2, 2));
+        assert!((tensor[[0, 0, 1, 1]] - 13.0 / 255.0).abs() < 1e-6);
+        assert!((tensor[[0, 2, 0, 1]] - 3.0 / 255.0).abs() < 1e-6);
+        let tensor = frame.to_nchw([0.5; 3], [0.5; 3])?;
+        assert_eq!(tensor[[0, 2, 0, 0]], -1.0);
+        assert!(frame.plane(0).is_err());
+
+        let frame = Frame::new(4, 2, 0, "NV12")?;
+        frame.alloc(None)?;
+        for (i, byte) in frame.mmap_mut().unwrap().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        assert_eq!(frame.plane(0)?.row(1).to_vec(), [4, 5, 6, 7]);
+        assert_eq!(frame.plane(1)?.row(0).to_vec(), [8, 10]);
+        assert_eq!(frame.plane(2)?.row(0).to_vec(), [9, 11]);
+        assert!(frame.plane(3).is_err());
+        assert!(frame.as_hwc().is_err());
+
+        let frame = Frame::new(4, 2, 0, "I420")?;
+        frame.alloc(None)?;
+        for (i, byte) in frame.mmap_mut().unwrap().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        assert_eq!(frame.plane(1)?.row(0).to_vec(), [8, 9]);
+        assert_eq!(frame.plane(2)?.row(0).to_vec(), [10, 11]);
+        Ok(())
+    }
+
     #[test]
     fn fourcc() {}
 
@@ -1,12 +1,12 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright 2025 Au-Zone Technologies
 
-use crate::NullStringError;
+use crate::{frame::Frame, NullStringError};
 use std::{
     error::Error,
     ffi::{CStr, CString},
     io,
-    os::unix::prelude::OsStrExt,
+    os::{fd::RawFd, unix::prelude::OsStrExt},
     path::{Path, PathBuf},
 };
 use videostream_sys as ffi;
@@ -45,11 +45,69 @@ impl Host {
         Ok(PathBuf::from(path_str))
     }
 
-    pub fn poll(&self) {}
+    /// Publishes the frame to the connected clients.  Ownership of the frame
+    /// is transferred to the host which releases it once it expires, the
+    /// expiry is an absolute time as returned by [`crate::timestamp`] while
+    /// the duration, pts and dts are in nanoseconds.  Returns the serial
+    /// assigned to the frame, as seen by clients through [`Frame::serial`].
+    pub fn post(
+        &self,
+        frame: Frame,
+        expires: i64,
+        duration: i64,
+        pts: i64,
+        dts: i64,
+    ) -> Result<i64, Box<dyn Error>> {
+        let ret =
+            unsafe { ffi::vsl_host_post(self.ptr, frame.get_ptr(), expires, duration, pts, dts) };
+        if ret != 0 {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+        let serial = frame.serial();
+        std::mem::forget(frame);
+        Ok(serial)
+    }
 
-    pub fn process(&self) {}
+    /// Waits up to wait milliseconds for activity on the host sockets and
+    /// returns the number of sockets ready to be processed.
+    pub fn poll(&self, wait: i64) -> Result<i32, Box<dyn Error>> {
+        let ret = unsafe { ffi::vsl_host_poll(self.ptr, wait) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+        Ok(ret)
+    }
 
-    pub fn sockets(&self) {}
+    /// Expires old frames and services the first ready connection, accepting
+    /// new clients and handling client messages.
+    pub fn process(&self) -> Result<(), Box<dyn Error>> {
+        let ret = unsafe { ffi::vsl_host_process(self.ptr) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+        Ok(())
+    }
+
+    /// Returns the sockets managed by the host, the first being the socket
+    /// accepting new connections followed by the client sockets.
+    pub fn sockets(&self) -> Result<Vec<RawFd>, Box<dyn Error>> {
+        let mut count: usize = 0;
+        unsafe { ffi::vsl_host_sockets(self.ptr, 0, std::ptr::null_mut(), &mut count) };
+        // Leave headroom for clients connecting between the two calls.
+        let mut sockets = vec![0; count + 8];
+        let ret = unsafe {
+            ffi::vsl_host_sockets(self.ptr, sockets.len(), sockets.as_mut_ptr(), &mut count)
+        };
+        if ret != 0 {
+            let err = io::Error::last_os_error();
+            return Err(Box::new(err));
+        }
+        sockets.truncate(count);
+        Ok(sockets)
+    }
 }
 
 impl Drop for Host {
@@ -0,0 +1,415 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{camera::CameraBuffer, frame::Frame};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+/// Upper bound of the first histogram bucket, 10µs.
+const FIRST_BOUND: f64 = 10_000.0;
+
+/// Number of buckets per doubling of latency, about 19% apart.
+const BUCKETS_PER_OCTAVE: f64 = 4.0;
+
+/// Number of bounded buckets, the last bound is about 168s.
+const BUCKETS: usize = 97;
+
+/// The stages of a frame's path from the camera to a subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// The camera captured the frame.
+    Capture,
+    /// The host posted the frame to its clients.
+    Post,
+    /// A client received the frame.
+    Receive,
+    /// The client released the frame.
+    Release,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Capture, Stage::Post, Stage::Receive, Stage::Release];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Capture => "capture",
+            Stage::Post => "post",
+            Stage::Receive => "receive",
+            Stage::Release => "release",
+        }
+    }
+}
+
+/// The Histogram records latencies in nanoseconds into exponential buckets
+/// from 10µs to almost three minutes, with larger values counted in a final
+/// overflow bucket.  Percentiles are interpolated within their bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: i64,
+    min: i64,
+    max: i64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS + 1],
+            count: 0,
+            sum: 0,
+            min: i64::MAX,
+            max: i64::MIN,
+        }
+    }
+
+    /// Returns the upper bound in nanoseconds of the bucket, or None for the
+    /// overflow bucket.
+    pub fn bound(bucket: usize) -> Option<i64> {
+        if bucket >= BUCKETS {
+            return None;
+        }
+        Some((FIRST_BOUND * (bucket as f64 / BUCKETS_PER_OCTAVE).exp2()).round() as i64)
+    }
+
+    fn bucket(value: i64) -> usize {
+        let estimate = if value as f64 <= FIRST_BOUND {
+            0
+        } else {
+            ((value as f64 / FIRST_BOUND).log2() * BUCKETS_PER_OCTAVE).ceil() as usize
+        };
+        // Correct any rounding of the estimate against the exact bounds.
+        let mut bucket = estimate.min(BUCKETS);
+        while bucket > 0 && Self::bound(bucket - 1).is_some_and(|bound| value <= bound) {
+            bucket -= 1;
+        }
+        while Self::bound(bucket).is_some_and(|bound| value > bound) {
+            bucket += 1;
+        }
+        bucket
+    }
+
+    /// Records the latency in nanoseconds, negative values are counted as
+    /// zero.
+    pub fn record(&mut self, value: i64) {
+        let value = value.max(0);
+        self.counts[Self::bucket(value)] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the sum in nanoseconds of the recorded latencies.
+    pub fn sum(&self) -> i64 {
+        self.sum
+    }
+
+    pub fn min(&self) -> Option<i64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<i64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<i64> {
+        (self.count > 0).then(|| self.sum / self.count as i64)
+    }
+
+    /// Returns the latency below which the fraction of recorded latencies
+    /// falls, for example 0.99 for the 99th percentile.
+    pub fn percentile(&self, fraction: f64) -> Option<i64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (fraction.clamp(0.0, 1.0) * self.count as f64)
+            .ceil()
+            .max(1.0);
+        let mut below = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            if (below + count) as f64 >= rank {
+                let lower = bucket
+                    .checked_sub(1)
+                    .and_then(Self::bound)
+                    .unwrap_or(0)
+                    .max(self.min);
+                let upper = Self::bound(bucket).unwrap_or(self.max).min(self.max);
+                let position = (rank - below as f64) / count as f64;
+                return Some(lower + ((upper - lower) as f64 * position).round() as i64);
+            }
+            below += count;
+        }
+        Some(self.max)
+    }
+
+    pub fn p50(&self) -> Option<i64> {
+        self.percentile(0.5)
+    }
+
+    pub fn p99(&self) -> Option<i64> {
+        self.percentile(0.99)
+    }
+
+    /// Returns the cumulative count of each bucket along with its upper
+    /// bound, None for the final overflow bucket, as used by Prometheus
+    /// histograms.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<i64>, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .scan(0, |total, (bucket, count)| {
+                *total += count;
+                Some((Self::bound(bucket), *total))
+            })
+    }
+
+    /// Adds the latencies recorded by the other histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+struct Inner {
+    frames: HashMap<i64, [Option<i64>; 4]>,
+    order: VecDeque<i64>,
+    stages: [Histogram; 4],
+    total: Histogram,
+}
+
+/// The LatencyTracer follows frames by their serial through the stages from
+/// capture to release, recording the time spent reaching each stage from the
+/// previous one into a histogram per stage.
+///
+/// Timestamps are in nanoseconds of the monotonic clock returned by
+/// [`crate::timestamp`], which is shared between the host and client
+/// processes.  A host records the capture and post stages using the serial
+/// returned by [`crate::host::Host::post`] while clients record the receive
+/// and release stages, the post time being carried by the frame.  The tracer
+/// can be shared between threads.
+///
+/// With the `tracing` feature an info event is emitted for each released
+/// frame, carrying the latency of every stage.
+pub struct LatencyTracer {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl Default for LatencyTracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyTracer {
+    /// Creates a tracer following up to 256 frames at a time.
+    pub fn new() -> Self {
+        LatencyTracer {
+            capacity: 256,
+            inner: Mutex::new(Inner {
+                frames: HashMap::new(),
+                order: VecDeque::new(),
+                stages: Default::default(),
+                total: Histogram::new(),
+            }),
+        }
+    }
+
+    /// Sets the number of frames followed at a time, the oldest frames are
+    /// forgotten once exceeded such as frames which are never released.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Records the time the frame reached the stage.
+    pub fn record(&self, serial: i64, stage: Stage, timestamp: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        if !inner.frames.contains_key(&serial) {
+            if inner.order.len() >= self.capacity {
+                if let Some(oldest) = inner.order.pop_front() {
+                    inner.frames.remove(&oldest);
+                }
+            }
+            inner.order.push_back(serial);
+        }
+        let times = inner.frames.entry(serial).or_default();
+        let index = stage as usize;
+        times[index] = Some(timestamp);
+
+        if let Some(previous) = times[..index].iter().rev().find_map(|time| *time) {
+            inner.stages[index].record(timestamp - previous);
+        }
+
+        if stage == Stage::Release {
+            let times = inner.frames.remove(&serial).unwrap_or_default();
+            inner.order.retain(|other| *other != serial);
+            if let Some(first) = times[..index].iter().find_map(|time| *time) {
+                inner.total.record(timestamp - first);
+            }
+            #[cfg(feature = "tracing")]
+            emit_event(serial, &times);
+        }
+    }
+
+    /// Records the capture time of the camera buffer for the frame.
+    pub fn captured(&self, serial: i64, buffer: &CameraBuffer) {
+        let timestamp = buffer.timestamp();
+        let nanos = timestamp.seconds() * 1_000_000_000 + timestamp.subsec(9) as i64;
+        self.record(serial, Stage::Capture, nanos);
+    }
+
+    /// Records the frame as posted now.
+    pub fn posted(&self, serial: i64) {
+        self.record(serial, Stage::Post, crate::timestamp());
+    }
+
+    /// Records the frame as received now, along with the time it was posted.
+    pub fn received(&self, frame: &Frame) {
+        let serial = frame.serial();
+        self.record(serial, Stage::Post, frame.timestamp());
+        self.record(serial, Stage::Receive, crate::timestamp());
+    }
+
+    /// Records the frame as released now.
+    pub fn released(&self, frame: &Frame) {
+        self.record(frame.serial(), Stage::Release, crate::timestamp());
+    }
+
+    /// Returns the latencies of reaching the stage from the previous stage,
+    /// the capture stage has no latency of its own.
+    pub fn histogram(&self, stage: Stage) -> Histogram {
+        self.inner.lock().unwrap().stages[stage as usize].clone()
+    }
+
+    /// Returns the latencies from the first recorded stage to the release of
+    /// the frames.
+    pub fn total(&self) -> Histogram {
+        self.inner.lock().unwrap().total.clone()
+    }
+
+    /// Clears the histograms and the frames being followed.
+    pub fn reset(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.frames.clear();
+        inner.order.clear();
+        inner.stages = Default::default();
+        inner.total = Histogram::new();
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn emit_event(serial: i64, times: &[Option<i64>; 4]) {
+    let latency = |stage: Stage| {
+        let index = stage as usize;
+        let previous = times[..index].iter().rev().find_map(|time| *time)?;
+        Some(times[index]? - previous)
+    };
+    tracing::info!(
+        serial,
+        post_ns = latency(Stage::Post),
+        receive_ns = latency(Stage::Receive),
+        release_ns = latency(Stage::Release),
+        "frame released"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.p50(), None);
+
+        for bucket in 0..BUCKETS {
+            let bound = Histogram::bound(bucket).unwrap();
+            assert_eq!(Histogram::bucket(bound), bucket);
+            assert_eq!(Histogram::bucket(bound + 1), bucket + 1);
+        }
+
+        // 1ms to 100ms in 1ms steps.
+        for i in 1..=100 {
+            histogram.record(i * 1_000_000);
+        }
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.min(), Some(1_000_000));
+        assert_eq!(histogram.max(), Some(100_000_000));
+        assert_eq!(histogram.mean(), Some(50_500_000));
+
+        // Interpolation within buckets keeps percentiles within 19%.
+        let p50 = histogram.p50().unwrap() as f64;
+        assert!((p50 / 50e6 - 1.0).abs() < 0.19, "p50 {}", p50);
+        let p99 = histogram.p99().unwrap() as f64;
+        assert!((p99 / 99e6 - 1.0).abs() < 0.19, "p99 {}", p99);
+        assert_eq!(histogram.percentile(1.0), Some(100_000_000));
+
+        let buckets: Vec<_> = histogram.buckets().collect();
+        assert_eq!(buckets.len(), BUCKETS + 1);
+        assert_eq!(buckets.last(), Some(&(None, 100)));
+        assert!(buckets.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        let mut merged = Histogram::new();
+        merged.merge(&histogram);
+        merged.merge(&histogram);
+        assert_eq!(merged.count(), 200);
+        assert_eq!(merged.p50(), histogram.p50());
+    }
+
+    #[test]
+    fn test_tracer() {
+        let tracer = LatencyTracer::new().with_capacity(4);
+        for serial in 0..10 {
+            let start = serial * 33_000_000;
+            tracer.record(serial, Stage::Capture, start);
+            tracer.record(serial, Stage::Post, start + 2_000_000);
+            tracer.record(serial, Stage::Receive, start + 2_100_000);
+            tracer.record(serial, Stage::Release, start + 12_100_000);
+        }
+        // A frame missing its post stage measures from its capture.
+        tracer.record(10, Stage::Capture, 0);
+        tracer.record(10, Stage::Receive, 3_000_000);
+
+        assert_eq!(tracer.histogram(Stage::Capture).count(), 0);
+        assert_eq!(tracer.histogram(Stage::Post).count(), 10);
+        assert_eq!(tracer.histogram(Stage::Post).max(), Some(2_000_000));
+        assert_eq!(tracer.histogram(Stage::Receive).count(), 11);
+        assert_eq!(tracer.histogram(Stage::Receive).max(), Some(3_000_000));
+        assert_eq!(tracer.histogram(Stage::Release).mean(), Some(10_000_000));
+        assert_eq!(tracer.total().count(), 10);
+        assert_eq!(tracer.total().min(), Some(12_100_000));
+
+        // Frames beyond the capacity are forgotten.
+        for serial in 20..30 {
+            tracer.record(serial, Stage::Post, 0);
+        }
+        tracer.record(10, Stage::Release, 4_000_000);
+        assert_eq!(tracer.histogram(Stage::Release).count(), 10);
+
+        tracer.reset();
+        assert_eq!(tracer.total().count(), 0);
+    }
+}
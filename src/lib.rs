@@ -68,12 +68,59 @@ pub mod encoder;
 /// The encoder module provides accelerated video decoding from h.264 and h.265
 pub mod decoder;
 
+/// The software module provides H.264 encoding and decoding with the openh264
+/// library, implementing the encoder and decoder traits on hosts without a
+/// hardware codec.
+#[cfg(feature = "software")]
+pub mod software;
+
+/// The mp4 module provides fragmented MP4 recording of encoded video.
+pub mod mp4;
+
+/// The mkv module provides Matroska recording of encoded video.
+pub mod mkv;
+
+/// The recorder module provides continuous recording to rotating segments.
+pub mod recorder;
+
+/// The demux module provides reading of recorded MP4 and Matroska files and
+/// their playback through a host.
+pub mod demux;
+
+/// The rtp module provides RTP packetization of encoded video.
+pub mod rtp;
+
+/// The rtsp module provides an embedded RTSP server for encoded video.
+#[cfg(feature = "rtsp")]
+pub mod rtsp;
+
+/// The latency module provides per-frame latency tracing from capture to
+/// release.
+pub mod latency;
+
+/// The metrics module provides Prometheus and OpenMetrics statistics.
+pub mod metrics;
+
+/// The y4m module provides reading and writing of frames as YUV4MPEG2 streams.
+pub mod y4m;
+
 /// The camera module provides camera capture capabilities.
 pub mod camera;
 
+/// The source module provides a common interface to camera, synthetic and
+/// file frame sources.
+pub mod source;
+
+/// The pipeline module provides publishing of camera frames through a host.
+pub mod pipeline;
+
 /// The fourcc module provides portable handling of fourcc codes.
 pub mod fourcc;
 
+mod listener;
+mod nal;
+mod v4l2;
+
 #[derive(Debug)]
 struct NullStringError;
 
@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use std::{
+    error::Error,
+    io,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the accept thread checks for new connections and for shutdown.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The Listener accepts TCP connections on a background thread and handles
+/// each connection on its own thread, so a slow client never holds up the
+/// others.  Connections are accepted without blocking so shutting down only
+/// waits for the accept thread, connections still being handled are left to
+/// finish on their own.
+pub(crate) struct Listener {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Listener {
+    /// Binds the address and calls the handler with each accepted connection.
+    pub fn bind<A, F>(address: A, handler: F) -> Result<Self, Box<dyn Error>>
+    where
+        A: ToSocketAddrs,
+        F: Fn(TcpStream) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handler = Arc::new(handler);
+        let thread = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if stream.set_nonblocking(false).is_err() {
+                            continue;
+                        }
+                        let handler = handler.clone();
+                        thread::spawn(move || handler(stream));
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_INTERVAL)
+                    }
+                    Err(_) => (),
+                }
+            }
+        });
+
+        Ok(Listener {
+            address,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops accepting connections, returning once the accept thread exits.
+    pub fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, sync::mpsc, time::Instant};
+
+    #[test]
+    fn test_shutdown() -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        let mut listener = Listener::bind("127.0.0.1:0", move |stream: TcpStream| {
+            let _ = tx.lock().unwrap().send(stream.peer_addr().is_ok());
+            // A client which never finishes does not hold up the shutdown.
+            thread::sleep(Duration::from_secs(5));
+        })?;
+
+        let mut idle = TcpStream::connect(listener.local_addr())?;
+        let mut client = TcpStream::connect(listener.local_addr())?;
+        idle.write_all(b"GET")?;
+        client.write_all(b"GET")?;
+        for _ in 0..2 {
+            assert!(rx.recv_timeout(Duration::from_secs(1))?);
+        }
+
+        let start = Instant::now();
+        listener.shutdown();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(TcpStream::connect(listener.local_addr()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,466 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    camera::CameraReader,
+    encoder::EncodedPacket,
+    frame::Frame,
+    host::Host,
+    latency::{Histogram, LatencyTracer, Stage},
+    listener::Listener,
+};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Content type of the OpenMetrics text exposition format.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Measures the rate of events over a sliding window.
+struct Rate {
+    window: Duration,
+    events: VecDeque<(Instant, u64)>,
+}
+
+impl Rate {
+    fn new(window: Duration) -> Self {
+        Rate {
+            window,
+            events: VecDeque::new(),
+        }
+    }
+
+    fn add(&mut self, now: Instant, value: u64) {
+        self.events.push_back((now, value));
+        self.expire(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((time, _)) = self.events.front() {
+            if now.duration_since(*time) <= self.window {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+
+    /// Returns the rate per second over the window.
+    fn per_second(&mut self, now: Instant) -> f64 {
+        self.expire(now);
+        let total: u64 = self.events.iter().map(|(_, value)| value).sum();
+        total as f64 / self.window.as_secs_f64()
+    }
+}
+
+struct Inner {
+    host_frames: u64,
+    host_rate: Rate,
+    host_clients: Option<usize>,
+    client_frames: u64,
+    client_dropped: u64,
+    client_rate: Rate,
+    client_serial: Option<i64>,
+    frame_age: Histogram,
+    encoder_packets: u64,
+    encoder_keyframes: u64,
+    encoder_bytes: u64,
+    encoder_rate: Rate,
+    camera_frames: u64,
+    camera_queued: Option<i32>,
+}
+
+/// The Metrics structure collects statistics from hosts, clients, encoders
+/// and cameras and renders them in the Prometheus and OpenMetrics text
+/// exposition format, either directly or through a [`MetricsServer`].
+///
+/// Counters are exposed for the frames and bytes seen, allowing rates to be
+/// computed by the monitoring system, along with gauges of the rates measured
+/// over a sliding window of five seconds.  The metrics can be shared between
+/// threads.
+pub struct Metrics {
+    tracer: Option<Arc<LatencyTracer>>,
+    inner: Mutex<Inner>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::with_window(Duration::from_secs(5))
+    }
+
+    /// Creates the metrics measuring rates over the window.
+    pub fn with_window(window: Duration) -> Self {
+        Metrics {
+            tracer: None,
+            inner: Mutex::new(Inner {
+                host_frames: 0,
+                host_rate: Rate::new(window),
+                host_clients: None,
+                client_frames: 0,
+                client_dropped: 0,
+                client_rate: Rate::new(window),
+                client_serial: None,
+                frame_age: Histogram::new(),
+                encoder_packets: 0,
+                encoder_keyframes: 0,
+                encoder_bytes: 0,
+                encoder_rate: Rate::new(window),
+                camera_frames: 0,
+                camera_queued: None,
+            }),
+        }
+    }
+
+    /// Includes the stage latencies of the tracer in the rendered metrics.
+    pub fn with_tracer(mut self, tracer: Arc<LatencyTracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Records a frame posted by the host along with its connected clients.
+    pub fn record_post(&self, host: &Host) {
+        let clients = host
+            .sockets()
+            .ok()
+            .map(|sockets| sockets.len().saturating_sub(1));
+        let mut inner = self.inner.lock().unwrap();
+        inner.host_frames += 1;
+        inner.host_rate.add(Instant::now(), 1);
+        if clients.is_some() {
+            inner.host_clients = clients;
+        }
+    }
+
+    /// Records a frame received by a client, frames skipped since the
+    /// previous frame are counted as dropped.
+    pub fn record_receive(&self, frame: &Frame) {
+        let age = crate::timestamp() - frame.timestamp();
+        let serial = frame.serial();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(previous) = inner.client_serial {
+            if serial > previous + 1 {
+                inner.client_dropped += (serial - previous - 1) as u64;
+            }
+        }
+        inner.client_serial = Some(serial);
+        inner.client_frames += 1;
+        inner.client_rate.add(Instant::now(), 1);
+        inner.frame_age.record(age);
+    }
+
+    /// Records a frame the client received but could not use, such as one
+    /// which expired before it could be locked.
+    pub fn record_drop(&self) {
+        self.inner.lock().unwrap().client_dropped += 1;
+    }
+
+    /// Records a packet produced by an encoder.
+    pub fn record_encode(&self, packet: &EncodedPacket) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.encoder_packets += 1;
+        inner.encoder_keyframes += packet.keyframe as u64;
+        inner.encoder_bytes += packet.data.len() as u64;
+        inner
+            .encoder_rate
+            .add(Instant::now(), packet.data.len() as u64);
+    }
+
+    /// Records a frame captured by the camera along with the number of
+    /// buffers remaining queued for capture.
+    pub fn record_capture(&self, camera: &CameraReader) {
+        let queued = camera.queued_buffers();
+        let mut inner = self.inner.lock().unwrap();
+        inner.camera_frames += 1;
+        inner.camera_queued = Some(queued);
+    }
+
+    /// Renders the metrics in the OpenMetrics text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+
+        counter(
+            &mut out,
+            "videostream_host_frames",
+            "Frames posted by the host.",
+            inner.host_frames,
+        );
+        let rate = inner.host_rate.per_second(now);
+        gauge(
+            &mut out,
+            "videostream_host_frame_rate",
+            "Frames posted per second.",
+            Some(rate),
+        );
+        gauge(
+            &mut out,
+            "videostream_host_clients",
+            "Clients connected to the host.",
+            inner.host_clients.map(|clients| clients as f64),
+        );
+
+        counter(
+            &mut out,
+            "videostream_client_frames",
+            "Frames received by the client.",
+            inner.client_frames,
+        );
+        counter(
+            &mut out,
+            "videostream_client_dropped_frames",
+            "Frames missed or dropped by the client.",
+            inner.client_dropped,
+        );
+        let rate = inner.client_rate.per_second(now);
+        gauge(
+            &mut out,
+            "videostream_client_frame_rate",
+            "Frames received per second.",
+            Some(rate),
+        );
+        histogram(
+            &mut out,
+            "videostream_client_frame_age_seconds",
+            "Age of the frames when received by the client.",
+            &[("", &inner.frame_age)],
+        );
+
+        counter(
+            &mut out,
+            "videostream_encoder_packets",
+            "Packets produced by the encoder.",
+            inner.encoder_packets,
+        );
+        counter(
+            &mut out,
+            "videostream_encoder_keyframes",
+            "Keyframes produced by the encoder.",
+            inner.encoder_keyframes,
+        );
+        counter(
+            &mut out,
+            "videostream_encoder_bytes",
+            "Bytes produced by the encoder.",
+            inner.encoder_bytes,
+        );
+        let rate = inner.encoder_rate.per_second(now) * 8.0;
+        gauge(
+            &mut out,
+            "videostream_encoder_bitrate",
+            "Encoded bits per second.",
+            Some(rate),
+        );
+
+        counter(
+            &mut out,
+            "videostream_camera_frames",
+            "Frames captured by the camera.",
+            inner.camera_frames,
+        );
+        gauge(
+            &mut out,
+            "videostream_camera_queued_buffers",
+            "Buffers queued to the camera for capture.",
+            inner.camera_queued.map(|queued| queued as f64),
+        );
+        drop(inner);
+
+        if let Some(tracer) = &self.tracer {
+            let stages: Vec<_> = Stage::ALL[1..]
+                .iter()
+                .map(|stage| (stage.name(), tracer.histogram(*stage)))
+                .collect();
+            let labeled: Vec<_> = stages
+                .iter()
+                .map(|(name, histogram)| (*name, histogram))
+                .collect();
+            histogram(
+                &mut out,
+                "videostream_latency_seconds",
+                "Latency of the frames reaching each stage from the previous stage.",
+                &labeled,
+            );
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "{}_total {}", name, value);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: Option<f64>) {
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    if let Some(value) = value {
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+}
+
+/// Writes the histograms in seconds, each labeled by stage unless the label
+/// is empty.
+fn histogram(out: &mut String, name: &str, help: &str, histograms: &[(&str, &Histogram)]) {
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    let _ = writeln!(out, "# UNIT {} seconds", name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    for (stage, histogram) in histograms {
+        let label = if stage.is_empty() {
+            String::new()
+        } else {
+            format!("stage=\"{}\",", stage)
+        };
+        for (bound, count) in histogram.buckets() {
+            let le = match bound {
+                Some(bound) => format!("{}", bound as f64 / 1e9),
+                None => "+Inf".to_owned(),
+            };
+            let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, label, le, count);
+        }
+        let label = label.trim_end_matches(',');
+        let label = if label.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", label)
+        };
+        let _ = writeln!(
+            out,
+            "{}_sum{} {}",
+            name,
+            label,
+            histogram.sum() as f64 / 1e9
+        );
+        let _ = writeln!(out, "{}_count{} {}", name, label, histogram.count());
+    }
+}
+
+/// The MetricsServer serves the rendered metrics over HTTP at `/metrics` for
+/// scraping by Prometheus, each connection being handled on its own
+/// background thread.
+pub struct MetricsServer {
+    listener: Listener,
+}
+
+impl MetricsServer {
+    pub fn bind<A: ToSocketAddrs>(
+        address: A,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let listener = Listener::bind(address, move |stream| {
+            let _ = serve(stream, &metrics);
+        })?;
+        Ok(MetricsServer { listener })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr()
+    }
+}
+
+fn serve(stream: TcpStream, metrics: &Metrics) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let (status, content_type, body) = if method != "GET" {
+        ("405 Method Not Allowed", "text/plain", String::new())
+    } else if path == "/metrics" || path.starts_with("/metrics?") {
+        ("200 OK", CONTENT_TYPE, metrics.render())
+    } else {
+        ("404 Not Found", "text/plain", String::new())
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_render() {
+        let tracer = Arc::new(LatencyTracer::new());
+        tracer.record(1, Stage::Post, 0);
+        tracer.record(1, Stage::Receive, 1_000_000);
+        let metrics = Metrics::new().with_tracer(tracer);
+        for i in 0..10 {
+            metrics.record_encode(&EncodedPacket {
+                data: vec![0; 1000],
+                keyframe: i == 0,
+                ..Default::default()
+            });
+        }
+        metrics.record_drop();
+
+        let text = metrics.render();
+        assert!(text.ends_with("# EOF\n"));
+        assert!(text.contains("videostream_encoder_packets_total 10\n"));
+        assert!(text.contains("videostream_encoder_keyframes_total 1\n"));
+        assert!(text.contains("videostream_encoder_bytes_total 10000\n"));
+        // 10000 bytes over the five second window.
+        assert!(text.contains("videostream_encoder_bitrate 16000\n"));
+        assert!(text.contains("videostream_client_dropped_frames_total 1\n"));
+        assert!(text.contains("videostream_client_frame_age_seconds_count 0\n"));
+        assert!(
+            text.contains("videostream_latency_seconds_bucket{stage=\"receive\",le=\"+Inf\"} 1\n")
+        );
+        assert!(text.contains("videostream_latency_seconds_sum{stage=\"receive\"} 0.001\n"));
+        assert!(!text.contains("\nvideostream_host_clients "));
+    }
+
+    #[test]
+    fn test_scrape() -> Result<(), Box<dyn Error>> {
+        let metrics = Arc::new(Metrics::new());
+        let server = MetricsServer::bind("127.0.0.1:0", metrics.clone())?;
+        metrics.record_encode(&EncodedPacket::default());
+
+        let scrape = |path: &str| -> Result<String, Box<dyn Error>> {
+            let mut stream = TcpStream::connect(server.local_addr())?;
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        let response = scrape("/metrics")?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.contains("videostream_encoder_packets_total 1\n"));
+        assert!(scrape("/")?.starts_with("HTTP/1.1 404"));
+        Ok(())
+    }
+}
@@ -0,0 +1,614 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    encoder::EncodedPacket,
+    fourcc::FourCC,
+    mp4::{avc_config, check_codec, hevc_config, is_hevc, resolution, AccessUnit},
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    ops::RangeInclusive,
+    path::Path,
+    time::Duration,
+};
+
+pub(crate) const EBML_HEADER: u32 = 0x1a45_dfa3;
+pub(crate) const EBML_VERSION: u32 = 0x4286;
+pub(crate) const EBML_READ_VERSION: u32 = 0x42f7;
+pub(crate) const EBML_MAX_ID_LENGTH: u32 = 0x42f2;
+pub(crate) const EBML_MAX_SIZE_LENGTH: u32 = 0x42f3;
+pub(crate) const EBML_DOC_TYPE: u32 = 0x4282;
+pub(crate) const EBML_DOC_TYPE_VERSION: u32 = 0x4287;
+pub(crate) const EBML_DOC_TYPE_READ_VERSION: u32 = 0x4285;
+pub(crate) const EBML_VOID: u32 = 0xec;
+
+pub(crate) const MKV_SEGMENT: u32 = 0x1853_8067;
+pub(crate) const MKV_SEEK_HEAD: u32 = 0x114d_9b74;
+pub(crate) const MKV_SEEK: u32 = 0x4dbb;
+pub(crate) const MKV_SEEK_ID: u32 = 0x53ab;
+pub(crate) const MKV_SEEK_POSITION: u32 = 0x53ac;
+pub(crate) const MKV_INFO: u32 = 0x1549_a966;
+pub(crate) const MKV_TIMECODE_SCALE: u32 = 0x2a_d7b1;
+pub(crate) const MKV_DURATION: u32 = 0x4489;
+pub(crate) const MKV_MUXING_APP: u32 = 0x4d80;
+pub(crate) const MKV_WRITING_APP: u32 = 0x5741;
+pub(crate) const MKV_TRACKS: u32 = 0x1654_ae6b;
+pub(crate) const MKV_TRACK_ENTRY: u32 = 0xae;
+pub(crate) const MKV_TRACK_NUMBER: u32 = 0xd7;
+pub(crate) const MKV_TRACK_UID: u32 = 0x73c5;
+pub(crate) const MKV_TRACK_TYPE: u32 = 0x83;
+pub(crate) const MKV_FLAG_LACING: u32 = 0x9c;
+pub(crate) const MKV_NAME: u32 = 0x536e;
+pub(crate) const MKV_CODEC_ID: u32 = 0x86;
+pub(crate) const MKV_CODEC_PRIVATE: u32 = 0x63a2;
+pub(crate) const MKV_DEFAULT_DURATION: u32 = 0x23_e383;
+pub(crate) const MKV_VIDEO: u32 = 0xe0;
+pub(crate) const MKV_PIXEL_WIDTH: u32 = 0xb0;
+pub(crate) const MKV_PIXEL_HEIGHT: u32 = 0xba;
+pub(crate) const MKV_CLUSTER: u32 = 0x1f43_b675;
+pub(crate) const MKV_TIMECODE: u32 = 0xe7;
+pub(crate) const MKV_SIMPLE_BLOCK: u32 = 0xa3;
+pub(crate) const MKV_BLOCK_GROUP: u32 = 0xa0;
+pub(crate) const MKV_BLOCK: u32 = 0xa1;
+pub(crate) const MKV_BLOCK_DURATION: u32 = 0x9b;
+pub(crate) const MKV_REFERENCE_BLOCK: u32 = 0xfb;
+pub(crate) const MKV_CUES: u32 = 0x1c53_bb6b;
+pub(crate) const MKV_CUE_POINT: u32 = 0xbb;
+pub(crate) const MKV_CUE_TIME: u32 = 0xb3;
+pub(crate) const MKV_CUE_TRACK_POSITIONS: u32 = 0xb7;
+pub(crate) const MKV_CUE_TRACK: u32 = 0xf7;
+pub(crate) const MKV_CUE_CLUSTER_POSITION: u32 = 0xf1;
+pub(crate) const MKV_CHAPTERS: u32 = 0x1043_a770;
+pub(crate) const MKV_EDITION_ENTRY: u32 = 0x45b9;
+pub(crate) const MKV_CHAPTER_ATOM: u32 = 0xb6;
+pub(crate) const MKV_CHAPTER_UID: u32 = 0x73c4;
+pub(crate) const MKV_CHAPTER_TIME_START: u32 = 0x91;
+pub(crate) const MKV_CHAPTER_DISPLAY: u32 = 0x80;
+pub(crate) const MKV_CHAP_STRING: u32 = 0x85;
+
+pub(crate) const MKV_CODEC_H264: &str = "V_MPEG4/ISO/AVC";
+pub(crate) const MKV_CODEC_HEVC: &str = "V_MPEGH/ISO/HEVC";
+pub(crate) const MKV_CODEC_TEXT: &str = "S_TEXT/UTF8";
+
+const VIDEO_TRACK: u64 = 1;
+const METADATA_TRACK: u64 = 2;
+
+/// Nanoseconds per timecode tick, the Matroska default of 1ms.
+const TIMECODE_SCALE: i64 = 1_000_000;
+
+/// Range of the block timecodes relative to their cluster.
+const BLOCK_OFFSET: RangeInclusive<i64> = i16::MIN as i64..=i16::MAX as i64;
+
+/// Space reserved at the start of the segment for the seek head, which is
+/// only written once the positions of the cues and chapters are known.
+const SEEK_HEAD_RESERVED: usize = 128;
+
+/// The unknown size marker used for the segment until it is finished.
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+
+fn id_bytes(id: u32) -> Vec<u8> {
+    id.to_be_bytes()[id.leading_zeros() as usize / 8..].to_vec()
+}
+
+/// Encodes the element size as an EBML variable length integer.
+fn size_bytes(size: u64, out: &mut Vec<u8>) {
+    let len = (1..8).find(|len| size < (1 << (7 * len)) - 1).unwrap_or(8);
+    let value = size | (1 << (7 * len));
+    out.extend_from_slice(&value.to_be_bytes()[8 - len..]);
+}
+
+/// Encodes the element with its ID and size.
+pub(crate) fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 12);
+    out.extend(id_bytes(id));
+    size_bytes(payload.len() as u64, &mut out);
+    out.extend_from_slice(payload);
+    out
+}
+
+pub(crate) fn uint_element(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = (value.leading_zeros() as usize / 8).min(7);
+    element(id, &bytes[skip..])
+}
+
+/// Encodes an unsigned integer using the full 8 bytes so the size of the
+/// element does not depend on the value.
+fn fixed_uint_element(id: u32, value: u64) -> Vec<u8> {
+    element(id, &value.to_be_bytes())
+}
+
+fn float_element(id: u32, value: f64) -> Vec<u8> {
+    element(id, &value.to_be_bytes())
+}
+
+/// Encodes a void element of exactly the given size, which must be at least
+/// nine bytes.
+fn void_element(size: usize) -> Vec<u8> {
+    // The size is encoded in 8 bytes so the element length is independent of
+    // the value.
+    let mut out = vec![EBML_VOID as u8];
+    let payload = size - 9;
+    out.extend_from_slice(&((payload as u64) | (1 << 56)).to_be_bytes());
+    out.resize(size, 0);
+    out
+}
+
+/// The MkvWriter records the encoded video to a Matroska file.
+///
+/// The segment is written with an unknown size and each cluster is written
+/// and synced to storage as a whole, so an interrupted recording remains
+/// playable up to the last complete cluster.  Clusters start on a keyframe
+/// once the cluster duration is reached.  Calling [`MkvWriter::finish`]
+/// writes the cues used for seeking and the chapters, then updates the
+/// segment size, duration and seek head.
+///
+/// Packets are expected in Annex-B format as produced by the encoders, blocks
+/// are timestamped with the presentation timestamp of the packets which when
+/// created with [`EncodedPacket::from_frame`] is the pts of the frame.  The
+/// header is written once the first keyframe is received, packets and
+/// metadata preceding it are dropped.
+///
+/// An optional metadata track carries per-frame JSON documents, such as
+/// detection results, as UTF-8 text blocks timestamped alongside the video.
+pub struct MkvWriter<W: Write + Seek = File> {
+    writer: W,
+    sync: Option<File>,
+    codec: FourCC,
+    metadata: bool,
+    cluster_duration: i64,
+    segment_start: u64,
+    duration_position: u64,
+    tracks_position: u64,
+    written: u64,
+    initialized: bool,
+    finished: bool,
+    base: Option<i64>,
+    cluster: Vec<u8>,
+    cluster_time: i64,
+    cluster_keyframe: bool,
+    end_time: i64,
+    cues: Vec<(i64, u64)>,
+    chapters: Vec<(i64, String)>,
+}
+
+impl MkvWriter<File> {
+    /// Creates the file at the path and records the encoded video of the
+    /// codec, H264 or HEVC, into it.
+    pub fn create<P: AsRef<Path>>(path: P, codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path)?;
+        let sync = file.try_clone()?;
+        let mut writer = MkvWriter::new(file, codec)?;
+        writer.sync = Some(sync);
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Seek> MkvWriter<W> {
+    pub fn new(writer: W, codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        check_codec(codec)?;
+        Ok(MkvWriter {
+            writer,
+            sync: None,
+            codec,
+            metadata: false,
+            cluster_duration: 1_000_000_000,
+            segment_start: 0,
+            duration_position: 0,
+            tracks_position: 0,
+            written: 0,
+            initialized: false,
+            finished: false,
+            base: None,
+            cluster: Vec::new(),
+            cluster_time: 0,
+            cluster_keyframe: false,
+            end_time: 0,
+            cues: Vec::new(),
+            chapters: Vec::new(),
+        })
+    }
+
+    /// Sets the minimum duration of a cluster, clusters are cut on the first
+    /// keyframe after this duration.  The default is one second.
+    pub fn with_cluster_duration(mut self, duration: Duration) -> Self {
+        self.cluster_duration = duration.as_nanos() as i64;
+        self
+    }
+
+    /// Adds the metadata track which receives the documents passed to
+    /// [`MkvWriter::write_metadata`].
+    pub fn with_metadata_track(mut self) -> Self {
+        self.metadata = true;
+        self
+    }
+
+    /// Number of bytes written to the file so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Writes the encoded packet, the packet timestamps are in nanoseconds.
+    pub fn write(&mut self, packet: &EncodedPacket) -> Result<(), Box<dyn Error>> {
+        if self.finished {
+            return Err("mkv writer already finished".into());
+        }
+
+        let unit = AccessUnit::parse(self.codec, &packet.data);
+        let keyframe = packet.keyframe || unit.keyframe;
+        if !self.initialized {
+            if !keyframe || unit.params.sps.is_empty() || unit.params.pps.is_empty() {
+                return Ok(());
+            }
+            let config = if is_hevc(self.codec) {
+                hevc_config(&unit.params)?
+            } else {
+                avc_config(&unit.params)?
+            };
+            let (width, height) = resolution(self.codec, &unit.params)?;
+            self.write_header(&config, width, height)?;
+        }
+        if unit.data.is_empty() {
+            return Ok(());
+        }
+
+        let base = *self.base.get_or_insert(packet.pts);
+        let time = (packet.pts - base).max(0) / TIMECODE_SCALE;
+
+        // Clusters are cut on keyframes, or regardless when the block
+        // timecode would overflow its 16-bit offset from the cluster.
+        let offset = time - self.cluster_time;
+        if !self.cluster.is_empty()
+            && ((keyframe && offset * TIMECODE_SCALE >= self.cluster_duration)
+                || !BLOCK_OFFSET.contains(&offset))
+        {
+            self.write_cluster()?;
+        }
+        if self.cluster.is_empty() {
+            self.cluster = uint_element(MKV_TIMECODE, time as u64);
+            self.cluster_time = time;
+            self.cluster_keyframe = keyframe;
+        }
+
+        self.push_block(VIDEO_TRACK, time, keyframe, &unit.data);
+        let duration = packet.duration.max(0) / TIMECODE_SCALE;
+        self.end_time = self.end_time.max(time + duration);
+        Ok(())
+    }
+
+    /// Writes the JSON document to the metadata track with the presentation
+    /// timestamp in nanoseconds, in the same clock as the video packets.
+    pub fn write_metadata(&mut self, pts: i64, json: &str) -> Result<(), Box<dyn Error>> {
+        if !self.metadata {
+            return Err("mkv writer has no metadata track".into());
+        }
+        let Some(base) = self.base else {
+            return Ok(());
+        };
+        let time = (pts - base).max(0) / TIMECODE_SCALE;
+        let offset = time - self.cluster_time;
+        if self.cluster.is_empty() || !BLOCK_OFFSET.contains(&offset) {
+            return Err("metadata timestamp outside of the current cluster".into());
+        }
+        self.push_block(METADATA_TRACK, time, true, json.as_bytes());
+        Ok(())
+    }
+
+    /// Adds a chapter starting at the presentation timestamp in nanoseconds,
+    /// in the same clock as the video packets.  Chapters are written when the
+    /// recording is finished.
+    pub fn add_chapter(&mut self, pts: i64, title: &str) {
+        self.chapters.push((pts, title.to_owned()));
+    }
+
+    /// Writes the pending cluster, the cues and the chapters, then completes
+    /// the headers.  The writer cannot be used afterwards.  Finishing is also
+    /// attempted when the writer is dropped.
+    pub fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        if !self.initialized {
+            return Ok(());
+        }
+        self.write_cluster()?;
+
+        let cues_position = self.writer.stream_position()? - self.segment_start;
+        let cues: Vec<u8> = self
+            .cues
+            .iter()
+            .flat_map(|(time, position)| {
+                let positions = [
+                    uint_element(MKV_CUE_TRACK, VIDEO_TRACK),
+                    uint_element(MKV_CUE_CLUSTER_POSITION, *position),
+                ]
+                .concat();
+                element(
+                    MKV_CUE_POINT,
+                    &[
+                        uint_element(MKV_CUE_TIME, *time as u64),
+                        element(MKV_CUE_TRACK_POSITIONS, &positions),
+                    ]
+                    .concat(),
+                )
+            })
+            .collect();
+        let mut tail = element(MKV_CUES, &cues);
+
+        let base = self.base.unwrap_or_default();
+        let chapters_position = cues_position + tail.len() as u64;
+        if !self.chapters.is_empty() {
+            let atoms: Vec<u8> = self
+                .chapters
+                .iter()
+                .enumerate()
+                .flat_map(|(index, (pts, title))| {
+                    element(
+                        MKV_CHAPTER_ATOM,
+                        &[
+                            uint_element(MKV_CHAPTER_UID, index as u64 + 1),
+                            uint_element(MKV_CHAPTER_TIME_START, (pts - base).max(0) as u64),
+                            element(
+                                MKV_CHAPTER_DISPLAY,
+                                &element(MKV_CHAP_STRING, title.as_bytes()),
+                            ),
+                        ]
+                        .concat(),
+                    )
+                })
+                .collect();
+            tail.extend(element(MKV_CHAPTERS, &element(MKV_EDITION_ENTRY, &atoms)));
+        }
+        self.writer.write_all(&tail)?;
+        let end = self.writer.stream_position()?;
+
+        let mut seeks = vec![
+            (MKV_INFO, SEEK_HEAD_RESERVED as u64),
+            (MKV_TRACKS, self.tracks_position),
+            (MKV_CUES, cues_position),
+        ];
+        if !self.chapters.is_empty() {
+            seeks.push((MKV_CHAPTERS, chapters_position));
+        }
+        let seeks: Vec<u8> = seeks
+            .into_iter()
+            .flat_map(|(id, position)| {
+                element(
+                    MKV_SEEK,
+                    &[
+                        element(MKV_SEEK_ID, &id_bytes(id)),
+                        fixed_uint_element(MKV_SEEK_POSITION, position),
+                    ]
+                    .concat(),
+                )
+            })
+            .collect();
+        let mut seek_head = element(MKV_SEEK_HEAD, &seeks);
+        seek_head.extend(void_element(SEEK_HEAD_RESERVED - seek_head.len()));
+
+        self.writer.seek(SeekFrom::Start(self.segment_start - 8))?;
+        self.writer
+            .write_all(&((end - self.segment_start) | (1 << 56)).to_be_bytes())?;
+        self.writer.write_all(&seek_head)?;
+        self.writer.seek(SeekFrom::Start(self.duration_position))?;
+        self.writer
+            .write_all(&(self.end_time as f64).to_be_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.sync()
+    }
+
+    fn sync(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        self.written = self.writer.stream_position()?;
+        if let Some(file) = &self.sync {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn push_block(&mut self, track: u64, time: i64, keyframe: bool, data: &[u8]) {
+        let mut block = Vec::with_capacity(data.len() + 4);
+        size_bytes(track, &mut block);
+        block.extend_from_slice(&((time - self.cluster_time) as i16).to_be_bytes());
+        block.push(if keyframe { 0x80 } else { 0 });
+        block.extend_from_slice(data);
+        self.cluster.extend(element(MKV_SIMPLE_BLOCK, &block));
+    }
+
+    fn write_header(
+        &mut self,
+        config: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let header = element(
+            EBML_HEADER,
+            &[
+                uint_element(EBML_VERSION, 1),
+                uint_element(EBML_READ_VERSION, 1),
+                uint_element(EBML_MAX_ID_LENGTH, 4),
+                uint_element(EBML_MAX_SIZE_LENGTH, 8),
+                element(EBML_DOC_TYPE, b"matroska"),
+                uint_element(EBML_DOC_TYPE_VERSION, 4),
+                uint_element(EBML_DOC_TYPE_READ_VERSION, 2),
+            ]
+            .concat(),
+        );
+        let mut segment = id_bytes(MKV_SEGMENT).to_vec();
+        segment.extend_from_slice(&UNKNOWN_SIZE);
+
+        let app = format!("videostream-rs {}", env!("CARGO_PKG_VERSION"));
+        let info_fields = [
+            uint_element(MKV_TIMECODE_SCALE, TIMECODE_SCALE as u64),
+            element(MKV_MUXING_APP, app.as_bytes()),
+            element(MKV_WRITING_APP, app.as_bytes()),
+        ]
+        .concat();
+        // The duration is the last field so its position is known.
+        let info = element(
+            MKV_INFO,
+            &[info_fields, float_element(MKV_DURATION, 0.0)].concat(),
+        );
+
+        let video = [
+            uint_element(MKV_PIXEL_WIDTH, width as u64),
+            uint_element(MKV_PIXEL_HEIGHT, height as u64),
+        ]
+        .concat();
+        let codec_id = if is_hevc(self.codec) {
+            MKV_CODEC_HEVC
+        } else {
+            MKV_CODEC_H264
+        };
+        let mut tracks = element(
+            MKV_TRACK_ENTRY,
+            &[
+                uint_element(MKV_TRACK_NUMBER, VIDEO_TRACK),
+                uint_element(MKV_TRACK_UID, VIDEO_TRACK),
+                uint_element(MKV_TRACK_TYPE, 1),
+                uint_element(MKV_FLAG_LACING, 0),
+                element(MKV_CODEC_ID, codec_id.as_bytes()),
+                element(MKV_CODEC_PRIVATE, config),
+                element(MKV_VIDEO, &video),
+            ]
+            .concat(),
+        );
+        if self.metadata {
+            tracks.extend(element(
+                MKV_TRACK_ENTRY,
+                &[
+                    uint_element(MKV_TRACK_NUMBER, METADATA_TRACK),
+                    uint_element(MKV_TRACK_UID, METADATA_TRACK),
+                    uint_element(MKV_TRACK_TYPE, 0x11),
+                    uint_element(MKV_FLAG_LACING, 0),
+                    element(MKV_NAME, b"metadata"),
+                    element(MKV_CODEC_ID, MKV_CODEC_TEXT.as_bytes()),
+                ]
+                .concat(),
+            ));
+        }
+        let tracks = element(MKV_TRACKS, &tracks);
+
+        let start = self.writer.stream_position()?;
+        self.segment_start = start + (header.len() + segment.len()) as u64;
+        self.tracks_position = (SEEK_HEAD_RESERVED + info.len()) as u64;
+        self.duration_position = self.segment_start + self.tracks_position - 8;
+        self.writer.write_all(
+            &[
+                header,
+                segment,
+                void_element(SEEK_HEAD_RESERVED),
+                info,
+                tracks,
+            ]
+            .concat(),
+        )?;
+        self.sync()?;
+        self.initialized = true;
+        Ok(())
+    }
+
+    fn write_cluster(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.cluster.is_empty() {
+            return Ok(());
+        }
+        let cluster = element(MKV_CLUSTER, &std::mem::take(&mut self.cluster));
+        let position = self.writer.stream_position()? - self.segment_start;
+        if self.cluster_keyframe {
+            self.cues.push((self.cluster_time, position));
+        }
+        self.writer.write_all(&cluster)?;
+        self.sync()
+    }
+}
+
+impl<W: Write + Seek> Drop for MkvWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        demux::{ebml_children, ebml_uint, Demuxer, MkvReader},
+        nal::tests::packet,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn test_size_bytes() {
+        for (size, expected) in [
+            (0, &[0x80][..]),
+            (126, &[0xfe]),
+            (127, &[0x40, 0x7f]),
+            (16382, &[0x7f, 0xfe]),
+            (16383, &[0x20, 0x3f, 0xff]),
+        ] {
+            let mut out = Vec::new();
+            size_bytes(size, &mut out);
+            assert_eq!(out, expected);
+        }
+        assert_eq!(void_element(20).len(), 20);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut out = Cursor::new(Vec::new());
+        {
+            let mut mkv = MkvWriter::new(&mut out, FourCC(*b"H264"))
+                .unwrap()
+                .with_cluster_duration(Duration::from_millis(200))
+                .with_metadata_track();
+            for i in 0..20 {
+                let packet = packet(i, i % 5 == 0, 5_000_000_000, 40_000_000);
+                mkv.write(&packet).unwrap();
+                mkv.write_metadata(packet.pts, &format!("{{\"frame\":{}}}", i))
+                    .unwrap();
+            }
+            mkv.add_chapter(5_400_000_000, "event");
+            mkv.finish().unwrap();
+        }
+        let file = out.into_inner();
+
+        let mut mkv = MkvReader::new(Cursor::new(file.clone())).unwrap();
+        assert_eq!((mkv.width(), mkv.height()), (1280, 720));
+        for i in 0..20 {
+            let packet = mkv.read_packet().unwrap().unwrap();
+            assert_eq!(packet.keyframe, i % 5 == 0);
+            assert_eq!(packet.pts, i as i64 * 40_000_000);
+            assert_eq!(packet.data.last(), Some(&(0x80 | i)));
+        }
+        assert!(mkv.read_packet().unwrap().is_none());
+
+        let top: Vec<_> = ebml_children(&file).collect();
+        assert_eq!(top.len(), 2);
+        let segment: Vec<_> = ebml_children(top[1].1).collect();
+        let ids: Vec<_> = segment.iter().map(|(id, _)| *id).collect();
+        assert_eq!(&ids[..4], [MKV_SEEK_HEAD, EBML_VOID, MKV_INFO, MKV_TRACKS]);
+        assert_eq!(ids.iter().filter(|id| **id == MKV_CLUSTER).count(), 4);
+        assert_eq!(&ids[ids.len() - 2..], [MKV_CUES, MKV_CHAPTERS]);
+
+        // Seek positions are relative to the segment payload.
+        let segment_start = file.len() - top[1].1.len();
+        for (_, seek) in ebml_children(segment[0].1) {
+            let mut fields = ebml_children(seek);
+            let id = fields.next().unwrap().1;
+            let position = ebml_uint(fields.next().unwrap().1) as usize;
+            assert_eq!(&file[segment_start + position..][..id.len()], id);
+        }
+
+        let cues = ebml_children(segment[ids.len() - 2].1).count();
+        assert_eq!(cues, 4);
+        let (_, cluster) = segment[4];
+        let metadata = ebml_children(cluster)
+            .filter(|(id, block)| *id == MKV_SIMPLE_BLOCK && block[0] == 0x82)
+            .count();
+        assert_eq!(metadata, 5);
+    }
+}
@@ -0,0 +1,799 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    encoder::EncodedPacket,
+    fourcc::FourCC,
+    nal::{
+        h264_nal_type, hevc_nal_type, is_high_profile, split_annexb, H264Sps, HevcSps,
+        H264_NAL_AUD, H264_NAL_IDR, H264_NAL_PPS, H264_NAL_SPS, HEVC_NAL_AUD, HEVC_NAL_BLA_W_LP,
+        HEVC_NAL_CRA, HEVC_NAL_PPS, HEVC_NAL_SPS, HEVC_NAL_VPS,
+    },
+};
+use std::{error::Error, fs::File, io::Write, path::Path, time::Duration};
+
+/// Default media timescale, the 90 kHz clock commonly used for video.
+const DEFAULT_TIMESCALE: u32 = 90_000;
+
+/// Frame duration assumed when packets do not carry usable timestamps.
+const DEFAULT_FRAME_DURATION: i64 = 1_000_000_000 / 30;
+
+const SAMPLE_FLAGS_SYNC: u32 = 0x0200_0000;
+const SAMPLE_FLAGS_NON_SYNC: u32 = 0x0101_0000;
+
+/// The parameter sets of an H.264 or H.265 stream, in the order VPS, SPS and
+/// PPS, as carried in the decoder configuration records of container
+/// formats.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ParameterSets {
+    pub vps: Vec<Vec<u8>>,
+    pub sps: Vec<Vec<u8>>,
+    pub pps: Vec<Vec<u8>>,
+}
+
+impl ParameterSets {
+    pub fn is_complete(&self, codec: FourCC) -> bool {
+        !self.sps.is_empty() && !self.pps.is_empty() && (!is_hevc(codec) || !self.vps.is_empty())
+    }
+}
+
+/// An access unit split into the parameter sets it carried and the
+/// remaining NAL units in length-prefixed format.
+pub(crate) struct AccessUnit {
+    pub params: ParameterSets,
+    pub data: Vec<u8>,
+    pub keyframe: bool,
+}
+
+impl AccessUnit {
+    /// Splits the Annex-B access unit, converting the NAL units other than
+    /// parameter sets and access unit delimiters to 4-byte length-prefixed
+    /// format.
+    pub fn parse(codec: FourCC, data: &[u8]) -> Self {
+        let mut unit = AccessUnit {
+            params: ParameterSets::default(),
+            data: Vec::with_capacity(data.len()),
+            keyframe: false,
+        };
+        for (nal, _) in split_annexb(data) {
+            if nal.is_empty() {
+                continue;
+            }
+            if is_hevc(codec) {
+                match hevc_nal_type(nal) {
+                    HEVC_NAL_VPS => unit.params.vps.push(nal.to_vec()),
+                    HEVC_NAL_SPS => unit.params.sps.push(nal.to_vec()),
+                    HEVC_NAL_PPS => unit.params.pps.push(nal.to_vec()),
+                    HEVC_NAL_AUD => (),
+                    kind => {
+                        // Random access points, BLA_W_LP through CRA.
+                        if (HEVC_NAL_BLA_W_LP..=HEVC_NAL_CRA).contains(&kind) {
+                            unit.keyframe = true;
+                        }
+                        push_length_prefixed(&mut unit.data, nal);
+                    }
+                }
+            } else {
+                match h264_nal_type(nal) {
+                    H264_NAL_SPS => unit.params.sps.push(nal.to_vec()),
+                    H264_NAL_PPS => unit.params.pps.push(nal.to_vec()),
+                    H264_NAL_AUD => (),
+                    kind => {
+                        if kind == H264_NAL_IDR {
+                            unit.keyframe = true;
+                        }
+                        push_length_prefixed(&mut unit.data, nal);
+                    }
+                }
+            }
+        }
+        unit
+    }
+}
+
+fn push_length_prefixed(out: &mut Vec<u8>, nal: &[u8]) {
+    out.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+    out.extend_from_slice(nal);
+}
+
+pub(crate) fn is_hevc(codec: FourCC) -> bool {
+    codec == FourCC(*b"HEVC") || codec == FourCC(*b"H265")
+}
+
+pub(crate) fn check_codec(codec: FourCC) -> Result<(), Box<dyn Error>> {
+    if codec == FourCC(*b"H264") || is_hevc(codec) {
+        return Ok(());
+    }
+    Err(format!("unsupported codec {}, expected H264 or HEVC", codec).into())
+}
+
+/// Returns the coded resolution described by the parameter sets.
+pub(crate) fn resolution(
+    codec: FourCC,
+    params: &ParameterSets,
+) -> Result<(u32, u32), Box<dyn Error>> {
+    let sps = params.sps.first().ok_or("missing sequence parameter set")?;
+    if is_hevc(codec) {
+        let sps = HevcSps::parse(sps)?;
+        Ok((sps.width, sps.height))
+    } else {
+        let sps = H264Sps::parse(sps)?;
+        Ok((sps.width(), sps.height()))
+    }
+}
+
+/// Builds the AVCDecoderConfigurationRecord (avcC) of ISO/IEC 14496-15.
+pub(crate) fn avc_config(params: &ParameterSets) -> Result<Vec<u8>, Box<dyn Error>> {
+    let first = params.sps.first().ok_or("missing sequence parameter set")?;
+    let sps = H264Sps::parse(first)?;
+
+    let mut out = vec![
+        1,
+        sps.profile_idc,
+        sps.constraint_flags,
+        sps.level_idc,
+        0xff,
+    ];
+    out.push(0xe0 | params.sps.len() as u8);
+    for nal in &params.sps {
+        out.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        out.extend_from_slice(nal);
+    }
+    out.push(params.pps.len() as u8);
+    for nal in &params.pps {
+        out.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        out.extend_from_slice(nal);
+    }
+    // The removed High 4:4:4 profile (144) also carries the extension.
+    if is_high_profile(sps.profile_idc) || sps.profile_idc == 144 {
+        out.push(0xfc | sps.chroma_format_idc as u8);
+        out.push(0xf8 | (sps.bit_depth_luma - 8) as u8);
+        out.push(0xf8 | (sps.bit_depth_chroma - 8) as u8);
+        out.push(0);
+    }
+    Ok(out)
+}
+
+/// Builds the HEVCDecoderConfigurationRecord (hvcC) of ISO/IEC 14496-15.
+pub(crate) fn hevc_config(params: &ParameterSets) -> Result<Vec<u8>, Box<dyn Error>> {
+    let first = params.sps.first().ok_or("missing sequence parameter set")?;
+    let sps = HevcSps::parse(first)?;
+
+    let mut out = vec![1];
+    out.extend_from_slice(&sps.profile_tier_level);
+    out.extend_from_slice(&[0xf0, 0x00, 0xfc]);
+    out.push(0xfc | sps.chroma_format_idc as u8);
+    out.push(0xf8 | (sps.bit_depth_luma - 8) as u8);
+    out.push(0xf8 | (sps.bit_depth_chroma - 8) as u8);
+    out.extend_from_slice(&[0, 0]); // avgFrameRate
+    out.push((sps.max_sub_layers << 3) | ((sps.temporal_id_nesting as u8) << 2) | 3);
+    out.push(3);
+    for (kind, nals) in [
+        (HEVC_NAL_VPS, &params.vps),
+        (HEVC_NAL_SPS, &params.sps),
+        (HEVC_NAL_PPS, &params.pps),
+    ] {
+        out.push(0x80 | kind);
+        out.extend_from_slice(&(nals.len() as u16).to_be_bytes());
+        for nal in nals {
+            out.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            out.extend_from_slice(nal);
+        }
+    }
+    Ok(out)
+}
+
+/// Parses an AVCDecoderConfigurationRecord, returning the size in bytes of
+/// the NAL unit length prefix and the parameter sets.
+pub(crate) fn parse_avc_config(data: &[u8]) -> Result<(usize, ParameterSets), Box<dyn Error>> {
+    if data.len() < 7 || data[0] != 1 {
+        return Err("invalid avc decoder configuration".into());
+    }
+    let mut params = ParameterSets::default();
+    let mut pos = 5;
+    let count = (data[pos] & 0x1f) as usize;
+    pos += 1;
+    for _ in 0..count {
+        params.sps.push(read_nal(data, &mut pos)?);
+    }
+    let count = *data.get(pos).ok_or("invalid avc decoder configuration")? as usize;
+    pos += 1;
+    for _ in 0..count {
+        params.pps.push(read_nal(data, &mut pos)?);
+    }
+    Ok(((data[4] & 3) as usize + 1, params))
+}
+
+/// Parses an HEVCDecoderConfigurationRecord, returning the size in bytes of
+/// the NAL unit length prefix and the parameter sets.
+pub(crate) fn parse_hevc_config(data: &[u8]) -> Result<(usize, ParameterSets), Box<dyn Error>> {
+    if data.len() < 23 || data[0] != 1 {
+        return Err("invalid hevc decoder configuration".into());
+    }
+    let mut params = ParameterSets::default();
+    let mut pos = 23;
+    for _ in 0..data[22] {
+        let header = data
+            .get(pos..pos + 3)
+            .ok_or("invalid hevc decoder configuration")?;
+        pos += 3;
+        let count = u16::from_be_bytes([header[1], header[2]]);
+        for _ in 0..count {
+            let nal = read_nal(data, &mut pos)?;
+            match header[0] & 0x3f {
+                HEVC_NAL_VPS => params.vps.push(nal),
+                HEVC_NAL_SPS => params.sps.push(nal),
+                HEVC_NAL_PPS => params.pps.push(nal),
+                _ => (),
+            }
+        }
+    }
+    Ok(((data[21] & 3) as usize + 1, params))
+}
+
+fn read_nal(data: &[u8], pos: &mut usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let len = data
+        .get(*pos..*pos + 2)
+        .ok_or("truncated decoder configuration")?;
+    let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+    let nal = data
+        .get(*pos + 2..*pos + 2 + len)
+        .ok_or("truncated decoder configuration")?;
+    *pos += 2 + len;
+    Ok(nal.to_vec())
+}
+
+pub(crate) fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 8);
+    out.extend_from_slice(&(payload.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+pub(crate) fn full_box(kind: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(payload.len() + 4);
+    body.extend_from_slice(&((version as u32) << 24 | flags).to_be_bytes());
+    body.extend_from_slice(payload);
+    mp4_box(kind, &body)
+}
+
+fn concat(parts: &[Vec<u8>]) -> Vec<u8> {
+    parts.concat()
+}
+
+const MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+struct Sample {
+    data: Vec<u8>,
+    dts: i64,
+    cts: i64,
+    duration: i64,
+    keyframe: bool,
+}
+
+/// The Mp4Writer records the encoded video to a fragmented MP4 file.
+///
+/// The movie header is written once the first keyframe is received, packets
+/// preceding it are dropped.  Samples are then grouped into fragments which
+/// start on a keyframe and are written and synced to storage as a whole once
+/// they reach the fragment duration.  Since the file is only ever appended
+/// to, an interrupted recording remains playable up to the last complete
+/// fragment.  Calling [`Mp4Writer::finish`] writes the final fragment along
+/// with an index for random access.
+///
+/// Packets are expected in Annex-B format as produced by the encoders, the
+/// parameter sets are moved into the sample description.  The parameter sets
+/// of the first keyframe describe the whole file.
+pub struct Mp4Writer<W: Write = File> {
+    writer: W,
+    sync: Option<File>,
+    codec: FourCC,
+    timescale: u32,
+    fragment_duration: i64,
+    frame_duration: i64,
+    position: u64,
+    sequence: u32,
+    initialized: bool,
+    finished: bool,
+    base: Option<i64>,
+    last_dts: Option<i64>,
+    samples: Vec<Sample>,
+    fragments: Vec<(u64, u64)>,
+}
+
+impl Mp4Writer<File> {
+    /// Creates the file at the path and records the encoded video of the
+    /// codec, H264 or HEVC, into it.
+    pub fn create<P: AsRef<Path>>(path: P, codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path)?;
+        let sync = file.try_clone()?;
+        let mut writer = Mp4Writer::new(file, codec)?;
+        writer.sync = Some(sync);
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Mp4Writer<W> {
+    pub fn new(writer: W, codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        check_codec(codec)?;
+        Ok(Mp4Writer {
+            writer,
+            sync: None,
+            codec,
+            timescale: DEFAULT_TIMESCALE,
+            fragment_duration: 2_000_000_000,
+            frame_duration: DEFAULT_FRAME_DURATION,
+            position: 0,
+            sequence: 1,
+            initialized: false,
+            finished: false,
+            base: None,
+            last_dts: None,
+            samples: Vec::new(),
+            fragments: Vec::new(),
+        })
+    }
+
+    /// Sets the minimum duration of a fragment, fragments are cut on the
+    /// first keyframe after this duration.  The default is two seconds.
+    pub fn with_fragment_duration(mut self, duration: Duration) -> Self {
+        self.fragment_duration = duration.as_nanos() as i64;
+        self
+    }
+
+    /// Sets the media timescale in ticks per second, the default is 90 kHz.
+    pub fn with_timescale(mut self, timescale: u32) -> Self {
+        self.timescale = timescale;
+        self
+    }
+
+    /// Sets the frame rate assumed when packets do not carry increasing
+    /// timestamps, the default is 30 frames per second.
+    pub fn with_frame_rate(mut self, fps: u32) -> Self {
+        self.frame_duration = 1_000_000_000 / fps.max(1) as i64;
+        self
+    }
+
+    /// Number of bytes written to the file so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.position
+    }
+
+    /// Writes the encoded packet, the packet timestamps are in nanoseconds.
+    pub fn write(&mut self, packet: &EncodedPacket) -> Result<(), Box<dyn Error>> {
+        if self.finished {
+            return Err("mp4 writer already finished".into());
+        }
+
+        let unit = AccessUnit::parse(self.codec, &packet.data);
+        let keyframe = packet.keyframe || unit.keyframe;
+        if !self.initialized {
+            if !keyframe || !unit.params.is_complete(self.codec) {
+                return Ok(());
+            }
+            self.write_header(&unit.params)?;
+        }
+        if unit.data.is_empty() {
+            return Ok(());
+        }
+
+        // Timestamps are relative to the first packet, packets without
+        // increasing timestamps are spaced by the nominal frame duration.
+        let base = *self.base.get_or_insert(packet.dts);
+        let mut dts = packet.dts - base;
+        if let Some(last) = self.last_dts {
+            if dts <= last {
+                dts = last + self.frame_duration;
+            }
+        }
+        self.last_dts = Some(dts);
+
+        if let Some(previous) = self.samples.last_mut() {
+            previous.duration = dts - previous.dts;
+        }
+        if keyframe {
+            if let Some(first) = self.samples.first() {
+                if dts - first.dts >= self.fragment_duration {
+                    self.write_fragment()?;
+                }
+            }
+        }
+
+        self.samples.push(Sample {
+            data: unit.data,
+            dts,
+            cts: packet.pts - packet.dts,
+            duration: if packet.duration > 0 {
+                packet.duration
+            } else {
+                self.frame_duration
+            },
+            keyframe,
+        });
+
+        Ok(())
+    }
+
+    /// Writes the pending fragment and the random access index.  The writer
+    /// cannot be used afterwards.  Finishing is also attempted when the
+    /// writer is dropped.
+    pub fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        if !self.initialized {
+            return Ok(());
+        }
+        self.write_fragment()?;
+
+        let mut tfra = Vec::new();
+        tfra.extend_from_slice(&1u32.to_be_bytes()); // track_ID
+        tfra.extend_from_slice(&0u32.to_be_bytes()); // 1-byte traf, trun and sample numbers
+        tfra.extend_from_slice(&(self.fragments.len() as u32).to_be_bytes());
+        for (time, offset) in &self.fragments {
+            tfra.extend_from_slice(&time.to_be_bytes());
+            tfra.extend_from_slice(&offset.to_be_bytes());
+            tfra.extend_from_slice(&[1, 1, 1]);
+        }
+        let tfra = full_box(b"tfra", 1, 0, &tfra);
+        let mfra_size = (8 + tfra.len() + 16) as u32;
+        let mfro = full_box(b"mfro", 0, 0, &mfra_size.to_be_bytes());
+        let mfra = mp4_box(b"mfra", &concat(&[tfra, mfro]));
+        self.emit(&mfra)
+    }
+
+    fn to_timescale(&self, ns: i64) -> i64 {
+        (ns as i128 * self.timescale as i128 + 500_000_000).div_euclid(1_000_000_000) as i64
+    }
+
+    fn emit(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.writer.write_all(data)?;
+        self.writer.flush()?;
+        if let Some(file) = &self.sync {
+            file.sync_data()?;
+        }
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_header(&mut self, params: &ParameterSets) -> Result<(), Box<dyn Error>> {
+        let (width, height) = resolution(self.codec, params)?;
+        let hevc = is_hevc(self.codec);
+
+        let mut ftyp = b"isom".to_vec();
+        ftyp.extend_from_slice(&0x200u32.to_be_bytes());
+        ftyp.extend_from_slice(b"isomiso6mp41");
+        ftyp.extend_from_slice(if hevc { b"hvc1" } else { b"avc1" });
+        let ftyp = mp4_box(b"ftyp", &ftyp);
+
+        let mut mvhd = Vec::new();
+        mvhd.extend_from_slice(&[0; 8]); // creation and modification time
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&0u32.to_be_bytes()); // duration
+        mvhd.extend_from_slice(&0x0001_0000u32.to_be_bytes()); // rate
+        mvhd.extend_from_slice(&0x0100u16.to_be_bytes()); // volume
+        mvhd.extend_from_slice(&[0; 10]);
+        MATRIX
+            .iter()
+            .for_each(|v| mvhd.extend_from_slice(&v.to_be_bytes()));
+        mvhd.extend_from_slice(&[0; 24]);
+        mvhd.extend_from_slice(&2u32.to_be_bytes()); // next_track_ID
+        let mvhd = full_box(b"mvhd", 0, 0, &mvhd);
+
+        let mut tkhd = Vec::new();
+        tkhd.extend_from_slice(&[0; 8]);
+        tkhd.extend_from_slice(&1u32.to_be_bytes()); // track_ID
+        tkhd.extend_from_slice(&[0; 4]);
+        tkhd.extend_from_slice(&0u32.to_be_bytes()); // duration
+        tkhd.extend_from_slice(&[0; 16]); // reserved, layer, alternate_group, volume
+        MATRIX
+            .iter()
+            .for_each(|v| tkhd.extend_from_slice(&v.to_be_bytes()));
+        tkhd.extend_from_slice(&(width << 16).to_be_bytes());
+        tkhd.extend_from_slice(&(height << 16).to_be_bytes());
+        let tkhd = full_box(b"tkhd", 0, 3, &tkhd);
+
+        let mut mdhd = Vec::new();
+        mdhd.extend_from_slice(&[0; 8]);
+        mdhd.extend_from_slice(&self.timescale.to_be_bytes());
+        mdhd.extend_from_slice(&0u32.to_be_bytes());
+        mdhd.extend_from_slice(&0x55c4u16.to_be_bytes()); // und
+        mdhd.extend_from_slice(&[0; 2]);
+        let mdhd = full_box(b"mdhd", 0, 0, &mdhd);
+
+        let mut hdlr = vec![0; 4];
+        hdlr.extend_from_slice(b"vide");
+        hdlr.extend_from_slice(&[0; 12]);
+        hdlr.extend_from_slice(b"VideoHandler\0");
+        let hdlr = full_box(b"hdlr", 0, 0, &hdlr);
+
+        let vmhd = full_box(b"vmhd", 0, 1, &[0; 8]);
+        let url = full_box(b"url ", 0, 1, &[]);
+        let mut dref = 1u32.to_be_bytes().to_vec();
+        dref.extend_from_slice(&url);
+        let dinf = mp4_box(b"dinf", &full_box(b"dref", 0, 0, &dref));
+
+        let mut entry = vec![0; 6];
+        entry.extend_from_slice(&1u16.to_be_bytes()); // data_reference_index
+        entry.extend_from_slice(&[0; 16]);
+        entry.extend_from_slice(&(width as u16).to_be_bytes());
+        entry.extend_from_slice(&(height as u16).to_be_bytes());
+        entry.extend_from_slice(&0x0048_0000u32.to_be_bytes());
+        entry.extend_from_slice(&0x0048_0000u32.to_be_bytes());
+        entry.extend_from_slice(&[0; 4]);
+        entry.extend_from_slice(&1u16.to_be_bytes()); // frame_count
+        entry.extend_from_slice(&[0; 32]); // compressorname
+        entry.extend_from_slice(&0x0018u16.to_be_bytes()); // depth
+        entry.extend_from_slice(&0xffffu16.to_be_bytes());
+        let entry = if hevc {
+            entry.extend_from_slice(&mp4_box(b"hvcC", &hevc_config(params)?));
+            mp4_box(b"hvc1", &entry)
+        } else {
+            entry.extend_from_slice(&mp4_box(b"avcC", &avc_config(params)?));
+            mp4_box(b"avc1", &entry)
+        };
+        let mut stsd = 1u32.to_be_bytes().to_vec();
+        stsd.extend_from_slice(&entry);
+
+        let empty = 0u32.to_be_bytes();
+        let stbl = mp4_box(
+            b"stbl",
+            &concat(&[
+                full_box(b"stsd", 0, 0, &stsd),
+                full_box(b"stts", 0, 0, &empty),
+                full_box(b"stsc", 0, 0, &empty),
+                full_box(b"stsz", 0, 0, &[0; 8]),
+                full_box(b"stco", 0, 0, &empty),
+            ]),
+        );
+        let minf = mp4_box(b"minf", &concat(&[vmhd, dinf, stbl]));
+        let mdia = mp4_box(b"mdia", &concat(&[mdhd, hdlr, minf]));
+        let trak = mp4_box(b"trak", &concat(&[tkhd, mdia]));
+
+        let mut trex = Vec::new();
+        trex.extend_from_slice(&1u32.to_be_bytes()); // track_ID
+        trex.extend_from_slice(&1u32.to_be_bytes()); // default_sample_description_index
+        trex.extend_from_slice(&[0; 12]);
+        let mvex = mp4_box(b"mvex", &full_box(b"trex", 0, 0, &trex));
+        let moov = mp4_box(b"moov", &concat(&[mvhd, trak, mvex]));
+
+        self.emit(&concat(&[ftyp, moov]))?;
+        self.initialized = true;
+        Ok(())
+    }
+
+    fn write_fragment(&mut self) -> Result<(), Box<dyn Error>> {
+        let samples = std::mem::take(&mut self.samples);
+        let Some(first) = samples.first() else {
+            return Ok(());
+        };
+        let base_time = self.to_timescale(first.dts);
+
+        let mut mfhd = Vec::new();
+        mfhd.extend_from_slice(&self.sequence.to_be_bytes());
+        let mfhd = full_box(b"mfhd", 0, 0, &mfhd);
+
+        // default-base-is-moof
+        let tfhd = full_box(b"tfhd", 0, 0x02_0000, &1u32.to_be_bytes());
+        let tfdt = full_box(b"tfdt", 1, 0, &(base_time as u64).to_be_bytes());
+
+        let mut trun = Vec::new();
+        trun.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+        let data_offset_pos = trun.len();
+        trun.extend_from_slice(&0u32.to_be_bytes());
+        let mut mdat_len = 0;
+        for sample in &samples {
+            let start = self.to_timescale(sample.dts);
+            let end = self.to_timescale(sample.dts + sample.duration);
+            trun.extend_from_slice(&((end - start).max(0) as u32).to_be_bytes());
+            trun.extend_from_slice(&(sample.data.len() as u32).to_be_bytes());
+            let flags = if sample.keyframe {
+                SAMPLE_FLAGS_SYNC
+            } else {
+                SAMPLE_FLAGS_NON_SYNC
+            };
+            trun.extend_from_slice(&flags.to_be_bytes());
+            trun.extend_from_slice(&(self.to_timescale(sample.cts) as i32).to_be_bytes());
+            mdat_len += sample.data.len();
+        }
+
+        // data-offset, sample-duration, sample-size, sample-flags and
+        // sample-composition-time-offset present.
+        let trun_len = trun.len() + 12;
+        let traf_len = 8 + tfhd.len() + tfdt.len() + trun_len;
+        let moof_len = 8 + mfhd.len() + traf_len;
+        let data_offset = (moof_len + 8) as u32;
+        trun[data_offset_pos..data_offset_pos + 4].copy_from_slice(&data_offset.to_be_bytes());
+        let trun = full_box(b"trun", 1, 0x0f01, &trun);
+
+        let traf = mp4_box(b"traf", &concat(&[tfhd, tfdt, trun]));
+        let mut fragment = mp4_box(b"moof", &concat(&[mfhd, traf]));
+        fragment.reserve(mdat_len + 8);
+        fragment.extend_from_slice(&(mdat_len as u32 + 8).to_be_bytes());
+        fragment.extend_from_slice(b"mdat");
+        for sample in &samples {
+            fragment.extend_from_slice(&sample.data);
+        }
+
+        self.fragments.push((base_time as u64, self.position));
+        self.emit(&fragment)?;
+        self.sequence += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for Mp4Writer<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nal::{
+        tests::{hevc_sps, packet, PPS, SPS},
+        BitWriter,
+    };
+
+    /// Walks the boxes at the top level of the buffer.
+    fn boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos + 8 <= data.len() {
+            let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = data[pos + 4..pos + 8].try_into().unwrap();
+            out.push((kind, &data[pos + 8..pos + size]));
+            pos += size;
+        }
+        assert_eq!(pos, data.len());
+        out
+    }
+
+    fn find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> &'a [u8] {
+        let (kind, rest) = path.split_first().unwrap();
+        let (_, payload) = boxes(data)
+            .into_iter()
+            .find(|(k, _)| k == *kind)
+            .unwrap_or_else(|| panic!("missing box {:?}", kind));
+        if rest.is_empty() {
+            payload
+        } else {
+            find(payload, rest)
+        }
+    }
+
+    #[test]
+    fn test_fragments() {
+        let mut out = Vec::new();
+        {
+            let mut mp4 = Mp4Writer::new(&mut out, FourCC(*b"H264"))
+                .unwrap()
+                .with_fragment_duration(Duration::from_millis(400));
+            // Packets ahead of the first keyframe are dropped.
+            mp4.write(&packet(0, false, 0, 33_333_333)).unwrap();
+            for i in 1..=60 {
+                mp4.write(&packet(i, i % 15 == 1, 0, 33_333_333)).unwrap();
+            }
+            mp4.finish().unwrap();
+        }
+
+        let top: Vec<_> = boxes(&out).into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(&top[..2], [*b"ftyp", *b"moov"]);
+        assert_eq!(top.iter().filter(|k| *k == b"moof").count(), 4);
+        assert_eq!(top.last(), Some(b"mfra"));
+
+        let stsd = find(
+            &out,
+            &[b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stsd"],
+        );
+        let avc1 = &stsd[8..];
+        assert_eq!(&avc1[4..8], b"avc1");
+        assert_eq!(u16::from_be_bytes([avc1[32], avc1[33]]), 1280);
+        assert_eq!(u16::from_be_bytes([avc1[34], avc1[35]]), 720);
+        let config = find(&avc1[8 + 78..], &[b"avcC"]);
+        assert_eq!(&config[..4], &[1, 0x42, 0xc0, 0x1f]);
+        assert_eq!(&config[8..8 + SPS.len()], &SPS);
+
+        let trun = find(&out, &[b"moof", b"traf", b"trun"]);
+        assert_eq!(u32::from_be_bytes(trun[4..8].try_into().unwrap()), 15);
+        // First sample is a sync sample of 3000 ticks.
+        assert_eq!(u32::from_be_bytes(trun[12..16].try_into().unwrap()), 3000);
+        assert_eq!(
+            u32::from_be_bytes(trun[20..24].try_into().unwrap()),
+            SAMPLE_FLAGS_SYNC
+        );
+
+        // Sample data is length-prefixed without parameter sets.
+        let mdat = find(&out, &[b"mdat"]);
+        assert_eq!(&mdat[..6], &[0, 0, 0, 2, 0x65, 0x81]);
+    }
+
+    #[test]
+    fn test_avc_config() {
+        for (profile_idc, extended) in [
+            (66, false),
+            (77, false),
+            (100, true),
+            (244, true),
+            (118, true),
+        ] {
+            let mut w = BitWriter::new();
+            w.write_bits(profile_idc, 8);
+            w.write_bits(0, 8); // constraint flags
+            w.write_bits(40, 8); // level_idc
+            w.write_ue(0); // seq_parameter_set_id
+            if extended {
+                w.write_ue(2); // chroma_format_idc: 4:2:2
+                w.write_ue(2); // bit_depth_luma_minus8
+                w.write_ue(2); // bit_depth_chroma_minus8
+                w.write_bit(false); // qpprime_y_zero_transform_bypass_flag
+                w.write_bit(false); // seq_scaling_matrix_present_flag
+            }
+            w.write_ue(0); // log2_max_frame_num_minus4
+            w.write_ue(2); // pic_order_cnt_type
+            w.write_ue(1); // max_num_ref_frames
+            w.write_bit(false); // gaps_in_frame_num_value_allowed_flag
+            w.write_ue(79); // pic_width_in_mbs_minus1
+            w.write_ue(44); // pic_height_in_map_units_minus1
+            w.write_bit(true); // frame_mbs_only_flag
+            w.write_bit(true); // direct_8x8_inference_flag
+            w.write_bit(false); // frame_cropping_flag
+            w.write_bit(false); // vui_parameters_present_flag
+            let mut sps = vec![0x67];
+            sps.extend_from_slice(&w.finish());
+
+            let params = ParameterSets {
+                vps: Vec::new(),
+                sps: vec![sps.clone()],
+                pps: vec![PPS.to_vec()],
+            };
+            let config = avc_config(&params).unwrap();
+            let end = 8 + sps.len() + 3 + PPS.len();
+            assert_eq!(config[1], profile_idc as u8);
+            if extended {
+                assert_eq!(&config[end..], &[0xfe, 0xfa, 0xfa, 0]);
+            } else {
+                assert_eq!(config.len(), end);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hevc_config() {
+        let params = ParameterSets {
+            vps: vec![vec![0x40, 0x01, 0x0c]],
+            sps: vec![hevc_sps(1280, 720)],
+            pps: vec![vec![0x44, 0x01, 0xc1]],
+        };
+        assert_eq!(resolution(FourCC(*b"HEVC"), &params).unwrap(), (1280, 720));
+        let config = hevc_config(&params).unwrap();
+        assert_eq!(config[0], 1);
+        assert_eq!(config[1], 0x01);
+        assert_eq!(config[21], 0x0f);
+        assert_eq!(config[22], 3);
+        assert_eq!(config[23], 0x80 | HEVC_NAL_VPS);
+        assert_eq!(parse_hevc_config(&config).unwrap(), (4, params));
+    }
+
+    #[test]
+    fn test_hevc_keyframes() {
+        let unit = |kind: u8| {
+            let data = [0, 0, 0, 1, kind << 1, 0x01, 0xaf];
+            AccessUnit::parse(FourCC(*b"HEVC"), &data).keyframe
+        };
+        for kind in 16..=21 {
+            assert!(unit(kind), "{}", kind);
+        }
+        for kind in [0, 1, 9, 15, 22, 23] {
+            assert!(!unit(kind), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_codec() {
+        assert!(Mp4Writer::new(Vec::new(), FourCC(*b"MJPG")).is_err());
+    }
+}
@@ -0,0 +1,1089 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+//! Helpers for handling H.264 and H.265 bitstreams at the NAL unit level.
+
+use std::error::Error;
+
+#[cfg(feature = "software")]
+pub(crate) const H264_NAL_SLICE: u8 = 1;
+pub(crate) const H264_NAL_IDR: u8 = 5;
+pub(crate) const H264_NAL_SPS: u8 = 7;
+pub(crate) const H264_NAL_PPS: u8 = 8;
+pub(crate) const H264_NAL_AUD: u8 = 9;
+
+pub(crate) const HEVC_NAL_BLA_W_LP: u8 = 16;
+pub(crate) const HEVC_NAL_CRA: u8 = 21;
+pub(crate) const HEVC_NAL_VPS: u8 = 32;
+pub(crate) const HEVC_NAL_SPS: u8 = 33;
+pub(crate) const HEVC_NAL_PPS: u8 = 34;
+pub(crate) const HEVC_NAL_AUD: u8 = 35;
+
+/// Returns the NAL unit type from the first byte of an H.264 NAL unit.
+pub(crate) fn h264_nal_type(nal: &[u8]) -> u8 {
+    nal.first().map_or(0, |b| b & 0x1f)
+}
+
+/// Returns the NAL unit type from the first byte of an H.265 NAL unit.
+pub(crate) fn hevc_nal_type(nal: &[u8]) -> u8 {
+    nal.first().map_or(0, |b| (b >> 1) & 0x3f)
+}
+
+/// Returns the offset of the next Annex-B start code at or after `from`,
+/// including the leading zero byte of a four byte start code.
+pub(crate) fn next_start_code(data: &[u8], from: usize) -> Option<usize> {
+    if data.len() < 3 {
+        return None;
+    }
+    (from..data.len() - 2)
+        .find(|&i| data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1)
+        .map(|i| {
+            if i > from && data[i - 1] == 0 {
+                i - 1
+            } else {
+                i
+            }
+        })
+}
+
+/// Returns the length of the buffer prefix which holds complete NAL units,
+/// which ends at the start code of the last NAL unit in the buffer.
+pub(crate) fn complete_len(data: &[u8]) -> usize {
+    let mut end = 0;
+    let mut pos = 0;
+    while let Some(start) = next_start_code(data, pos) {
+        if start > 0 {
+            end = start;
+        }
+        pos = start + 3;
+    }
+    end
+}
+
+/// Iterates over the NAL units of an Annex-B stream, yielding each NAL unit
+/// without its start code along with the offset of the end of the NAL unit.
+pub(crate) fn split_annexb(data: &[u8]) -> impl Iterator<Item = (&[u8], usize)> {
+    let mut pos = next_start_code(data, 0);
+    std::iter::from_fn(move || {
+        let start = pos?;
+        let payload = start + if data[start + 2] == 1 { 3 } else { 4 };
+        let end = next_start_code(data, payload).unwrap_or(data.len());
+        pos = if end < data.len() { Some(end) } else { None };
+
+        // Trailing zero bytes belong to the next start code.
+        let mut nal_end = end;
+        while nal_end > payload && data[nal_end - 1] == 0 {
+            nal_end -= 1;
+        }
+        Some((&data[payload..nal_end], end))
+    })
+}
+
+/// Inserts emulation prevention bytes into the raw byte sequence payload to
+/// form the NAL unit payload.
+#[cfg(test)]
+pub(crate) fn escape(rbsp: &[u8], out: &mut Vec<u8>) {
+    let mut zeros = 0;
+    for &byte in rbsp {
+        if zeros >= 2 && byte <= 3 {
+            out.push(3);
+            zeros = 0;
+        }
+        out.push(byte);
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+    }
+}
+
+/// Removes the emulation prevention bytes from the NAL unit payload.
+pub(crate) fn unescape(nal: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        out.push(byte);
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+    }
+    out
+}
+
+/// Writes bits in the most-significant-bit first order used by H.264.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct BitWriter {
+    data: Vec<u8>,
+    current: u8,
+    bits: u32,
+}
+
+#[cfg(test)]
+impl BitWriter {
+    pub fn new() -> Self {
+        BitWriter::default()
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        self.current = (self.current << 1) | bit as u8;
+        self.bits += 1;
+        if self.bits == 8 {
+            self.data.push(self.current);
+            self.current = 0;
+            self.bits = 0;
+        }
+    }
+
+    pub fn write_bits(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    /// Writes an unsigned Exp-Golomb code.
+    pub fn write_ue(&mut self, value: u32) {
+        let value = value as u64 + 1;
+        let len = 64 - value.leading_zeros();
+        self.write_bits(0, len - 1);
+        for i in (0..len).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    /// Writes a signed Exp-Golomb code.
+    pub fn write_se(&mut self, value: i32) {
+        let mapped = if value > 0 {
+            2 * value as u32 - 1
+        } else {
+            2 * value.unsigned_abs()
+        };
+        self.write_ue(mapped);
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Writes whole bytes, the writer must be byte aligned.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        debug_assert!(self.is_aligned());
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Writes the rbsp_trailing_bits and returns the payload.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.write_bit(true);
+        while !self.is_aligned() {
+            self.write_bit(false);
+        }
+        self.data
+    }
+}
+
+/// Reads bits from an unescaped raw byte sequence payload.
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Box<dyn Error>> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or("unexpected end of bitstream")?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1 == 1;
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    pub fn read_bits(&mut self, count: u32) -> Result<u32, Box<dyn Error>> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Ok(value)
+    }
+
+    pub fn skip_bits(&mut self, count: usize) -> Result<(), Box<dyn Error>> {
+        if self.pos + count > self.data.len() * 8 {
+            return Err("unexpected end of bitstream".into());
+        }
+        self.pos += count;
+        Ok(())
+    }
+
+    /// Reads an unsigned Exp-Golomb code.
+    pub fn read_ue(&mut self) -> Result<u32, Box<dyn Error>> {
+        let mut zeros = 0;
+        while !self.read_bit()? {
+            zeros += 1;
+            if zeros > 31 {
+                return Err("invalid exp-golomb code".into());
+            }
+        }
+        Ok(((1u64 << zeros) - 1 + self.read_bits(zeros)? as u64) as u32)
+    }
+
+    /// Reads a signed Exp-Golomb code.
+    pub fn read_se(&mut self) -> Result<i32, Box<dyn Error>> {
+        let value = self.read_ue()? as i64;
+        Ok(if value % 2 == 1 {
+            ((value + 1) / 2) as i32
+        } else {
+            -(value / 2) as i32
+        })
+    }
+
+    #[cfg(test)]
+    pub fn is_aligned(&self) -> bool {
+        self.pos.is_multiple_of(8)
+    }
+
+    #[cfg(test)]
+    pub fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    /// Reads whole bytes, the reader must be byte aligned.
+    #[cfg(test)]
+    pub fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        debug_assert!(self.is_aligned());
+        let start = self.pos / 8;
+        let bytes = self
+            .data
+            .get(start..start + count)
+            .ok_or("unexpected end of bitstream")?;
+        self.pos += count * 8;
+        Ok(bytes)
+    }
+}
+
+/// Returns true for the High and later profiles whose sequence parameter
+/// sets carry the chroma format and bit depths.
+pub(crate) fn is_high_profile(profile_idc: u8) -> bool {
+    matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    )
+}
+
+/// The fields of an H.264 sequence parameter set required to interpret the
+/// slices and to describe the stream in container formats.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct H264Sps {
+    pub profile_idc: u8,
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub log2_max_frame_num: u32,
+    pub poc_type: u32,
+    pub log2_max_poc_lsb: u32,
+    pub delta_pic_order_always_zero: bool,
+    pub width_mbs: u32,
+    pub height_mbs: u32,
+    pub frame_mbs_only: bool,
+    /// Frame cropping offsets in pixels as left, right, top, bottom.
+    pub crop: (u32, u32, u32, u32),
+}
+
+impl H264Sps {
+    /// Parses the SPS from the NAL unit, including the NAL unit header.
+    pub fn parse(nal: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if h264_nal_type(nal) != H264_NAL_SPS {
+            return Err("not an h.264 sequence parameter set".into());
+        }
+        let rbsp = unescape(&nal[1..]);
+        let mut r = BitReader::new(&rbsp);
+
+        let profile_idc = r.read_bits(8)? as u8;
+        let constraint_flags = r.read_bits(8)? as u8;
+        let level_idc = r.read_bits(8)? as u8;
+        r.read_ue()?; // seq_parameter_set_id
+
+        let mut chroma_format_idc = 1;
+        let mut bit_depth_luma = 8;
+        let mut bit_depth_chroma = 8;
+        if is_high_profile(profile_idc) {
+            chroma_format_idc = r.read_ue()?;
+            if chroma_format_idc == 3 {
+                r.read_bit()?; // separate_colour_plane_flag
+            }
+            bit_depth_luma = r.read_ue()? + 8;
+            bit_depth_chroma = r.read_ue()? + 8;
+            r.read_bit()?; // qpprime_y_zero_transform_bypass_flag
+            if r.read_bit()? {
+                let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+                for i in 0..lists {
+                    if r.read_bit()? {
+                        skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+
+        let log2_max_frame_num = r.read_ue()? + 4;
+        let poc_type = r.read_ue()?;
+        let mut log2_max_poc_lsb = 0;
+        let mut delta_pic_order_always_zero = false;
+        match poc_type {
+            0 => log2_max_poc_lsb = r.read_ue()? + 4,
+            1 => {
+                delta_pic_order_always_zero = r.read_bit()?;
+                r.read_se()?; // offset_for_non_ref_pic
+                r.read_se()?; // offset_for_top_to_bottom_field
+                for _ in 0..r.read_ue()? {
+                    r.read_se()?; // offset_for_ref_frame
+                }
+            }
+            _ => (),
+        }
+        r.read_ue()?; // max_num_ref_frames
+        r.read_bit()?; // gaps_in_frame_num_value_allowed_flag
+        let width_mbs = r.read_ue()? + 1;
+        let height_map_units = r.read_ue()? + 1;
+        let frame_mbs_only = r.read_bit()?;
+        if !frame_mbs_only {
+            r.read_bit()?; // mb_adaptive_frame_field_flag
+        }
+        r.read_bit()?; // direct_8x8_inference_flag
+        let height_mbs = height_map_units * if frame_mbs_only { 1 } else { 2 };
+
+        let mut crop = (0, 0, 0, 0);
+        if r.read_bit()? {
+            let (crop_x, crop_y) = match chroma_format_idc {
+                1 => (2, 2),
+                2 => (2, 1),
+                _ => (1, 1),
+            };
+            let crop_y = crop_y * if frame_mbs_only { 1 } else { 2 };
+            crop = (
+                r.read_ue()? * crop_x,
+                r.read_ue()? * crop_x,
+                r.read_ue()? * crop_y,
+                r.read_ue()? * crop_y,
+            );
+        }
+
+        Ok(H264Sps {
+            profile_idc,
+            constraint_flags,
+            level_idc,
+            chroma_format_idc,
+            bit_depth_luma,
+            bit_depth_chroma,
+            log2_max_frame_num,
+            poc_type,
+            log2_max_poc_lsb,
+            delta_pic_order_always_zero,
+            width_mbs,
+            height_mbs,
+            frame_mbs_only,
+            crop,
+        })
+    }
+
+    /// The displayed width in pixels after cropping.
+    pub fn width(&self) -> u32 {
+        (self.width_mbs * 16).saturating_sub(self.crop.0 + self.crop.1)
+    }
+
+    /// The displayed height in pixels after cropping.
+    pub fn height(&self) -> u32 {
+        (self.height_mbs * 16).saturating_sub(self.crop.2 + self.crop.3)
+    }
+}
+
+/// The fields of an H.265 sequence parameter set required to describe the
+/// stream in container formats.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HevcSps {
+    pub max_sub_layers: u8,
+    pub temporal_id_nesting: bool,
+    /// The general profile_tier_level fields: profile space, tier and profile
+    /// followed by the compatibility flags, constraint flags and level.
+    pub profile_tier_level: [u8; 12],
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl HevcSps {
+    /// Parses the SPS from the NAL unit, including the two byte NAL unit
+    /// header.
+    pub fn parse(nal: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if hevc_nal_type(nal) != HEVC_NAL_SPS || nal.len() < 2 {
+            return Err("not an h.265 sequence parameter set".into());
+        }
+        let rbsp = unescape(&nal[2..]);
+        let mut r = BitReader::new(&rbsp);
+
+        r.read_bits(4)?; // sps_video_parameter_set_id
+        let max_sub_layers_minus1 = r.read_bits(3)?;
+        let temporal_id_nesting = r.read_bit()?;
+
+        let mut profile_tier_level = [0; 12];
+        for byte in profile_tier_level.iter_mut() {
+            *byte = r.read_bits(8)? as u8;
+        }
+        let mut sub_layers = Vec::new();
+        for _ in 0..max_sub_layers_minus1 {
+            sub_layers.push((r.read_bit()?, r.read_bit()?));
+        }
+        if max_sub_layers_minus1 > 0 {
+            for _ in max_sub_layers_minus1..8 {
+                r.skip_bits(2)?;
+            }
+        }
+        for (profile_present, level_present) in sub_layers {
+            if profile_present {
+                r.skip_bits(88)?;
+            }
+            if level_present {
+                r.skip_bits(8)?;
+            }
+        }
+
+        r.read_ue()?; // sps_seq_parameter_set_id
+        let chroma_format_idc = r.read_ue()?;
+        if chroma_format_idc == 3 {
+            r.read_bit()?; // separate_colour_plane_flag
+        }
+        let mut width = r.read_ue()?;
+        let mut height = r.read_ue()?;
+        if r.read_bit()? {
+            let sub_width = if chroma_format_idc == 1 || chroma_format_idc == 2 {
+                2
+            } else {
+                1
+            };
+            let sub_height = if chroma_format_idc == 1 { 2 } else { 1 };
+            let left = r.read_ue()?;
+            let right = r.read_ue()?;
+            let top = r.read_ue()?;
+            let bottom = r.read_ue()?;
+            width = width.saturating_sub(sub_width * (left + right));
+            height = height.saturating_sub(sub_height * (top + bottom));
+        }
+        let bit_depth_luma = r.read_ue()? + 8;
+        let bit_depth_chroma = r.read_ue()? + 8;
+
+        Ok(HevcSps {
+            max_sub_layers: max_sub_layers_minus1 as u8 + 1,
+            temporal_id_nesting,
+            profile_tier_level,
+            chroma_format_idc,
+            bit_depth_luma,
+            bit_depth_chroma,
+            width,
+            height,
+        })
+    }
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Result<(), Box<dyn Error>> {
+    let mut last = 8;
+    let mut next = 8;
+    for _ in 0..size {
+        if next != 0 {
+            let delta = r.read_se()?;
+            next = (last + delta + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        decoder::{DecodeReturnCode, VideoDecoder},
+        encoder::{EncodedPacket, VSLRect, VideoEncoder},
+        frame::Frame,
+    };
+    use rand::Rng;
+    use std::io;
+
+    /// The mb_type of an I_PCM macroblock in an I slice.
+    const MB_TYPE_I_PCM: u32 = 25;
+
+    /// Size in bytes of the samples of a 4:2:0 I_PCM macroblock.
+    const PCM_MB_SIZE: usize = 16 * 16 + 2 * 8 * 8;
+
+    #[test]
+    fn test_complete_len() {
+        assert_eq!(complete_len(&[]), 0);
+        assert_eq!(complete_len(&[0, 0, 0, 1, 0x67, 0x42]), 0);
+        assert_eq!(complete_len(&[0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68]), 6);
+        assert_eq!(
+            complete_len(&[0, 0, 1, 0x67, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 1, 0x65]),
+            9
+        );
+        assert_eq!(complete_len(&[0x65, 0x88, 0, 0, 1, 0x41]), 2);
+    }
+
+    #[test]
+    fn test_next_start_code() {
+        let data = [0, 0, 0, 1, 0x67, 0, 0, 1, 0x68];
+        assert_eq!(next_start_code(&data, 0), Some(0));
+        assert_eq!(next_start_code(&data, 1), Some(1));
+        assert_eq!(next_start_code(&data, 4), Some(5));
+        assert_eq!(next_start_code(&data, 6), None);
+    }
+
+    #[test]
+    fn test_split_annexb() {
+        let data = [
+            0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 0, 1, 0x65, 0x88,
+        ];
+        let nals: Vec<_> = split_annexb(&data).collect();
+        assert_eq!(
+            nals,
+            vec![(&data[4..6], 6), (&data[9..11], 12), (&data[16..18], 18)]
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        let rbsp = [0, 0, 0, 1, 0, 0, 2, 0, 0, 4, 0, 0];
+        let mut nal = Vec::new();
+        escape(&rbsp, &mut nal);
+        assert_eq!(nal, [0, 0, 3, 0, 1, 0, 0, 3, 2, 0, 0, 4, 0, 0]);
+        assert_eq!(unescape(&nal), rbsp);
+    }
+
+    #[test]
+    fn test_exp_golomb() {
+        let mut w = BitWriter::new();
+        for value in [0, 1, 2, 7, 25, 1000, 65535] {
+            w.write_ue(value);
+        }
+        for value in [0, 1, -1, 12, -300] {
+            w.write_se(value);
+        }
+        let data = w.finish();
+
+        let mut r = BitReader::new(&data);
+        for value in [0, 1, 2, 7, 25, 1000, 65535] {
+            assert_eq!(r.read_ue().unwrap(), value);
+        }
+        for value in [0, 1, -1, 12, -300] {
+            assert_eq!(r.read_se().unwrap(), value);
+        }
+    }
+
+    /// Builds an HEVC Main profile SPS for the resolution.
+    pub(crate) fn hevc_sps(width: u32, height: u32) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_bits(0, 4); // sps_video_parameter_set_id
+        w.write_bits(0, 3); // sps_max_sub_layers_minus1
+        w.write_bit(true); // sps_temporal_id_nesting_flag
+        w.write_bits(0x01, 8); // general_profile_idc: main
+        w.write_bits(0x6000_0000, 32); // general_profile_compatibility_flags
+        w.write_bits(0x9000, 16); // progressive_source_flag, frame_only_constraint_flag
+        w.write_bits(0, 32);
+        w.write_bits(93, 8); // general_level_idc: 3.1
+        w.write_ue(0); // sps_seq_parameter_set_id
+        w.write_ue(1); // chroma_format_idc
+        w.write_ue(width.next_multiple_of(8));
+        w.write_ue(height.next_multiple_of(8));
+        let crop = height.next_multiple_of(8) - height;
+        w.write_bit(crop > 0);
+        if crop > 0 {
+            w.write_ue(0);
+            w.write_ue(0);
+            w.write_ue(0);
+            w.write_ue(crop / 2);
+        }
+        w.write_ue(0); // bit_depth_luma_minus8
+        w.write_ue(0); // bit_depth_chroma_minus8
+        let mut nal = vec![0x42, 0x01];
+        escape(&w.finish(), &mut nal);
+        nal
+    }
+
+    #[test]
+    fn test_hevc_sps() {
+        let sps = HevcSps::parse(&hevc_sps(1920, 1080)).unwrap();
+        assert_eq!(sps.max_sub_layers, 1);
+        assert!(sps.temporal_id_nesting);
+        assert_eq!(sps.profile_tier_level[0], 0x01);
+        assert_eq!(sps.profile_tier_level[11], 93);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!(sps.bit_depth_luma, 8);
+        assert_eq!(sps.width, 1920);
+        assert_eq!(sps.height, 1080);
+    }
+
+    #[test]
+    fn test_h264_sps() {
+        // Baseline 1280x720 from x264
+        let sps = [
+            0x67, 0x42, 0xc0, 0x1f, 0xda, 0x01, 0x40, 0x16, 0xe8, 0x40, 0x00, 0x00, 0x03, 0x00,
+            0x40, 0x00, 0x00, 0x0f, 0x23, 0xc6, 0x0c, 0xa8,
+        ];
+        let sps = H264Sps::parse(&sps).unwrap();
+        assert_eq!(sps.profile_idc, 66);
+        assert_eq!(sps.level_idc, 31);
+        assert_eq!(sps.width(), 1280);
+        assert_eq!(sps.height(), 720);
+    }
+
+    /// An 8-bit planar 4:2:0 picture, the intermediate representation between
+    /// frames and the I_PCM bitstream.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Picture {
+        width: usize,
+        height: usize,
+        y: Vec<u8>,
+        u: Vec<u8>,
+        v: Vec<u8>,
+    }
+
+    impl Picture {
+        fn new(width: usize, height: usize) -> Self {
+            Picture {
+                width,
+                height,
+                y: vec![0; width * height],
+                u: vec![128; width * height / 4],
+                v: vec![128; width * height / 4],
+            }
+        }
+
+        fn random(width: usize, height: usize) -> Self {
+            use rand::Rng;
+            let mut rng = rand::rng();
+            let mut pic = Picture::new(width, height);
+            rng.fill(&mut pic.y[..]);
+            rng.fill(&mut pic.u[..]);
+            rng.fill(&mut pic.v[..]);
+            pic
+        }
+
+        /// Reads the picture from a mapped frame, taking the chroma of the
+        /// top left pixel of each block.
+        fn read(frame: &Frame) -> Result<Self, Box<dyn Error>> {
+            let layout = frame.layout()?;
+            if !layout.width.is_multiple_of(2) || !layout.height.is_multiple_of(2) {
+                return Err("pcm encoder requires even frame dimensions".into());
+            }
+            let data = frame
+                .mmap()
+                .map_err(|_| io::Error::other("failed to map source frame"))?;
+            layout.check(data)?;
+
+            let mut pic = Picture::new(layout.width, layout.height);
+            for y in 0..pic.height {
+                for x in 0..pic.width {
+                    let [luma, u, v] = layout.yuv(data, x, y);
+                    pic.y[y * pic.width + x] = luma;
+                    if x.is_multiple_of(2) && y.is_multiple_of(2) {
+                        let chroma = y / 2 * (pic.width / 2) + x / 2;
+                        pic.u[chroma] = u;
+                        pic.v[chroma] = v;
+                    }
+                }
+            }
+            Ok(pic)
+        }
+
+        fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Picture {
+            let mut pic = Picture::new(width, height);
+            for row in 0..height {
+                let src = (y + row) * self.width + x;
+                pic.y[row * width..(row + 1) * width].copy_from_slice(&self.y[src..src + width]);
+            }
+            let (cw, ch) = (width / 2, height / 2);
+            for row in 0..ch {
+                let src = (y / 2 + row) * (self.width / 2) + x / 2;
+                pic.u[row * cw..(row + 1) * cw].copy_from_slice(&self.u[src..src + cw]);
+                pic.v[row * cw..(row + 1) * cw].copy_from_slice(&self.v[src..src + cw]);
+            }
+            pic
+        }
+
+        /// Writes the picture into a newly allocated NV12 frame.
+        fn to_frame(&self) -> Result<Frame, Box<dyn Error>> {
+            let frame = Frame::new(self.width as u32, self.height as u32, 0, "NV12")?;
+            frame.alloc(None)?;
+            let layout = frame.layout()?;
+            let data = frame
+                .mmap_mut()
+                .map_err(|_| io::Error::other("failed to map decoded frame"))?;
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let chroma = y / 2 * (self.width / 2) + x / 2;
+                    let yuv = [self.y[y * self.width + x], self.u[chroma], self.v[chroma]];
+                    layout.set_yuv(data, x, y, yuv);
+                }
+            }
+            Ok(frame)
+        }
+    }
+
+    /// The PcmEncoder produces a standard H.264 Constrained Baseline stream in
+    /// which every frame is an IDR picture made of I_PCM macroblocks.  The
+    /// samples are stored uncompressed so the encoding is lossless, which lets
+    /// the muxer, recorder and streaming tests check the frames end to end.
+    #[derive(Debug, Default)]
+    pub(crate) struct PcmEncoder {
+        idr_pic_id: u32,
+    }
+
+    impl PcmEncoder {
+        pub(crate) fn new() -> Self {
+            PcmEncoder::default()
+        }
+
+        fn encode_picture(&mut self, pic: &Picture) -> Vec<u8> {
+            let width_mbs = pic.width.div_ceil(16);
+            let height_mbs = pic.height.div_ceil(16);
+            let mut out = Vec::with_capacity(width_mbs * height_mbs * (PCM_MB_SIZE + 1) + 64);
+
+            write_nal(&mut out, 0x67, &baseline_sps(pic.width, pic.height));
+            write_nal(&mut out, 0x68, &baseline_pps());
+
+            let mut w = BitWriter::new();
+            w.write_ue(0); // first_mb_in_slice
+            w.write_ue(7); // slice_type: I, all slices of the picture
+            w.write_ue(0); // pic_parameter_set_id
+            w.write_bits(0, 4); // frame_num
+            w.write_ue(self.idr_pic_id);
+            w.write_bit(false); // no_output_of_prior_pics_flag
+            w.write_bit(false); // long_term_reference_flag
+            w.write_se(0); // slice_qp_delta
+            w.write_ue(1); // disable_deblocking_filter_idc
+
+            let mut samples = [0u8; PCM_MB_SIZE];
+            for mb_y in 0..height_mbs {
+                for mb_x in 0..width_mbs {
+                    w.write_ue(MB_TYPE_I_PCM);
+                    while !w.is_aligned() {
+                        w.write_bit(false);
+                    }
+                    read_macroblock(pic, mb_x, mb_y, &mut samples);
+                    w.write_bytes(&samples);
+                }
+            }
+            write_nal(&mut out, 0x65, &w.finish());
+
+            // Consecutive IDR pictures must use differing identifiers.
+            self.idr_pic_id = (self.idr_pic_id + 1) % 2;
+            out
+        }
+    }
+
+    impl VideoEncoder for PcmEncoder {
+        fn encode(&mut self, source: &Frame) -> Result<EncodedPacket, Box<dyn Error>> {
+            let pic = Picture::read(source)?;
+            Ok(EncodedPacket {
+                data: self.encode_picture(&pic),
+                pts: source.pts(),
+                dts: source.dts(),
+                duration: source.duration(),
+                keyframe: true,
+            })
+        }
+    }
+
+    /// The PcmDecoder decodes the single slice I_PCM pictures written by
+    /// [`PcmEncoder`] into NV12 frames.
+    #[derive(Debug, Default)]
+    pub(crate) struct PcmDecoder {
+        sps: Option<H264Sps>,
+    }
+
+    impl PcmDecoder {
+        pub(crate) fn new() -> Self {
+            PcmDecoder::default()
+        }
+
+        fn decode_picture(
+            &mut self,
+            data: &[u8],
+        ) -> Result<(DecodeReturnCode, usize, Option<Picture>), Box<dyn Error>> {
+            let mut code = DecodeReturnCode::Success;
+            for (nal, end) in split_annexb(data) {
+                match h264_nal_type(nal) {
+                    H264_NAL_SPS => {
+                        let sps = H264Sps::parse(nal)?;
+                        if self.sps.as_ref() != Some(&sps) {
+                            code = DecodeReturnCode::Initialized;
+                        }
+                        self.sps = Some(sps);
+                    }
+                    H264_NAL_IDR => {
+                        let pic = self.decode_slice(nal)?;
+                        if code == DecodeReturnCode::Success {
+                            code = DecodeReturnCode::FrameDecoded;
+                        }
+                        return Ok((code, end, Some(pic)));
+                    }
+                    _ => (),
+                }
+            }
+            Ok((code, data.len(), None))
+        }
+
+        /// Decodes the IDR slice, relying on the header layout written by
+        /// [`PcmEncoder`].
+        fn decode_slice(&self, nal: &[u8]) -> Result<Picture, Box<dyn Error>> {
+            let sps = self.sps.as_ref().ok_or("slice received before the SPS")?;
+            let rbsp = unescape(&nal[1..]);
+            let mut r = BitReader::new(&rbsp);
+            if r.read_ue()? != 0 {
+                return Err("pcm decoder only supports a single slice per picture".into());
+            }
+            r.read_ue()?; // slice_type
+            r.read_ue()?; // pic_parameter_set_id
+            r.read_bits(sps.log2_max_frame_num)?; // frame_num
+            r.read_ue()?; // idr_pic_id
+            r.skip_bits(2)?; // dec_ref_pic_marking
+            r.read_se()?; // slice_qp_delta
+            r.read_ue()?; // disable_deblocking_filter_idc
+
+            let width_mbs = sps.width_mbs as usize;
+            let height_mbs = sps.height_mbs as usize;
+            let mut padded = Picture::new(width_mbs * 16, height_mbs * 16);
+            for mb in 0..width_mbs * height_mbs {
+                if r.read_ue()? != MB_TYPE_I_PCM {
+                    return Err("pcm decoder only supports I_PCM macroblocks".into());
+                }
+                r.align();
+                let samples = r.read_bytes(PCM_MB_SIZE)?;
+                write_macroblock(&mut padded, mb % width_mbs, mb / width_mbs, samples);
+            }
+
+            let (left, _, top, _) = sps.crop;
+            Ok(padded.crop(
+                left as usize,
+                top as usize,
+                sps.width() as usize,
+                sps.height() as usize,
+            ))
+        }
+    }
+
+    impl VideoDecoder for PcmDecoder {
+        fn decode_frame(
+            &mut self,
+            data: &[u8],
+        ) -> Result<(DecodeReturnCode, usize, Option<Frame>), Box<dyn Error>> {
+            let (code, used, pic) = self.decode_picture(data)?;
+            let frame = match pic {
+                Some(pic) => Some(pic.to_frame()?),
+                None => None,
+            };
+            Ok((code, used, frame))
+        }
+
+        fn flush(&mut self) -> Result<Vec<Frame>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        fn width(&self) -> i32 {
+            self.sps.as_ref().map_or(0, |sps| sps.width() as i32)
+        }
+
+        fn height(&self) -> i32 {
+            self.sps.as_ref().map_or(0, |sps| sps.height() as i32)
+        }
+
+        fn crop(&self) -> VSLRect {
+            VSLRect::new(0, 0, self.width(), self.height())
+        }
+    }
+
+    /// Appends the NAL unit with a four byte start code.
+    pub(crate) fn write_nal(out: &mut Vec<u8>, header: u8, rbsp: &[u8]) {
+        out.extend_from_slice(&[0, 0, 0, 1, header]);
+        escape(rbsp, out);
+    }
+
+    /// Builds a Constrained Baseline SPS for the resolution, cropping the
+    /// padding of partial macroblocks.
+    pub(crate) fn baseline_sps(width: usize, height: usize) -> Vec<u8> {
+        let width_mbs = width.div_ceil(16);
+        let height_mbs = height.div_ceil(16);
+        let crop_right = (width_mbs * 16 - width) / 2;
+        let crop_bottom = (height_mbs * 16 - height) / 2;
+
+        let mut w = BitWriter::new();
+        w.write_bits(66, 8); // profile_idc: baseline
+        w.write_bits(0xc0, 8); // constraint_set0_flag, constraint_set1_flag
+        w.write_bits(51, 8); // level_idc
+        w.write_ue(0); // seq_parameter_set_id
+        w.write_ue(0); // log2_max_frame_num_minus4
+        w.write_ue(2); // pic_order_cnt_type
+        w.write_ue(1); // max_num_ref_frames
+        w.write_bit(false); // gaps_in_frame_num_value_allowed_flag
+        w.write_ue(width_mbs as u32 - 1);
+        w.write_ue(height_mbs as u32 - 1);
+        w.write_bit(true); // frame_mbs_only_flag
+        w.write_bit(true); // direct_8x8_inference_flag
+        if crop_right > 0 || crop_bottom > 0 {
+            w.write_bit(true);
+            w.write_ue(0);
+            w.write_ue(crop_right as u32);
+            w.write_ue(0);
+            w.write_ue(crop_bottom as u32);
+        } else {
+            w.write_bit(false);
+        }
+        w.write_bit(false); // vui_parameters_present_flag
+        w.finish()
+    }
+
+    /// Builds a CAVLC PPS with the deblocking filter control present.
+    pub(crate) fn baseline_pps() -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_ue(0); // pic_parameter_set_id
+        w.write_ue(0); // seq_parameter_set_id
+        w.write_bit(false); // entropy_coding_mode_flag
+        w.write_bit(false); // bottom_field_pic_order_in_frame_present_flag
+        w.write_ue(0); // num_slice_groups_minus1
+        w.write_ue(0); // num_ref_idx_l0_default_active_minus1
+        w.write_ue(0); // num_ref_idx_l1_default_active_minus1
+        w.write_bit(false); // weighted_pred_flag
+        w.write_bits(0, 2); // weighted_bipred_idc
+        w.write_se(0); // pic_init_qp_minus26
+        w.write_se(0); // pic_init_qs_minus26
+        w.write_se(0); // chroma_qp_index_offset
+        w.write_bit(true); // deblocking_filter_control_present_flag
+        w.write_bit(false); // constrained_intra_pred_flag
+        w.write_bit(false); // redundant_pic_cnt_present_flag
+        w.finish()
+    }
+
+    /// The SPS of a 1280x720 Constrained Baseline stream and its PPS, as
+    /// written by an encoder, for the container tests.
+    pub(crate) const SPS: [u8; 22] = [
+        0x67, 0x42, 0xc0, 0x1f, 0xda, 0x01, 0x40, 0x16, 0xe8, 0x40, 0x00, 0x00, 0x03, 0x00, 0x40,
+        0x00, 0x00, 0x0f, 0x23, 0xc6, 0x0c, 0xa8,
+    ];
+    pub(crate) const PPS: [u8; 4] = [0x68, 0xce, 0x3c, 0x80];
+
+    /// Builds a packet of a placeholder slice whose last byte is 0x80 | index,
+    /// led by the [`SPS`] and [`PPS`] on keyframes.  The packets are timed
+    /// every duration from the start.
+    pub(crate) fn packet(index: u8, keyframe: bool, start: i64, duration: i64) -> EncodedPacket {
+        let mut data = Vec::new();
+        if keyframe {
+            for nal in [&SPS[..], &PPS[..]] {
+                data.extend_from_slice(&[0, 0, 0, 1]);
+                data.extend_from_slice(nal);
+            }
+        }
+        data.extend_from_slice(&[0, 0, 0, 1, if keyframe { 0x65 } else { 0x41 }, 0x80 | index]);
+        EncodedPacket {
+            data,
+            pts: start + index as i64 * duration,
+            dts: start + index as i64 * duration,
+            duration,
+            keyframe,
+        }
+    }
+
+    /// Encodes a 32x16 frame of noise with the [`PcmEncoder`] and repeats it
+    /// at 30 frames per second, with a keyframe every gop packets.
+    pub(crate) fn pcm_packets(
+        count: usize,
+        gop: usize,
+    ) -> Result<Vec<EncodedPacket>, Box<dyn Error>> {
+        let mut encoder = PcmEncoder::new();
+        let frame = Frame::new(32, 16, 0, "NV12")?;
+        frame.alloc(None)?;
+        rand::rng().fill(frame.mmap_mut().unwrap());
+        let mut packet = encoder.encode(&frame)?;
+        let mut out = Vec::new();
+        for i in 0..count {
+            packet.keyframe = i % gop == 0;
+            packet.pts = i as i64 * 33_333_333;
+            packet.dts = packet.pts;
+            out.push(packet.clone());
+        }
+        Ok(out)
+    }
+
+    /// Gathers the macroblock samples, replicating the picture edges into the
+    /// padding of partial macroblocks.
+    fn read_macroblock(pic: &Picture, mb_x: usize, mb_y: usize, samples: &mut [u8; PCM_MB_SIZE]) {
+        let (cw, ch) = (pic.width / 2, pic.height / 2);
+        for y in 0..16 {
+            let row = (mb_y * 16 + y).min(pic.height - 1);
+            for x in 0..16 {
+                let col = (mb_x * 16 + x).min(pic.width - 1);
+                samples[y * 16 + x] = pic.y[row * pic.width + col];
+            }
+        }
+        for y in 0..8 {
+            let row = (mb_y * 8 + y).min(ch - 1);
+            for x in 0..8 {
+                let col = (mb_x * 8 + x).min(cw - 1);
+                samples[256 + y * 8 + x] = pic.u[row * cw + col];
+                samples[320 + y * 8 + x] = pic.v[row * cw + col];
+            }
+        }
+    }
+
+    fn write_macroblock(pic: &mut Picture, mb_x: usize, mb_y: usize, samples: &[u8]) {
+        let cw = pic.width / 2;
+        for y in 0..16 {
+            let dst = (mb_y * 16 + y) * pic.width + mb_x * 16;
+            pic.y[dst..dst + 16].copy_from_slice(&samples[y * 16..y * 16 + 16]);
+        }
+        for y in 0..8 {
+            let dst = (mb_y * 8 + y) * cw + mb_x * 8;
+            pic.u[dst..dst + 8].copy_from_slice(&samples[256 + y * 8..256 + y * 8 + 8]);
+            pic.v[dst..dst + 8].copy_from_slice(&samples[320 + y * 8..320 + y * 8 + 8]);
+        }
+    }
+
+    #[test]
+    fn test_pcm_roundtrip() {
+        let mut encoder = PcmEncoder::new();
+        let mut decoder = PcmDecoder::new();
+
+        for (width, height) in [(64, 48), (70, 38)] {
+            let pic = Picture::random(width, height);
+            let data = encoder.encode_picture(&pic);
+
+            let (code, used, decoded) = decoder.decode_picture(&data).unwrap();
+            assert_eq!(code, DecodeReturnCode::Initialized);
+            assert_eq!(used, data.len());
+            assert_eq!(decoder.width(), width as i32);
+            assert_eq!(decoder.height(), height as i32);
+            assert_eq!(decoded.unwrap(), pic);
+
+            let pic = Picture::random(width, height);
+            let data = encoder.encode_picture(&pic);
+            let (code, _, decoded) = decoder.decode_picture(&data).unwrap();
+            assert_eq!(code, DecodeReturnCode::FrameDecoded);
+            assert_eq!(decoded.unwrap(), pic);
+        }
+    }
+
+    #[test]
+    fn test_baseline_sps() {
+        let mut data = Vec::new();
+        write_nal(&mut data, 0x67, &baseline_sps(1920, 1080));
+        let sps = H264Sps::parse(&data[4..]).unwrap();
+        assert_eq!(sps.profile_idc, 66);
+        assert_eq!(sps.width_mbs, 120);
+        assert_eq!(sps.height_mbs, 68);
+        assert_eq!(sps.width(), 1920);
+        assert_eq!(sps.height(), 1080);
+    }
+}
@@ -0,0 +1,546 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    camera::{Camera, CameraBuffer},
+    fourcc::FourCC,
+    frame::Frame,
+    host::Host,
+    source::FrameSource,
+};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// The number of frames which may wait between capture and posting before
+/// frames are dropped.
+const QUEUE_DEPTH: usize = 2;
+
+/// How long the capture thread waits for a frame before checking for stop.
+const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long the host thread waits for client activity between frames.
+const SERVICE_WAIT: i64 = 2;
+
+/// The statistics of a capture pipeline since it was started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    /// Frames read from the camera or source.
+    pub captured: u64,
+    /// Frames posted to the host.
+    pub posted: u64,
+    /// Frames dropped because the host fell behind the capture.
+    pub dropped: u64,
+    /// Failed reads, conversions and posts.
+    pub errors: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    captured: AtomicU64,
+    posted: AtomicU64,
+    dropped: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// A frame on its way from the capture thread to the host thread.
+struct Captured {
+    frame: Frame,
+    duration: i64,
+    pts: i64,
+    /// Receives the expiry of a frame sharing a camera buffer once it is
+    /// posted, the camera buffer is held until then.
+    expires: Option<Arc<AtomicI64>>,
+}
+
+/// Where the pipeline reads its frames from.
+enum Input {
+    Camera(Camera),
+    Source(Arc<Mutex<Box<dyn FrameSource + Send>>>),
+}
+
+/// The CapturePipeline publishes the frames of a camera or any other
+/// [`FrameSource`] through a host, running the capture and the servicing of
+/// the host's clients on their own threads.
+///
+/// Camera buffers are posted without copying unless an output format or
+/// resolution is requested, in which case each frame is converted into a
+/// newly allocated frame.  A posted camera buffer is only returned to the
+/// camera once its frame expires, so a lifetime longer than the time it takes
+/// the camera to cycle through its buffers lowers the capture rate.
+///
+/// ```no_run
+/// use videostream::{camera::create_camera, fourcc::FourCC, pipeline::CapturePipeline};
+///
+/// let camera = create_camera().with_device("/dev/video3");
+/// let mut pipeline =
+///     CapturePipeline::new(camera, "/tmp/camera.vsl").with_output(640, 480, FourCC(*b"RGBA"));
+/// pipeline.start()?;
+/// std::thread::sleep(std::time::Duration::from_secs(10));
+/// pipeline.stop()?;
+/// println!("{:?}", pipeline.stats());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct CapturePipeline {
+    input: Input,
+    path: PathBuf,
+    output: Option<(u32, u32, FourCC)>,
+    lifetime: Duration,
+    running: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+    threads: Vec<JoinHandle<Result<(), String>>>,
+}
+
+impl CapturePipeline {
+    /// Creates the pipeline from the camera settings and the path of the host
+    /// socket, which are opened when the pipeline is started.
+    pub fn new<P: AsRef<Path>>(camera: Camera, path: P) -> Self {
+        Self::with_input(Input::Camera(camera), path)
+    }
+
+    /// Creates the pipeline from a frame source, such as a
+    /// [`SyntheticSource`](crate::source::SyntheticSource) or a
+    /// [`FileSource`](crate::source::FileSource), and the path of the host
+    /// socket.  The source frames are always posted as their own frames and
+    /// the pipeline stops once a finite source ends.
+    pub fn from_source<S, P>(source: S, path: P) -> Self
+    where
+        S: FrameSource + Send + 'static,
+        P: AsRef<Path>,
+    {
+        Self::with_input(Input::Source(Arc::new(Mutex::new(Box::new(source)))), path)
+    }
+
+    fn with_input<P: AsRef<Path>>(input: Input, path: P) -> Self {
+        CapturePipeline {
+            input,
+            path: path.as_ref().to_owned(),
+            output: None,
+            lifetime: Duration::from_millis(100),
+            running: Arc::new(AtomicBool::new(false)),
+            counters: Arc::new(Counters::default()),
+            threads: Vec::new(),
+        }
+    }
+
+    /// Converts the captured frames to the resolution and format before they
+    /// are posted, using the accelerated frame copy.
+    pub fn with_output(mut self, width: u32, height: u32, fourcc: FourCC) -> Self {
+        self.output = Some((width, height, fourcc));
+        self
+    }
+
+    /// Sets how long posted frames remain available to clients, the default
+    /// is 100ms.
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Starts the host and capture threads, returning once both the host and
+    /// the camera are open or failed to open.
+    pub fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.is_running() {
+            return Err("capture pipeline is already running".into());
+        }
+        // Reap the threads of a previous run which stopped on its own.
+        let _ = self.stop();
+        for counter in [
+            &self.counters.captured,
+            &self.counters.posted,
+            &self.counters.dropped,
+            &self.counters.errors,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.running.store(true, Ordering::SeqCst);
+
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (frame_tx, frame_rx) = mpsc::sync_channel(QUEUE_DEPTH);
+
+        let path = self.path.clone();
+        let lifetime = self.lifetime.as_nanos() as i64;
+        let running = self.running.clone();
+        let counters = self.counters.clone();
+        let ready = ready_tx.clone();
+        self.threads.push(thread::spawn(move || {
+            host_thread(&path, lifetime, frame_rx, &running, &counters, ready)
+        }));
+
+        let output = self.output;
+        let running = self.running.clone();
+        let counters = self.counters.clone();
+        self.threads.push(match &self.input {
+            Input::Camera(camera) => {
+                let camera = camera.clone();
+                thread::spawn(move || {
+                    capture_thread(camera, output, frame_tx, &running, &counters, ready_tx)
+                })
+            }
+            Input::Source(source) => {
+                let source = source.clone();
+                thread::spawn(move || {
+                    source_thread(&source, output, frame_tx, &running, &counters, ready_tx)
+                })
+            }
+        });
+
+        for _ in 0..self.threads.len() {
+            if let Ok(Err(err)) | Err(err) = ready_rx.recv().map_err(|err| err.to_string()) {
+                let _ = self.stop();
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops the threads and returns the error which stopped either of them,
+    /// if any.
+    pub fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.running.store(false, Ordering::SeqCst);
+        let mut result = Ok(());
+        for thread in self.threads.drain(..) {
+            let status = thread
+                .join()
+                .unwrap_or_else(|_| Err("capture pipeline thread panicked".to_owned()));
+            if let Err(err) = status {
+                result = result.and(Err(err.into()));
+            }
+        }
+        result
+    }
+
+    /// Returns true while both threads are running.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+            && !self.threads.is_empty()
+            && self.threads.iter().all(|thread| !thread.is_finished())
+    }
+
+    pub fn stats(&self) -> PipelineStats {
+        PipelineStats {
+            captured: self.counters.captured.load(Ordering::Relaxed),
+            posted: self.counters.posted.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            errors: self.counters.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for CapturePipeline {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Posts the captured frames to the host and services its clients.
+fn host_thread(
+    path: &Path,
+    lifetime: i64,
+    frames: Receiver<Captured>,
+    running: &AtomicBool,
+    counters: &Counters,
+    ready: mpsc::Sender<Result<(), String>>,
+) -> Result<(), String> {
+    let host = match Host::new(path) {
+        Ok(host) => {
+            let _ = ready.send(Ok(()));
+            host
+        }
+        Err(err) => {
+            let _ = ready.send(Err(err.to_string()));
+            running.store(false, Ordering::SeqCst);
+            return Ok(());
+        }
+    };
+
+    while running.load(Ordering::SeqCst) {
+        loop {
+            match frames.try_recv() {
+                Ok(captured) => {
+                    let expires = crate::timestamp() + lifetime;
+                    let posted = host.post(
+                        captured.frame,
+                        expires,
+                        captured.duration,
+                        captured.pts,
+                        captured.pts,
+                    );
+                    let expires = match posted {
+                        Ok(_) => {
+                            counters.posted.fetch_add(1, Ordering::Relaxed);
+                            expires
+                        }
+                        Err(_) => {
+                            counters.errors.fetch_add(1, Ordering::Relaxed);
+                            0
+                        }
+                    };
+                    if let Some(release) = captured.expires {
+                        release.store(expires, Ordering::Release);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    running.store(false, Ordering::SeqCst);
+                    return Ok(());
+                }
+            }
+        }
+
+        match host.poll(SERVICE_WAIT) {
+            // Client errors such as a disconnect are not fatal to the host.
+            Ok(ready) if ready > 0 => {
+                let _ = host.process();
+            }
+            Ok(_) => (),
+            Err(err) => {
+                running.store(false, Ordering::SeqCst);
+                return Err(err.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the camera frames, converting them if requested, and queues them
+/// for the host thread.
+fn capture_thread(
+    camera: Camera,
+    output: Option<(u32, u32, FourCC)>,
+    frames: SyncSender<Captured>,
+    running: &AtomicBool,
+    counters: &Counters,
+    ready: mpsc::Sender<Result<(), String>>,
+) -> Result<(), String> {
+    let reader = match camera.open().and_then(|reader| {
+        reader.start()?;
+        Ok(reader)
+    }) {
+        Ok(reader) => {
+            let _ = ready.send(Ok(()));
+            reader
+        }
+        Err(err) => {
+            let _ = ready.send(Err(err.to_string()));
+            running.store(false, Ordering::SeqCst);
+            return Ok(());
+        }
+    };
+    let duration = match reader.frame_interval() {
+        Ok((numerator, denominator)) if denominator > 0 => {
+            numerator as i64 * 1_000_000_000 / denominator as i64
+        }
+        _ => 0,
+    };
+    // The camera buffers shared by queued and posted frames, oldest first,
+    // with the expiry of their frames which is i64::MAX until posted.
+    let mut held: VecDeque<(CameraBuffer<'_>, Arc<AtomicI64>)> = VecDeque::new();
+
+    while running.load(Ordering::SeqCst) {
+        let now = crate::timestamp();
+        while held
+            .front()
+            .is_some_and(|(_, expires)| expires.load(Ordering::Acquire) <= now)
+        {
+            held.pop_front();
+        }
+        if !held.is_empty() && reader.queued_buffers() == 0 {
+            // Every buffer is held by a frame, wait for the oldest to expire.
+            let expires = held[0].1.load(Ordering::Acquire);
+            thread::sleep(match expires {
+                i64::MAX => Duration::from_millis(SERVICE_WAIT as u64),
+                expires => Duration::from_nanos((expires - now) as u64),
+            });
+            continue;
+        }
+
+        let buffer = match reader.read_timeout(CAPTURE_TIMEOUT) {
+            Ok(buffer) => buffer,
+            Err(err) => match err.downcast_ref::<io::Error>() {
+                Some(err) if err.kind() == io::ErrorKind::TimedOut => continue,
+                _ => {
+                    running.store(false, Ordering::SeqCst);
+                    return Err(err.to_string());
+                }
+            },
+        };
+        counters.captured.fetch_add(1, Ordering::Relaxed);
+
+        let timestamp = buffer.timestamp();
+        let pts = timestamp.seconds() * 1_000_000_000 + timestamp.subsec(9) as i64;
+        let frame = Frame::try_from(&buffer).and_then(|frame| convert(frame, output));
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(_) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+
+        // Converted frames are copies, their buffer is released right away.
+        let expires = output.is_none().then(|| Arc::new(AtomicI64::new(i64::MAX)));
+        let captured = Captured {
+            frame,
+            duration,
+            pts,
+            expires: expires.clone(),
+        };
+        match queue(&frames, captured, counters) {
+            Some(true) => {
+                if let Some(expires) = expires {
+                    held.push_back((buffer, expires));
+                }
+            }
+            Some(false) => (),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Reads the frames of the source, converting them if requested, and queues
+/// them for the host thread until the source ends.
+fn source_thread(
+    source: &Mutex<Box<dyn FrameSource + Send>>,
+    output: Option<(u32, u32, FourCC)>,
+    frames: SyncSender<Captured>,
+    running: &AtomicBool,
+    counters: &Counters,
+    ready: mpsc::Sender<Result<(), String>>,
+) -> Result<(), String> {
+    let mut source = match source.lock() {
+        Ok(source) => {
+            let _ = ready.send(Ok(()));
+            source
+        }
+        Err(_) => {
+            let _ = ready.send(Err("frame source is poisoned".to_owned()));
+            running.store(false, Ordering::SeqCst);
+            return Ok(());
+        }
+    };
+
+    while running.load(Ordering::SeqCst) {
+        let read = match source.read_frame() {
+            Ok(Some(read)) => read,
+            // The host thread posts the queued frames before it stops.
+            Ok(None) => break,
+            Err(err) => {
+                running.store(false, Ordering::SeqCst);
+                return Err(err.to_string());
+            }
+        };
+        counters.captured.fetch_add(1, Ordering::Relaxed);
+
+        let frame = match convert(read.frame, output) {
+            Ok(frame) => frame,
+            Err(_) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+        let captured = Captured {
+            frame,
+            duration: read.duration,
+            pts: read.timestamp,
+            expires: None,
+        };
+        if queue(&frames, captured, counters).is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Converts the frame to the output resolution and format, if any, into a
+/// newly allocated frame.
+fn convert(frame: Frame, output: Option<(u32, u32, FourCC)>) -> Result<Frame, Box<dyn Error>> {
+    match output {
+        Some((width, height, fourcc)) => {
+            let converted = Frame::new(width, height, 0, &fourcc.to_string())?;
+            converted.alloc(None)?;
+            converted.copy_from(&frame, None)?;
+            Ok(converted)
+        }
+        None => Ok(frame),
+    }
+}
+
+/// Queues the frame for the host thread, counting it as dropped when the
+/// queue is full.  Returns whether the frame was queued, or None once the
+/// host thread has stopped.
+fn queue(frames: &SyncSender<Captured>, captured: Captured, counters: &Counters) -> Option<bool> {
+    match frames.try_send(captured) {
+        Ok(()) => Some(true),
+        Err(TrySendError::Full(_)) => {
+            counters.dropped.fetch_add(1, Ordering::Relaxed);
+            Some(false)
+        }
+        Err(TrySendError::Disconnected(_)) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{camera::create_camera, client::Client, source::SyntheticSource};
+
+    #[test]
+    fn test_open_failure() {
+        let camera = create_camera().with_device("/dev/null/video");
+        let path = std::env::temp_dir().join(format!("pipeline-{}.vsl", std::process::id()));
+        let mut pipeline = CapturePipeline::new(camera, &path);
+        assert!(pipeline.start().is_err());
+        assert!(!pipeline.is_running());
+        assert_eq!(pipeline.stats(), PipelineStats::default());
+        assert!(pipeline.stop().is_ok());
+    }
+
+    #[test]
+    fn test_source() {
+        // Frames are generated as fast as they are read so the host falls
+        // behind and frames are dropped.
+        let source = SyntheticSource::new(FourCC(*b"NV12"))
+            .unwrap()
+            .with_resolution(64, 48)
+            .unwrap()
+            .with_realtime(false);
+        let path = std::env::temp_dir().join(format!("pipeline-source-{}.vsl", std::process::id()));
+        let mut pipeline =
+            CapturePipeline::from_source(source, &path).with_output(32, 24, FourCC(*b"RGBA"));
+        pipeline.start().unwrap();
+        assert!(pipeline.is_running());
+
+        let client = Client::new(path.to_str().unwrap(), false).unwrap();
+        client.set_timeout(1.0);
+        for _ in 0..5 {
+            let frame = client.get_frame(0).unwrap();
+            assert_eq!(frame.width(), 32);
+            assert_eq!(frame.height(), 24);
+            assert_eq!(frame.fourcc(), u32::from(FourCC(*b"RGBA")));
+        }
+        drop(client);
+
+        pipeline.stop().unwrap();
+        assert!(!pipeline.is_running());
+        let stats = pipeline.stats();
+        assert!(stats.posted >= 5);
+        assert!(stats.dropped > 0);
+        assert_eq!(stats.errors, 0);
+        // Up to a full queue and the frame read as the host stopped are
+        // neither posted nor dropped.
+        let pending = stats.captured - stats.posted - stats.dropped;
+        assert!(pending <= QUEUE_DEPTH as u64 + 1);
+    }
+}
@@ -0,0 +1,723 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    client::Client,
+    encoder::{EncodedPacket, Encoder, VideoEncoder},
+    fourcc::FourCC,
+    frame::Frame,
+    mkv::MkvWriter,
+    mp4::{check_codec, AccessUnit, Mp4Writer, ParameterSets},
+};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fs,
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// The PacketWriter trait abstracts the container formats so that recordings
+/// can be written to either MP4 or Matroska files.
+pub trait PacketWriter {
+    fn write(&mut self, packet: &EncodedPacket) -> Result<(), Box<dyn Error>>;
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>>;
+
+    fn bytes_written(&self) -> u64;
+}
+
+impl<W: Write> PacketWriter for Mp4Writer<W> {
+    fn write(&mut self, packet: &EncodedPacket) -> Result<(), Box<dyn Error>> {
+        Mp4Writer::write(self, packet)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Mp4Writer::finish(self)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        Mp4Writer::bytes_written(self)
+    }
+}
+
+impl<W: Write + Seek> PacketWriter for MkvWriter<W> {
+    fn write(&mut self, packet: &EncodedPacket) -> Result<(), Box<dyn Error>> {
+        MkvWriter::write(self, packet)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        MkvWriter::finish(self)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        MkvWriter::bytes_written(self)
+    }
+}
+
+/// Returns the packet led by the parameter sets in Annex-B format.
+fn with_parameter_sets(packet: &EncodedPacket, params: &ParameterSets) -> EncodedPacket {
+    let mut data = Vec::new();
+    for nal in params.vps.iter().chain(&params.sps).chain(&params.pps) {
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(nal);
+    }
+    data.extend_from_slice(&packet.data);
+    EncodedPacket {
+        data,
+        ..packet.clone()
+    }
+}
+
+/// Container format of the recorded segments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+    Mp4,
+    Mkv,
+}
+
+impl Container {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "mkv",
+        }
+    }
+}
+
+struct Segment {
+    path: PathBuf,
+    writer: Box<dyn PacketWriter + Send>,
+    started: Instant,
+}
+
+/// The SegmentWriter records encoded video into a sequence of segment files
+/// named `<prefix>_<index>.<extension>` in a directory.
+///
+/// A new segment is started once the current segment reaches the configured
+/// wall-clock duration or size, the switch happens on the next keyframe so
+/// that every segment can be played independently.  Every packet is written
+/// to exactly one segment, the keyframe triggering the rotation being the
+/// first packet of the new segment, so no frames are lost at a boundary.
+/// Segments may therefore exceed the limits by up to one group of pictures.
+/// The last parameter sets of the stream are added to the keyframe starting
+/// a segment when the encoder does not repeat them.
+///
+/// When a quota is configured the oldest segments in the directory are
+/// deleted whenever a new segment is started to keep the total size of the
+/// segments within the quota.
+pub struct SegmentWriter {
+    directory: PathBuf,
+    prefix: String,
+    codec: FourCC,
+    container: Container,
+    duration: Option<Duration>,
+    size: Option<u64>,
+    quota: Option<u64>,
+    index: Option<u64>,
+    current: Option<Segment>,
+    params: ParameterSets,
+}
+
+impl SegmentWriter {
+    /// Creates a segment writer for the codec, H264 or HEVC, writing into the
+    /// directory which is created if required.  Segment numbering continues
+    /// after any segments already present in the directory.
+    pub fn new<P: AsRef<Path>>(directory: P, codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        check_codec(codec)?;
+        fs::create_dir_all(directory.as_ref())?;
+        Ok(SegmentWriter {
+            directory: directory.as_ref().to_path_buf(),
+            prefix: "segment".to_owned(),
+            codec,
+            container: Container::Mp4,
+            duration: None,
+            size: None,
+            quota: None,
+            index: None,
+            current: None,
+            params: ParameterSets::default(),
+        })
+    }
+
+    /// Sets the container format of the segments, the default is MP4.
+    pub fn with_container(mut self, container: Container) -> Self {
+        self.container = container;
+        self
+    }
+
+    /// Sets the prefix of the segment file names, the default is "segment".
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    /// Rotates to a new segment once the current one has been recording for
+    /// the duration.
+    pub fn with_segment_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Rotates to a new segment once the current one reaches the size in
+    /// bytes.
+    pub fn with_segment_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Deletes the oldest segments to keep the total size of the segments in
+    /// the directory within the quota in bytes.  The segment being recorded
+    /// is never deleted.
+    pub fn with_quota(mut self, quota: u64) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Path of the segment currently being recorded.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|segment| segment.path.as_path())
+    }
+
+    /// Returns the segments present in the directory, oldest first.
+    pub fn segments(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        Ok(self.scan()?.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Writes the encoded packet, starting a new segment on a keyframe when
+    /// the current segment is complete.  Packets preceding the first keyframe
+    /// are dropped.
+    pub fn write(&mut self, packet: &EncodedPacket) -> Result<(), Box<dyn Error>> {
+        let mut packet = packet;
+        let mut primed = None;
+        if packet.keyframe {
+            let params = AccessUnit::parse(self.codec, &packet.data).params;
+            let complete = params.is_complete(self.codec);
+            if complete {
+                self.params = params;
+            }
+            let rotate = match &self.current {
+                None => true,
+                Some(segment) => {
+                    self.duration
+                        .is_some_and(|duration| segment.started.elapsed() >= duration)
+                        || self
+                            .size
+                            .is_some_and(|size| segment.writer.bytes_written() >= size)
+                }
+            };
+            if rotate {
+                self.rotate()?;
+                // The new segment needs the parameter sets which the encoder
+                // may not repeat on every keyframe.
+                if !complete && self.params.is_complete(self.codec) {
+                    packet = primed.insert(with_parameter_sets(packet, &self.params));
+                }
+            }
+        }
+
+        match &mut self.current {
+            Some(segment) => segment.writer.write(packet),
+            None => Ok(()),
+        }
+    }
+
+    /// Finishes the current segment.  A following keyframe starts a new
+    /// segment.
+    pub fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.current.take() {
+            Some(mut segment) => segment.writer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    fn rotate(&mut self) -> Result<(), Box<dyn Error>> {
+        self.finish()?;
+
+        let index = match self.index {
+            Some(index) => index,
+            None => self.scan()?.last().map_or(0, |(index, _)| index + 1),
+        };
+        self.index = Some(index + 1);
+
+        let path = self.directory.join(format!(
+            "{}_{:06}.{}",
+            self.prefix,
+            index,
+            self.container.extension()
+        ));
+        let writer: Box<dyn PacketWriter + Send> = match self.container {
+            Container::Mp4 => Box::new(Mp4Writer::create(&path, self.codec)?),
+            Container::Mkv => Box::new(MkvWriter::create(&path, self.codec)?),
+        };
+        self.current = Some(Segment {
+            path,
+            writer,
+            started: Instant::now(),
+        });
+
+        self.enforce_quota()
+    }
+
+    fn enforce_quota(&self) -> Result<(), Box<dyn Error>> {
+        let Some(quota) = self.quota else {
+            return Ok(());
+        };
+        let mut segments = Vec::new();
+        for (_, path) in self.scan()? {
+            segments.push((fs::metadata(&path)?.len(), path));
+        }
+        let mut total: u64 = segments.iter().map(|(size, _)| size).sum();
+        for (size, path) in segments {
+            if total <= quota || Some(path.as_path()) == self.current_path() {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total -= size,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => total -= size,
+                Err(err) => return Err(Box::new(err)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the segments of the directory with their index, sorted by
+    /// index.
+    fn scan(&self) -> Result<Vec<(u64, PathBuf)>, Box<dyn Error>> {
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let index = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(self.prefix.as_str()))
+                .and_then(|name| name.strip_prefix('_'))
+                .and_then(|name| name.strip_suffix(self.container.extension()))
+                .and_then(|name| name.strip_suffix('.'))
+                .and_then(|index| index.parse().ok());
+            if let Some(index) = index {
+                segments.push((index, path));
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+}
+
+impl Drop for SegmentWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// The Recorder records the frames received by a [`Client`] by encoding them
+/// and writing the encoded video to rotating segments through a
+/// [`SegmentWriter`].
+pub struct Recorder<E: VideoEncoder = Encoder> {
+    client: Client,
+    encoder: E,
+    writer: SegmentWriter,
+}
+
+impl<E: VideoEncoder> Recorder<E> {
+    pub fn new(client: Client, encoder: E, writer: SegmentWriter) -> Self {
+        Recorder {
+            client,
+            encoder,
+            writer,
+        }
+    }
+
+    pub fn writer(&self) -> &SegmentWriter {
+        &self.writer
+    }
+
+    /// Encodes and records the frame.
+    pub fn record_frame(&mut self, frame: &Frame) -> Result<(), Box<dyn Error>> {
+        let packet = self.encoder.encode(frame)?;
+        self.writer.write(&packet)
+    }
+
+    /// Waits for the next frame from the client and records it.  Returns
+    /// false if the frame expired before it could be locked.
+    pub fn record_next(&mut self) -> Result<bool, Box<dyn Error>> {
+        let frame = self.client.get_frame(0)?;
+        if frame.trylock().is_err() {
+            return Ok(false);
+        }
+        let result = self.record_frame(&frame);
+        frame.unlock()?;
+        result.map(|_| true)
+    }
+
+    /// Records frames until stop is set, then finishes the current segment.
+    /// Client timeouts, as configured through [`Client::set_timeout`], allow
+    /// the stop flag to be checked while no frames are received.
+    pub fn run(&mut self, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+        while !stop.load(Ordering::Relaxed) {
+            if let Err(err) = self.record_next() {
+                let timeout = err.downcast_ref::<io::Error>().is_some_and(|err| {
+                    matches!(
+                        err.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    )
+                });
+                if !timeout {
+                    return Err(err);
+                }
+            }
+        }
+        self.writer.finish()
+    }
+}
+
+/// The PacketRing holds the most recent encoded packets in memory, bounded by
+/// duration and size.  The ring always starts on a keyframe so that its
+/// contents can be decoded, packets are therefore dropped a whole group of
+/// pictures at a time.
+pub struct PacketRing {
+    packets: VecDeque<EncodedPacket>,
+    duration: i64,
+    max_bytes: usize,
+    bytes: usize,
+}
+
+impl PacketRing {
+    /// Creates a ring keeping at least the duration of packets, unless this
+    /// would exceed the maximum size in bytes.
+    pub fn new(duration: Duration, max_bytes: usize) -> Self {
+        PacketRing {
+            packets: VecDeque::new(),
+            duration: duration.as_nanos() as i64,
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Total size in bytes of the buffered packets.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Duration in nanoseconds covered by the buffered packets.
+    pub fn duration(&self) -> i64 {
+        match (self.packets.front(), self.packets.back()) {
+            (Some(first), Some(last)) => last.pts - first.pts,
+            _ => 0,
+        }
+    }
+
+    pub fn push(&mut self, packet: EncodedPacket) {
+        if self.packets.is_empty() && !packet.keyframe {
+            return;
+        }
+        self.bytes += packet.data.len();
+        self.packets.push_back(packet);
+
+        // Drop the oldest group of pictures while the following one still
+        // covers the duration, or the size is exceeded.
+        loop {
+            let next = self.packets.iter().skip(1).position(|p| p.keyframe);
+            let newest = self.packets.back().map_or(0, |p| p.pts);
+            let drop = match next {
+                Some(next) => {
+                    self.bytes > self.max_bytes
+                        || newest - self.packets[next + 1].pts >= self.duration
+                }
+                None => self.bytes > self.max_bytes,
+            };
+            if !drop {
+                break;
+            }
+            let count = next.map_or(self.packets.len(), |next| next + 1);
+            for packet in self.packets.drain(..count) {
+                self.bytes -= packet.data.len();
+            }
+        }
+    }
+
+    /// Iterates over the buffered packets, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &EncodedPacket> + '_ {
+        self.packets.iter()
+    }
+}
+
+/// The EventRecorder keeps a pre-roll of encoded packets in a [`PacketRing`]
+/// and records events to files through a [`SegmentWriter`].  Calling
+/// [`EventRecorder::trigger`] writes the pre-roll and continues recording the
+/// incoming packets until the post-roll has elapsed, triggering again during
+/// an event extends it.  Time is measured using the packet timestamps.
+///
+/// Each event is written to its own segment, the limits of the segment writer
+/// should therefore only be used for the quota.
+pub struct EventRecorder {
+    ring: PacketRing,
+    writer: SegmentWriter,
+    post_roll: i64,
+    latest: Option<i64>,
+    end: Option<i64>,
+}
+
+impl EventRecorder {
+    /// Creates an event recorder with a pre-roll of 10 seconds, bounded to
+    /// 64MiB, and a post-roll of 20 seconds.
+    pub fn new(writer: SegmentWriter) -> Self {
+        EventRecorder {
+            ring: PacketRing::new(Duration::from_secs(10), 64 * 1024 * 1024),
+            writer,
+            post_roll: 20_000_000_000,
+            latest: None,
+            end: None,
+        }
+    }
+
+    /// Sets the duration and maximum size in bytes of the pre-roll.
+    pub fn with_pre_roll(mut self, duration: Duration, max_bytes: usize) -> Self {
+        self.ring = PacketRing::new(duration, max_bytes);
+        self
+    }
+
+    /// Sets the duration recorded after the trigger.
+    pub fn with_post_roll(mut self, duration: Duration) -> Self {
+        self.post_roll = duration.as_nanos() as i64;
+        self
+    }
+
+    pub fn writer(&self) -> &SegmentWriter {
+        &self.writer
+    }
+
+    /// Returns true while an event is being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// Writes the packet to the event being recorded, if any, and to the
+    /// pre-roll.
+    pub fn write(&mut self, packet: EncodedPacket) -> Result<(), Box<dyn Error>> {
+        self.latest = Some(packet.pts);
+        if let Some(end) = self.end {
+            self.writer.write(&packet)?;
+            if packet.pts >= end {
+                self.end = None;
+                self.writer.finish()?;
+            }
+        }
+        self.ring.push(packet);
+        Ok(())
+    }
+
+    /// Starts recording an event with the pre-roll, or extends the event
+    /// being recorded, until the post-roll elapses after the latest packet.
+    /// Returns the path of the event file.
+    pub fn trigger(&mut self) -> Result<PathBuf, Box<dyn Error>> {
+        if self.end.is_none() {
+            if self.ring.is_empty() {
+                return Err("no keyframe received for the pre-roll".into());
+            }
+            self.writer.finish()?;
+            for packet in self.ring.iter() {
+                self.writer.write(packet)?;
+            }
+        }
+        self.end = self.latest.map(|latest| latest + self.post_roll);
+        self.writer
+            .current_path()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| "event recording not started".into())
+    }
+
+    /// Finishes the event being recorded.
+    pub fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.end = None;
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        demux::{open, Demuxer, MkvReader, Mp4Reader},
+        nal::{h264_nal_type, split_annexb, tests::pcm_packets, H264_NAL_PPS, H264_NAL_SPS},
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("videostream-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_rotation() -> Result<(), Box<dyn Error>> {
+        let dir = temp_dir("rotation");
+        let packets = pcm_packets(20, 5)?;
+        let mut writer = SegmentWriter::new(&dir, FourCC(*b"H264"))?
+            .with_container(Container::Mkv)
+            .with_segment_size(1);
+        for packet in &packets {
+            writer.write(packet)?;
+        }
+        writer.finish()?;
+
+        // Every keyframe starts a new segment and every frame is kept.
+        let segments = writer.segments()?;
+        assert_eq!(segments.len(), 4);
+        assert!(segments[0].ends_with("segment_000000.mkv"));
+        let mut total = 0;
+        for path in &segments {
+            let mut reader = MkvReader::open(path)?;
+            let first = reader.read_packet()?.unwrap();
+            assert!(first.keyframe);
+            let mut count = 1;
+            while reader.read_packet()?.is_some() {
+                count += 1;
+            }
+            assert_eq!(count, 5);
+            total += count;
+        }
+        assert_eq!(total, packets.len());
+
+        // Numbering continues after existing segments.
+        let mut writer = SegmentWriter::new(&dir, FourCC(*b"H264"))?.with_container(Container::Mkv);
+        writer.write(&packets[0])?;
+        assert!(writer
+            .current_path()
+            .unwrap()
+            .ends_with("segment_000004.mkv"));
+
+        drop(writer);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parameter_sets() -> Result<(), Box<dyn Error>> {
+        // Only the first access unit carries the SPS and PPS.
+        let mut packets = pcm_packets(20, 5)?;
+        for packet in &mut packets[1..] {
+            let mut data = Vec::new();
+            for (nal, _) in split_annexb(&packet.data) {
+                if !matches!(h264_nal_type(nal), H264_NAL_SPS | H264_NAL_PPS) {
+                    data.extend_from_slice(&[0, 0, 0, 1]);
+                    data.extend_from_slice(nal);
+                }
+            }
+            packet.data = data;
+        }
+
+        for container in [Container::Mp4, Container::Mkv] {
+            let dir = temp_dir(&format!("params-{}", container.extension()));
+            let mut writer = SegmentWriter::new(&dir, FourCC(*b"H264"))?
+                .with_container(container)
+                .with_segment_size(1);
+            for packet in &packets {
+                writer.write(packet)?;
+            }
+            writer.finish()?;
+
+            let segments = writer.segments()?;
+            assert_eq!(segments.len(), 4);
+            for path in &segments {
+                let mut reader = open(path)?;
+                let mut count = 0;
+                while reader.read_packet()?.is_some() {
+                    count += 1;
+                }
+                assert_eq!(count, 5, "{}", path.display());
+            }
+            fs::remove_dir_all(&dir)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_quota() -> Result<(), Box<dyn Error>> {
+        let dir = temp_dir("quota");
+        let packets = pcm_packets(40, 4)?;
+        let mut writer = SegmentWriter::new(&dir, FourCC(*b"H264"))?
+            .with_segment_duration(Duration::ZERO)
+            .with_quota(3 * 4 * packets[0].data.len() as u64);
+        for packet in &packets {
+            writer.write(packet)?;
+        }
+        writer.finish()?;
+
+        let segments = writer.segments()?;
+        assert!(segments.len() <= 3);
+        assert!(segments.last().unwrap().ends_with("segment_000009.mp4"));
+        let mp4 = Mp4Reader::open(segments.last().unwrap())?;
+        assert_eq!(mp4.len(), 4);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_ring() {
+        let mut ring = PacketRing::new(Duration::from_secs(1), usize::MAX);
+        for i in 0..30 {
+            ring.push(EncodedPacket {
+                data: vec![0; 10],
+                pts: i * 100_000_000,
+                keyframe: i % 5 == 0,
+                ..Default::default()
+            });
+            assert!(ring.iter().next().unwrap().keyframe);
+            assert!(ring.duration() >= (i * 100_000_000).min(1_000_000_000));
+        }
+        assert_eq!(ring.iter().next().unwrap().pts, 1_500_000_000);
+        assert_eq!(ring.bytes(), 150);
+
+        let mut ring = PacketRing::new(Duration::from_secs(10), 100);
+        for i in 0..30 {
+            ring.push(EncodedPacket {
+                data: vec![0; 10],
+                pts: i * 100_000_000,
+                keyframe: i % 5 == 0,
+                ..Default::default()
+            });
+            assert!(ring.bytes() <= 100);
+        }
+        assert_eq!(ring.len(), 10);
+    }
+
+    #[test]
+    fn test_event() -> Result<(), Box<dyn Error>> {
+        let dir = temp_dir("event");
+        let packets = pcm_packets(50, 5)?;
+        let writer = SegmentWriter::new(&dir, FourCC(*b"H264"))?.with_prefix("event");
+        let mut recorder = EventRecorder::new(writer)
+            .with_pre_roll(Duration::from_millis(250), usize::MAX)
+            .with_post_roll(Duration::from_millis(300));
+
+        let mut path = None;
+        for (i, packet) in packets.into_iter().enumerate() {
+            recorder.write(packet)?;
+            if i == 29 {
+                path = Some(recorder.trigger()?);
+            }
+        }
+        assert!(!recorder.is_recording());
+
+        // The pre-roll starts on the keyframe 20 and the post-roll ends with
+        // the first packet 300ms after the trigger.
+        let mut mp4 = Mp4Reader::open(path.unwrap())?;
+        assert_eq!(mp4.len(), 10 + 10);
+        assert!(mp4.read_packet()?.unwrap().keyframe);
+        assert_eq!(recorder.writer().segments()?.len(), 1);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
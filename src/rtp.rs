@@ -0,0 +1,575 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    encoder::EncodedPacket,
+    fourcc::FourCC,
+    mp4::{check_codec, is_hevc},
+    nal::{
+        h264_nal_type, hevc_nal_type, split_annexb, H264_NAL_IDR, HEVC_NAL_BLA_W_LP, HEVC_NAL_CRA,
+    },
+};
+use std::{collections::VecDeque, error::Error};
+
+/// The RTP clock rate for video payloads.
+pub const CLOCK_RATE: u32 = 90_000;
+
+const RTP_HEADER: usize = 12;
+const RTP_VERSION: u8 = 2;
+
+const H264_STAP_A: u8 = 24;
+const H264_FU_A: u8 = 28;
+const HEVC_AP: u8 = 48;
+const HEVC_FU: u8 = 49;
+
+/// Converts nanoseconds to the 90 kHz RTP clock.
+fn to_clock(ns: i64) -> u32 {
+    (ns as i128 * CLOCK_RATE as i128 / 1_000_000_000) as u32
+}
+
+/// The Packetizer fragments encoded access units into RTP packets following
+/// RFC 6184 for H.264 and RFC 7798 for H.265.
+///
+/// NAL units which fit the MTU are sent as single NAL unit packets, or
+/// aggregated with the following NAL units into STAP-A or AP packets when
+/// they fit together, while larger NAL units are fragmented into FU-A or FU
+/// packets.  The marker bit is set on the last packet of each access unit.
+/// RTP timestamps are the packet pts, which for encoder output is the pts of
+/// the source frame, converted to the 90 kHz clock.
+pub struct Packetizer {
+    codec: FourCC,
+    ssrc: u32,
+    payload_type: u8,
+    mtu: usize,
+    sequence: u16,
+}
+
+impl Packetizer {
+    /// Creates a packetizer for the codec, H264 or HEVC, using the dynamic
+    /// payload type 96 and an MTU of 1400 bytes.
+    pub fn new(codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        check_codec(codec)?;
+        Ok(Packetizer {
+            codec,
+            ssrc: rand_u32(),
+            payload_type: 96,
+            mtu: 1400,
+            sequence: rand_u32() as u16,
+        })
+    }
+
+    /// Sets the synchronization source identifier, which is random by
+    /// default.
+    pub fn with_ssrc(mut self, ssrc: u32) -> Self {
+        self.ssrc = ssrc;
+        self
+    }
+
+    pub fn with_payload_type(mut self, payload_type: u8) -> Self {
+        self.payload_type = payload_type & 0x7f;
+        self
+    }
+
+    /// Sets the maximum size of the RTP packets, including the RTP header.
+    pub fn with_mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// Sets the sequence number of the next packet, which is random by
+    /// default.
+    pub fn with_sequence(mut self, sequence: u16) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Packetizes the Annex-B access unit into RTP packets.
+    pub fn packetize(&mut self, packet: &EncodedPacket) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let hevc = is_hevc(self.codec);
+        let header_len = if hevc { 2 } else { 1 };
+        let max_payload = self.mtu.saturating_sub(RTP_HEADER);
+        // Fragments need room for the payload and FU headers plus data.
+        if max_payload <= header_len + 1 + 1 {
+            return Err(format!("rtp mtu {} is too small", self.mtu).into());
+        }
+
+        let timestamp = to_clock(packet.pts);
+        let nals: Vec<&[u8]> = split_annexb(&packet.data)
+            .map(|(nal, _)| nal)
+            .filter(|nal| nal.len() >= header_len)
+            .collect();
+
+        let mut payloads = Vec::new();
+        let mut group: Vec<&[u8]> = Vec::new();
+        let mut group_len = header_len;
+        for nal in nals {
+            if !group.is_empty() && group_len + 2 + nal.len() > max_payload {
+                payloads.push(aggregate(hevc, &group));
+                group.clear();
+                group_len = header_len;
+            }
+            if nal.len() > max_payload {
+                payloads.extend(fragment(hevc, nal, max_payload));
+            } else {
+                group.push(nal);
+                group_len += 2 + nal.len();
+            }
+        }
+        if !group.is_empty() {
+            payloads.push(aggregate(hevc, &group));
+        }
+
+        let count = payloads.len();
+        Ok(payloads
+            .into_iter()
+            .enumerate()
+            .map(|(index, payload)| {
+                let mut out = Vec::with_capacity(RTP_HEADER + payload.len());
+                out.push(RTP_VERSION << 6);
+                let marker = if index + 1 == count { 0x80 } else { 0 };
+                out.push(marker | self.payload_type);
+                out.extend_from_slice(&self.sequence.to_be_bytes());
+                out.extend_from_slice(&timestamp.to_be_bytes());
+                out.extend_from_slice(&self.ssrc.to_be_bytes());
+                out.extend_from_slice(&payload);
+                self.sequence = self.sequence.wrapping_add(1);
+                out
+            })
+            .collect())
+    }
+}
+
+pub(crate) fn rand_u32() -> u32 {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+    };
+    RandomState::new().build_hasher().finish() as u32
+}
+
+/// Builds a single NAL unit payload, or an aggregation packet for multiple
+/// NAL units.
+fn aggregate(hevc: bool, nals: &[&[u8]]) -> Vec<u8> {
+    if let [nal] = nals {
+        return nal.to_vec();
+    }
+
+    let mut out = if hevc {
+        // The layer and temporal IDs are the lowest of the aggregated units.
+        let layer = nals.iter().map(|n| ((n[0] & 1) << 5) | (n[1] >> 3)).min();
+        let tid = nals.iter().map(|n| n[1] & 7).min();
+        let forbidden = nals.iter().fold(0, |f, n| f | (n[0] & 0x80));
+        let layer = layer.unwrap_or(0);
+        vec![
+            forbidden | (HEVC_AP << 1) | (layer >> 5),
+            (layer << 3) | tid.unwrap_or(1),
+        ]
+    } else {
+        let forbidden = nals.iter().fold(0, |f, n| f | (n[0] & 0x80));
+        let nri = nals.iter().map(|n| n[0] & 0x60).max().unwrap_or(0);
+        vec![forbidden | nri | H264_STAP_A]
+    };
+    for nal in nals {
+        out.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        out.extend_from_slice(nal);
+    }
+    out
+}
+
+/// Splits the NAL unit into fragmentation unit payloads.
+fn fragment(hevc: bool, nal: &[u8], max_payload: usize) -> Vec<Vec<u8>> {
+    let (header, kind, body) = if hevc {
+        let kind = hevc_nal_type(nal);
+        let header = vec![(nal[0] & 0x81) | (HEVC_FU << 1), nal[1]];
+        (header, kind, &nal[2..])
+    } else {
+        let kind = h264_nal_type(nal);
+        let header = vec![(nal[0] & 0xe0) | H264_FU_A];
+        (header, kind, &nal[1..])
+    };
+
+    let chunk = max_payload - header.len() - 1;
+    let count = body.len().div_ceil(chunk);
+    body.chunks(chunk)
+        .enumerate()
+        .map(|(index, data)| {
+            let mut fu = kind;
+            if index == 0 {
+                fu |= 0x80;
+            }
+            if index + 1 == count {
+                fu |= 0x40;
+            }
+            let mut out = Vec::with_capacity(header.len() + 1 + data.len());
+            out.extend_from_slice(&header);
+            out.push(fu);
+            out.extend_from_slice(data);
+            out
+        })
+        .collect()
+}
+
+/// The Depacketizer reassembles access units from RTP packets produced by a
+/// [`Packetizer`] or any RFC 6184 or RFC 7798 compliant sender, returning
+/// Annex-B packets which can be pushed to a [`crate::decoder::StreamDecoder`].
+///
+/// An access unit is complete when a packet with the marker bit is received
+/// or when the RTP timestamp changes.  Fragmented NAL units missing a
+/// fragment due to packet loss are dropped.
+pub struct Depacketizer {
+    codec: FourCC,
+    current: Vec<u8>,
+    keyframe: bool,
+    timestamp: Option<u32>,
+    extended: i64,
+    sequence: Option<u16>,
+    fragment: Option<Vec<u8>>,
+    ready: VecDeque<EncodedPacket>,
+    lost: u64,
+}
+
+impl Depacketizer {
+    pub fn new(codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        check_codec(codec)?;
+        Ok(Depacketizer {
+            codec,
+            current: Vec::new(),
+            keyframe: false,
+            timestamp: None,
+            extended: 0,
+            sequence: None,
+            fragment: None,
+            ready: VecDeque::new(),
+            lost: 0,
+        })
+    }
+
+    /// Number of packets detected as lost from gaps in the sequence numbers.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Processes the RTP packet, completed access units are returned by
+    /// [`Depacketizer::drain`].
+    pub fn push(&mut self, packet: &[u8]) -> Result<(), Box<dyn Error>> {
+        if packet.len() < RTP_HEADER || packet[0] >> 6 != RTP_VERSION {
+            return Err("invalid rtp packet".into());
+        }
+        let marker = packet[1] & 0x80 != 0;
+        let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+        let timestamp = u32::from_be_bytes(packet[4..8].try_into()?);
+
+        let mut start = RTP_HEADER + 4 * (packet[0] & 0x0f) as usize;
+        if packet[0] & 0x10 != 0 {
+            let extension = packet
+                .get(start + 2..start + 4)
+                .ok_or("truncated rtp extension")?;
+            start += 4 + 4 * u16::from_be_bytes([extension[0], extension[1]]) as usize;
+        }
+        let mut end = packet.len();
+        if packet[0] & 0x20 != 0 {
+            end = end.saturating_sub(*packet.last().unwrap_or(&0) as usize);
+        }
+        let payload = packet.get(start..end).ok_or("truncated rtp packet")?;
+
+        if let Some(previous) = self.sequence {
+            let gap = sequence.wrapping_sub(previous).wrapping_sub(1);
+            if gap != 0 && gap < 0x8000 {
+                self.lost += gap as u64;
+                self.fragment = None;
+            }
+        }
+        self.sequence = Some(sequence);
+
+        match self.timestamp {
+            Some(previous) if previous != timestamp => {
+                self.complete();
+                self.extended += timestamp.wrapping_sub(previous) as i32 as i64;
+            }
+            _ => (),
+        }
+        self.timestamp = Some(timestamp);
+
+        if is_hevc(self.codec) {
+            self.push_hevc(payload)?;
+        } else {
+            self.push_h264(payload)?;
+        }
+
+        if marker {
+            self.complete();
+        }
+        Ok(())
+    }
+
+    /// Returns the completed access units.
+    pub fn drain(&mut self) -> impl Iterator<Item = EncodedPacket> + '_ {
+        self.ready.drain(..)
+    }
+
+    fn push_h264(&mut self, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+        let header = *payload.first().ok_or("empty rtp payload")?;
+        match header & 0x1f {
+            H264_STAP_A => self.push_aggregate(&payload[1..]),
+            H264_FU_A => {
+                let fu = *payload.get(1).ok_or("truncated fu-a")?;
+                let nal_header = (header & 0xe0) | (fu & 0x1f);
+                self.push_fragment(fu, &[nal_header], &payload[2..]);
+                Ok(())
+            }
+            1..=23 => {
+                self.push_nal(payload);
+                Ok(())
+            }
+            kind => Err(format!("unsupported h.264 rtp payload type {}", kind).into()),
+        }
+    }
+
+    fn push_hevc(&mut self, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+        if payload.len() < 2 {
+            return Err("truncated rtp payload".into());
+        }
+        match hevc_nal_type(payload) {
+            HEVC_AP => self.push_aggregate(&payload[2..]),
+            HEVC_FU => {
+                let fu = *payload.get(2).ok_or("truncated fu")?;
+                let nal_header = [(payload[0] & 0x81) | ((fu & 0x3f) << 1), payload[1]];
+                self.push_fragment(fu, &nal_header, &payload[3..]);
+                Ok(())
+            }
+            0..=47 => {
+                self.push_nal(payload);
+                Ok(())
+            }
+            kind => Err(format!("unsupported h.265 rtp payload type {}", kind).into()),
+        }
+    }
+
+    fn push_aggregate(&mut self, mut data: &[u8]) -> Result<(), Box<dyn Error>> {
+        while data.len() >= 2 {
+            let len = u16::from_be_bytes([data[0], data[1]]) as usize;
+            let nal = data.get(2..2 + len).ok_or("truncated aggregation packet")?;
+            self.push_nal(nal);
+            data = &data[2 + len..];
+        }
+        Ok(())
+    }
+
+    fn push_fragment(&mut self, fu: u8, nal_header: &[u8], data: &[u8]) {
+        if fu & 0x80 != 0 {
+            let mut nal = nal_header.to_vec();
+            nal.extend_from_slice(data);
+            self.fragment = Some(nal);
+        } else if let Some(nal) = &mut self.fragment {
+            nal.extend_from_slice(data);
+        }
+        if fu & 0x40 != 0 {
+            if let Some(nal) = self.fragment.take() {
+                self.push_nal(&nal);
+            }
+        }
+    }
+
+    fn push_nal(&mut self, nal: &[u8]) {
+        self.keyframe |= if is_hevc(self.codec) {
+            (HEVC_NAL_BLA_W_LP..=HEVC_NAL_CRA).contains(&hevc_nal_type(nal))
+        } else {
+            h264_nal_type(nal) == H264_NAL_IDR
+        };
+        self.current.extend_from_slice(&[0, 0, 0, 1]);
+        self.current.extend_from_slice(nal);
+    }
+
+    fn complete(&mut self) {
+        self.fragment = None;
+        if self.current.is_empty() {
+            return;
+        }
+        let pts = (self.extended as i128 * 1_000_000_000 / CLOCK_RATE as i128) as i64;
+        self.ready.push_back(EncodedPacket {
+            data: std::mem::take(&mut self.current),
+            pts,
+            dts: pts,
+            duration: 0,
+            keyframe: std::mem::take(&mut self.keyframe),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::{net::UdpSocket, time::Duration};
+
+    fn annexb(nals: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for nal in nals {
+            out.extend_from_slice(&[0, 0, 0, 1]);
+            out.extend_from_slice(nal);
+        }
+        out
+    }
+
+    fn nal(header: &[u8], len: usize) -> Vec<u8> {
+        let mut nal = header.to_vec();
+        // Avoid start code emulation in the random payload.
+        nal.extend((0..len).map(|_| rand::rng().random_range(1..=255u8)));
+        nal
+    }
+
+    #[test]
+    fn test_h264_modes() {
+        let mut packetizer = Packetizer::new(FourCC(*b"H264"))
+            .unwrap()
+            .with_mtu(200)
+            .with_ssrc(0x1234_5678)
+            .with_sequence(0xfffe);
+        let access_unit = annexb(&[nal(&[0x67], 20), nal(&[0x68], 4), nal(&[0x65], 1000)]);
+        let packets = packetizer
+            .packetize(&EncodedPacket {
+                data: access_unit.clone(),
+                pts: 1_000_000_000,
+                keyframe: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+        // STAP-A with SPS and PPS followed by FU-A fragments.
+        assert_eq!(packets[0][RTP_HEADER] & 0x1f, H264_STAP_A);
+        assert!(packets[1..]
+            .iter()
+            .all(|p| p[RTP_HEADER] & 0x1f == H264_FU_A && p.len() <= 200));
+        assert_eq!(packets[1][RTP_HEADER + 1], 0x80 | 5);
+        assert_eq!(
+            u32::from_be_bytes(packets[0][4..8].try_into().unwrap()),
+            90_000
+        );
+        assert_eq!(
+            u32::from_be_bytes(packets[0][8..12].try_into().unwrap()),
+            0x1234_5678
+        );
+        assert_eq!(packets.iter().filter(|p| p[1] & 0x80 != 0).count(), 1);
+        assert_eq!(
+            packetizer.sequence(),
+            (packets.len() as u16).wrapping_sub(2)
+        );
+
+        let mut depacketizer = Depacketizer::new(FourCC(*b"H264")).unwrap();
+        for packet in &packets {
+            depacketizer.push(packet).unwrap();
+        }
+        let output: Vec<_> = depacketizer.drain().collect();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].data, access_unit);
+        assert!(output[0].keyframe);
+        assert_eq!(depacketizer.lost(), 0);
+    }
+
+    #[test]
+    fn test_hevc_modes() {
+        let mut packetizer = Packetizer::new(FourCC(*b"HEVC")).unwrap().with_mtu(300);
+        let access_unit = annexb(&[
+            nal(&[0x40, 0x01], 20),
+            nal(&[0x42, 0x01], 40),
+            nal(&[0x44, 0x01], 4),
+            nal(&[0x26, 0x01], 1000),
+        ]);
+        let packets = packetizer
+            .packetize(&EncodedPacket {
+                data: access_unit.clone(),
+                keyframe: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(packets[0][RTP_HEADER] >> 1, HEVC_AP);
+        assert_eq!(packets[1][RTP_HEADER] >> 1, HEVC_FU);
+        assert_eq!(packets[1][RTP_HEADER + 2], 0x80 | 19);
+
+        let mut depacketizer = Depacketizer::new(FourCC(*b"HEVC")).unwrap();
+        for packet in &packets {
+            depacketizer.push(packet).unwrap();
+        }
+        let output: Vec<_> = depacketizer.drain().collect();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].data, access_unit);
+        assert!(output[0].keyframe);
+    }
+
+    #[test]
+    fn test_header_only_nals() {
+        // End of sequence and end of stream NAL units are a bare header.
+        for (codec, access_unit) in [
+            (
+                FourCC(*b"H264"),
+                annexb(&[nal(&[0x41], 100), vec![0x0a], vec![0x0b]]),
+            ),
+            (
+                FourCC(*b"HEVC"),
+                annexb(&[nal(&[0x02, 0x01], 100), vec![0x48, 0x01], vec![0x4a, 0x01]]),
+            ),
+        ] {
+            let mut packetizer = Packetizer::new(codec).unwrap();
+            let packets = packetizer
+                .packetize(&EncodedPacket {
+                    data: access_unit.clone(),
+                    ..Default::default()
+                })
+                .unwrap();
+            let mut depacketizer = Depacketizer::new(codec).unwrap();
+            for packet in &packets {
+                depacketizer.push(packet).unwrap();
+            }
+            let output: Vec<_> = depacketizer.drain().collect();
+            assert_eq!(output.len(), 1);
+            assert_eq!(output[0].data, access_unit);
+        }
+    }
+
+    #[test]
+    fn test_loopback() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.connect(receiver.local_addr().unwrap()).unwrap();
+
+        let mut packetizer = Packetizer::new(FourCC(*b"H264")).unwrap();
+        let mut depacketizer = Depacketizer::new(FourCC(*b"H264")).unwrap();
+        let mut sent = Vec::new();
+        let mut received = Vec::new();
+        let mut buffer = [0; 2048];
+        for i in 0..10 {
+            let packet = EncodedPacket {
+                data: annexb(&[nal(&[if i == 0 { 0x65 } else { 0x41 }], 100 + i * 500)]),
+                pts: i as i64 * 33_333_333,
+                keyframe: i == 0,
+                ..Default::default()
+            };
+            for rtp in packetizer.packetize(&packet).unwrap() {
+                sender.send(&rtp).unwrap();
+                let len = receiver.recv(&mut buffer).unwrap();
+                depacketizer.push(&buffer[..len]).unwrap();
+            }
+            received.extend(depacketizer.drain());
+            sent.push(packet);
+        }
+
+        assert_eq!(received.len(), sent.len());
+        for (sent, received) in sent.iter().zip(&received) {
+            assert_eq!(sent.data, received.data);
+            assert_eq!(sent.keyframe, received.keyframe);
+            // Timestamps are relative to the first packet at 90 kHz precision.
+            assert!((sent.pts - received.pts).abs() * (CLOCK_RATE as i64) < 1_000_000_000);
+        }
+    }
+}
@@ -0,0 +1,877 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    client::Client,
+    encoder::{EncodedPacket, VideoEncoder},
+    fourcc::FourCC,
+    listener::Listener,
+    mp4::{check_codec, is_hevc, ParameterSets},
+    nal::{
+        h264_nal_type, hevc_nal_type, split_annexb, H264_NAL_PPS, H264_NAL_SPS, HEVC_NAL_PPS,
+        HEVC_NAL_SPS, HEVC_NAL_VPS,
+    },
+    rtp::{rand_u32, Packetizer, CLOCK_RATE},
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Write timeout after which a viewer using interleaved TCP is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Session timeout in seconds advertised to viewers, sessions which receive
+/// no request for this long are dropped.
+const SESSION_TIMEOUT: u32 = 60;
+
+/// Access units queued for a viewer using interleaved TCP, viewers which let
+/// their queue fill up are dropped.
+const VIEWER_QUEUE: usize = 32;
+
+/// Size limit of the request line and headers of an RTSP request.
+const MAX_REQUEST_SIZE: u64 = 16 * 1024;
+
+enum Transport {
+    Udp {
+        socket: UdpSocket,
+        peer: SocketAddr,
+    },
+    Tcp {
+        stream: Arc<Mutex<TcpStream>>,
+        channel: u8,
+        // Interleaved frames written to the stream by the viewer's thread.
+        queue: SyncSender<Vec<u8>>,
+    },
+}
+
+struct Viewer {
+    connection: u64,
+    transport: Transport,
+    packetizer: Packetizer,
+    playing: bool,
+    // New viewers only receive packets once a keyframe arrives.
+    synced: bool,
+    // Time of the last request of the session.
+    active: Instant,
+}
+
+impl Viewer {
+    fn send(&mut self, packet: &EncodedPacket) -> io::Result<()> {
+        let packets = self
+            .packetizer
+            .packetize(packet)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        match &self.transport {
+            Transport::Udp { socket, peer } => {
+                for rtp in packets {
+                    socket.send_to(&rtp, peer)?;
+                }
+            }
+            Transport::Tcp { channel, queue, .. } => {
+                let mut frames = Vec::new();
+                for rtp in packets {
+                    frames.push(b'$');
+                    frames.push(*channel);
+                    frames.extend_from_slice(&(rtp.len() as u16).to_be_bytes());
+                    frames.extend_from_slice(&rtp);
+                }
+                queue.try_send(frames).map_err(|err| match err {
+                    TrySendError::Full(_) => {
+                        io::Error::new(io::ErrorKind::WouldBlock, "viewer queue is full")
+                    }
+                    TrySendError::Disconnected(_) => io::Error::from(io::ErrorKind::BrokenPipe),
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Shared {
+    codec: FourCC,
+    params: Mutex<ParameterSets>,
+    viewers: Mutex<HashMap<String, Viewer>>,
+    stop: AtomicBool,
+}
+
+struct Request {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn session(&self) -> Option<&str> {
+        self.header("Session")
+            .map(|session| session.split(';').next().unwrap_or("").trim())
+    }
+}
+
+/// The RtspServer streams encoded video to RTSP viewers such as VLC or
+/// GStreamer's rtspsrc, providing a single stream at any URL of the server.
+///
+/// Viewers connect through DESCRIBE, SETUP, PLAY and TEARDOWN requests and
+/// receive RTP either over UDP or interleaved in the RTSP connection.  Each
+/// connection is handled on its own thread while packets are delivered to
+/// every playing viewer by [`RtspServer::send`], or by [`RtspServer::run`]
+/// which encodes the frames received by a [`Client`].  Viewers only receive
+/// packets from the next keyframe on.
+pub struct RtspServer {
+    shared: Arc<Shared>,
+    listener: Listener,
+}
+
+impl RtspServer {
+    /// Binds the server to the address and starts accepting connections, the
+    /// codec is H264 or HEVC.
+    pub fn bind<A: ToSocketAddrs>(address: A, codec: FourCC) -> Result<Self, Box<dyn Error>> {
+        check_codec(codec)?;
+        let shared = Arc::new(Shared {
+            codec,
+            params: Mutex::new(ParameterSets::default()),
+            viewers: Mutex::new(HashMap::new()),
+            stop: AtomicBool::new(false),
+        });
+
+        let accept = shared.clone();
+        let connections = AtomicU64::new(0);
+        let listener = Listener::bind(address, move |stream| {
+            let connection = connections.fetch_add(1, Ordering::Relaxed);
+            let _ = accept.handle(stream, connection);
+            accept
+                .viewers
+                .lock()
+                .unwrap()
+                .retain(|_, viewer| viewer.connection != connection);
+        })?;
+
+        Ok(RtspServer { shared, listener })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr()
+    }
+
+    /// Returns the number of viewers currently playing the stream.
+    pub fn viewers(&self) -> usize {
+        let viewers = self.shared.viewers.lock().unwrap();
+        viewers.values().filter(|viewer| viewer.playing).count()
+    }
+
+    /// Sends the Annex-B access unit to every playing viewer, viewers which
+    /// fail to receive it or whose session timed out are dropped.  Viewers
+    /// using interleaved TCP are written by their own thread so a slow viewer
+    /// does not hold up the others.  The parameter sets of keyframes are kept
+    /// to describe the stream to new viewers.
+    pub fn send(&self, packet: &EncodedPacket) -> Result<(), Box<dyn Error>> {
+        if packet.keyframe {
+            let params = parameter_sets(self.shared.codec, &packet.data);
+            if !params.sps.is_empty() {
+                *self.shared.params.lock().unwrap() = params;
+            }
+        }
+
+        let timeout = Duration::from_secs(SESSION_TIMEOUT as u64);
+        let mut viewers = self.shared.viewers.lock().unwrap();
+        viewers.retain(|_, viewer| {
+            if viewer.active.elapsed() > timeout {
+                return false;
+            }
+            if !viewer.playing {
+                return true;
+            }
+            viewer.synced |= packet.keyframe;
+            !viewer.synced || viewer.send(packet).is_ok()
+        });
+        Ok(())
+    }
+
+    /// Encodes the frames received by the client and sends them to the
+    /// viewers until stop is set.  Client timeouts, as configured through
+    /// [`Client::set_timeout`], allow the stop flag to be checked while no
+    /// frames are received.
+    pub fn run<E: VideoEncoder>(
+        &self,
+        client: &Client,
+        encoder: &mut E,
+        stop: &AtomicBool,
+    ) -> Result<(), Box<dyn Error>> {
+        while !stop.load(Ordering::Relaxed) {
+            let frame = match client.get_frame(0) {
+                Ok(frame) => frame,
+                Err(err) => {
+                    let timeout = err.downcast_ref::<io::Error>().is_some_and(|err| {
+                        matches!(
+                            err.kind(),
+                            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                        )
+                    });
+                    if timeout {
+                        continue;
+                    }
+                    return Err(err);
+                }
+            };
+            if frame.trylock().is_err() {
+                continue;
+            }
+            let packet = encoder.encode(&frame);
+            frame.unlock()?;
+            self.send(&packet?)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RtspServer {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        self.listener.shutdown();
+        for viewer in self.shared.viewers.lock().unwrap().values() {
+            if let Transport::Tcp { stream, .. } = &viewer.transport {
+                let _ = stream.lock().unwrap().shutdown(Shutdown::Both);
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn handle(&self, stream: TcpStream, connection: u64) -> Result<(), Box<dyn Error>> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+        let mut reader = BufReader::new(stream);
+        loop {
+            let request = match read_request(&mut reader) {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    writer
+                        .lock()
+                        .unwrap()
+                        .write_all(b"RTSP/1.0 400 Bad Request\r\n\r\n")?;
+                    break;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+            if let Some(session) = request.session() {
+                if let Some(viewer) = self.viewers.lock().unwrap().get_mut(session) {
+                    viewer.active = Instant::now();
+                }
+            }
+            let response = self.respond(&request, connection, &writer);
+            writer.lock().unwrap().write_all(response.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn respond(
+        &self,
+        request: &Request,
+        connection: u64,
+        writer: &Arc<Mutex<TcpStream>>,
+    ) -> String {
+        let cseq = request.header("CSeq").unwrap_or("0");
+        let result = match request.method.as_str() {
+            "OPTIONS" => Ok((
+                "Public: OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN, GET_PARAMETER\r\n".to_owned(),
+                String::new(),
+            )),
+            "DESCRIBE" => self.describe(request, writer),
+            "SETUP" => self.setup(request, connection, writer),
+            "PLAY" => self.play(request),
+            "TEARDOWN" => match request.session() {
+                Some(session) => {
+                    self.viewers.lock().unwrap().remove(session);
+                    Ok((String::new(), String::new()))
+                }
+                None => Err((454, "Session Not Found")),
+            },
+            "GET_PARAMETER" => Ok((String::new(), String::new())),
+            _ => Err((501, "Not Implemented")),
+        };
+
+        match result {
+            Ok((headers, body)) => {
+                let mut response = format!("RTSP/1.0 200 OK\r\nCSeq: {}\r\n{}", cseq, headers);
+                if !body.is_empty() {
+                    response += &format!("Content-Length: {}\r\n", body.len());
+                }
+                response + "\r\n" + &body
+            }
+            Err((code, reason)) => {
+                format!("RTSP/1.0 {} {}\r\nCSeq: {}\r\n\r\n", code, reason, cseq)
+            }
+        }
+    }
+
+    fn describe(
+        &self,
+        request: &Request,
+        writer: &Arc<Mutex<TcpStream>>,
+    ) -> Result<(String, String), (u16, &'static str)> {
+        let local = writer
+            .lock()
+            .unwrap()
+            .local_addr()
+            .map(|addr| addr.ip())
+            .map_err(|_| (500, "Internal Server Error"))?;
+        let family = match local {
+            IpAddr::V4(_) => "IP4",
+            IpAddr::V6(_) => "IP6",
+        };
+
+        let params = self.params.lock().unwrap();
+        let (encoding, fmtp) = if is_hevc(self.codec) {
+            let mut fmtp = Vec::new();
+            for (name, sets) in [
+                ("sprop-vps", &params.vps),
+                ("sprop-sps", &params.sps),
+                ("sprop-pps", &params.pps),
+            ] {
+                if let Some(set) = sets.first() {
+                    fmtp.push(format!("{}={}", name, base64(set)));
+                }
+            }
+            ("H265", fmtp.join(";"))
+        } else {
+            let mut fmtp = "packetization-mode=1".to_owned();
+            if let Some(sps) = params.sps.first() {
+                if sps.len() >= 4 {
+                    fmtp += &format!(
+                        ";profile-level-id={:02x}{:02x}{:02x}",
+                        sps[1], sps[2], sps[3]
+                    );
+                }
+                let sets: Vec<_> = params
+                    .sps
+                    .iter()
+                    .chain(&params.pps)
+                    .map(|set| base64(set))
+                    .collect();
+                fmtp += &format!(";sprop-parameter-sets={}", sets.join(","));
+            }
+            ("H264", fmtp)
+        };
+
+        let mut sdp = format!(
+            "v=0\r\no=- {} 1 IN {} {}\r\ns=videostream\r\nc=IN {} {}\r\nt=0 0\r\n",
+            rand_u32(),
+            family,
+            local,
+            family,
+            if family == "IP4" { "0.0.0.0" } else { "::" },
+        );
+        sdp += &format!(
+            "m=video 0 RTP/AVP 96\r\na=rtpmap:96 {}/{}\r\n",
+            encoding, CLOCK_RATE
+        );
+        if !fmtp.is_empty() {
+            sdp += &format!("a=fmtp:96 {}\r\n", fmtp);
+        }
+        sdp += "a=control:trackID=0\r\n";
+
+        let base = request.url.trim_end_matches('/');
+        Ok((
+            format!(
+                "Content-Type: application/sdp\r\nContent-Base: {}/\r\n",
+                base
+            ),
+            sdp,
+        ))
+    }
+
+    fn setup(
+        &self,
+        request: &Request,
+        connection: u64,
+        writer: &Arc<Mutex<TcpStream>>,
+    ) -> Result<(String, String), (u16, &'static str)> {
+        const UNSUPPORTED: (u16, &str) = (461, "Unsupported Transport");
+        let transport = request.header("Transport").ok_or(UNSUPPORTED)?;
+        let options: Vec<&str> = transport
+            .split(',')
+            .next()
+            .unwrap_or("")
+            .split(';')
+            .collect();
+        let option = |name: &str| {
+            options
+                .iter()
+                .find_map(|option| option.trim().strip_prefix(name)?.strip_prefix('='))
+        };
+        let ports = |value: &str| -> Option<(u16, u16)> {
+            let mut ports = value.split('-').map(|port| port.parse::<u16>().ok());
+            let first = ports.next()??;
+            let second = ports.next().flatten().unwrap_or(first.wrapping_add(1));
+            Some((first, second))
+        };
+
+        let packetizer = Packetizer::new(self.codec).map_err(|_| (500, "Internal Server Error"))?;
+        let (transport, reply) = if options[0].trim().starts_with("RTP/AVP/TCP") {
+            let channel = option("interleaved")
+                .and_then(ports)
+                .map_or(0, |(rtp, _)| rtp);
+            let (queue, frames) = mpsc::sync_channel::<Vec<u8>>(VIEWER_QUEUE);
+            let stream = writer.clone();
+            thread::spawn(move || {
+                for frame in frames {
+                    if stream.lock().unwrap().write_all(&frame).is_err() {
+                        break;
+                    }
+                }
+            });
+            let transport = Transport::Tcp {
+                stream: writer.clone(),
+                channel: channel as u8,
+                queue,
+            };
+            let reply = format!(
+                "RTP/AVP/TCP;unicast;interleaved={}-{};ssrc={:08X}",
+                channel,
+                channel + 1,
+                packetizer.ssrc()
+            );
+            (transport, reply)
+        } else if options[0].trim().starts_with("RTP/AVP") {
+            let (rtp, rtcp) = option("client_port").and_then(ports).ok_or(UNSUPPORTED)?;
+            let peer = writer
+                .lock()
+                .unwrap()
+                .peer_addr()
+                .map_err(|_| (500, "Internal Server Error"))?;
+            let bind = SocketAddr::new(
+                match peer.ip() {
+                    IpAddr::V4(_) => IpAddr::from([0u8; 4]),
+                    IpAddr::V6(_) => IpAddr::from([0u16; 8]),
+                },
+                0,
+            );
+            let socket = UdpSocket::bind(bind).map_err(|_| (500, "Internal Server Error"))?;
+            let port = socket
+                .local_addr()
+                .map_err(|_| (500, "Internal Server Error"))?
+                .port();
+            let reply = format!(
+                "RTP/AVP;unicast;client_port={}-{};server_port={}-{};ssrc={:08X}",
+                rtp,
+                rtcp,
+                port,
+                port.wrapping_add(1),
+                packetizer.ssrc()
+            );
+            let transport = Transport::Udp {
+                socket,
+                peer: SocketAddr::new(peer.ip(), rtp),
+            };
+            (transport, reply)
+        } else {
+            return Err(UNSUPPORTED);
+        };
+
+        let session = format!("{:08X}", rand_u32());
+        self.viewers.lock().unwrap().insert(
+            session.clone(),
+            Viewer {
+                connection,
+                transport,
+                packetizer,
+                playing: false,
+                synced: false,
+                active: Instant::now(),
+            },
+        );
+        Ok((
+            format!(
+                "Transport: {}\r\nSession: {};timeout={}\r\n",
+                reply, session, SESSION_TIMEOUT
+            ),
+            String::new(),
+        ))
+    }
+
+    fn play(&self, request: &Request) -> Result<(String, String), (u16, &'static str)> {
+        let session = request.session().ok_or((454, "Session Not Found"))?;
+        let mut viewers = self.viewers.lock().unwrap();
+        let viewer = viewers.get_mut(session).ok_or((454, "Session Not Found"))?;
+        viewer.playing = true;
+        Ok((
+            format!(
+                "Session: {}\r\nRange: npt=0.000-\r\nRTP-Info: url={};seq={}\r\n",
+                session,
+                request.url,
+                viewer.packetizer.sequence()
+            ),
+            String::new(),
+        ))
+    }
+}
+
+/// Reads the next RTSP request, skipping interleaved RTCP packets sent by the
+/// viewer.  Returns None once the connection is closed and an InvalidData
+/// error for malformed requests or requests larger than [`MAX_REQUEST_SIZE`].
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        if buffer[0] != b'$' {
+            break;
+        }
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        let len = u16::from_be_bytes([header[2], header[3]]) as u64;
+        io::copy(&mut reader.take(len), &mut io::sink())?;
+    }
+
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut limited = reader.by_ref().take(MAX_REQUEST_SIZE);
+    let mut read_line = |line: &mut String| -> io::Result<usize> {
+        line.clear();
+        let len = limited.read_line(line)?;
+        if len > 0 && !line.ends_with('\n') && limited.limit() == 0 {
+            return Err(invalid("rtsp request too large"));
+        }
+        Ok(len)
+    };
+
+    let mut line = String::new();
+    if read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| invalid("invalid rtsp request"))?
+        .to_owned();
+    let url = parts
+        .next()
+        .ok_or_else(|| invalid("invalid rtsp request"))?
+        .to_owned();
+
+    let mut headers = Vec::new();
+    loop {
+        if read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+
+    let request = Request {
+        method,
+        url,
+        headers,
+    };
+    let len = request
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(0);
+    io::copy(&mut reader.take(len), &mut io::sink())?;
+    Ok(Some(request))
+}
+
+/// Collects the parameter sets carried by the access unit.
+fn parameter_sets(codec: FourCC, annexb: &[u8]) -> ParameterSets {
+    let mut params = ParameterSets::default();
+    for (nal, _) in split_annexb(annexb) {
+        if is_hevc(codec) {
+            match hevc_nal_type(nal) {
+                HEVC_NAL_VPS => params.vps.push(nal.to_vec()),
+                HEVC_NAL_SPS => params.sps.push(nal.to_vec()),
+                HEVC_NAL_PPS => params.pps.push(nal.to_vec()),
+                _ => (),
+            }
+        } else {
+            match h264_nal_type(nal) {
+                H264_NAL_SPS => params.sps.push(nal.to_vec()),
+                H264_NAL_PPS => params.pps.push(nal.to_vec()),
+                _ => (),
+            }
+        }
+    }
+    params
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nal::tests::pcm_packets, rtp::Depacketizer};
+
+    fn request(
+        reader: &mut BufReader<TcpStream>,
+        method: &str,
+        url: &str,
+        headers: &str,
+    ) -> Result<(String, String), Box<dyn Error>> {
+        write!(
+            reader.get_mut(),
+            "{} {} RTSP/1.0\r\nCSeq: 1\r\n{}\r\n",
+            method,
+            url,
+            headers
+        )?;
+        let mut response = String::new();
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                len = value.trim().parse()?;
+            }
+            response += &line;
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body)?;
+        Ok((response, String::from_utf8(body)?))
+    }
+
+    fn header<'a>(response: &'a str, name: &str) -> &'a str {
+        response
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .unwrap()
+            .trim()
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_interleaved() -> Result<(), Box<dyn Error>> {
+        let server = RtspServer::bind("127.0.0.1:0", FourCC(*b"H264"))?;
+        let packets = pcm_packets(5, 1)?;
+        server.send(&packets[0])?;
+
+        let url = format!("rtsp://{}/stream", server.local_addr());
+        let mut reader = BufReader::new(TcpStream::connect(server.local_addr())?);
+        let (response, sdp) = request(&mut reader, "DESCRIBE", &url, "")?;
+        assert!(response.starts_with("RTSP/1.0 200 OK"));
+        assert!(sdp.contains("a=rtpmap:96 H264/90000"));
+        assert!(sdp.contains("sprop-parameter-sets="));
+
+        let (response, _) = request(
+            &mut reader,
+            "SETUP",
+            &format!("{}/trackID=0", url),
+            "Transport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n",
+        )?;
+        assert!(header(&response, "Transport").contains("interleaved=0-1"));
+        let session = header(&response, "Session")
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned();
+        let (response, _) = request(
+            &mut reader,
+            "PLAY",
+            &url,
+            &format!("Session: {}\r\n", session),
+        )?;
+        assert!(response.starts_with("RTSP/1.0 200 OK"));
+        assert_eq!(server.viewers(), 1);
+
+        for packet in &packets {
+            server.send(packet)?;
+        }
+
+        let mut depacketizer = Depacketizer::new(FourCC(*b"H264"))?;
+        let mut received = Vec::new();
+        while received.len() < packets.len() {
+            let mut header = [0; 4];
+            reader.read_exact(&mut header)?;
+            assert_eq!(header[0], b'$');
+            assert_eq!(header[1], 0);
+            let mut rtp = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize];
+            reader.read_exact(&mut rtp)?;
+            depacketizer.push(&rtp)?;
+            received.extend(depacketizer.drain());
+        }
+        for (sent, received) in packets.iter().zip(&received) {
+            assert_eq!(sent.data, received.data);
+        }
+
+        let (response, _) = request(
+            &mut reader,
+            "TEARDOWN",
+            &url,
+            &format!("Session: {}\r\n", session),
+        )?;
+        assert!(response.starts_with("RTSP/1.0 200 OK"));
+        assert_eq!(server.viewers(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_udp() -> Result<(), Box<dyn Error>> {
+        let server = RtspServer::bind("127.0.0.1:0", FourCC(*b"H264"))?;
+        let packets = pcm_packets(5, 1)?;
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let port = socket.local_addr()?.port();
+
+        let url = format!("rtsp://{}/stream", server.local_addr());
+        let mut reader = BufReader::new(TcpStream::connect(server.local_addr())?);
+        let (response, _) = request(
+            &mut reader,
+            "SETUP",
+            &url,
+            &format!(
+                "Transport: RTP/AVP;unicast;client_port={}-{}\r\n",
+                port,
+                port + 1
+            ),
+        )?;
+        assert!(header(&response, "Transport").contains("server_port="));
+        let session = header(&response, "Session")
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned();
+
+        let (response, _) = request(&mut reader, "PLAY", &url, "Session: unknown\r\n")?;
+        assert!(response.starts_with("RTSP/1.0 454"));
+        request(
+            &mut reader,
+            "PLAY",
+            &url,
+            &format!("Session: {}\r\n", session),
+        )?;
+
+        let mut depacketizer = Depacketizer::new(FourCC(*b"H264"))?;
+        let mut received = Vec::new();
+        let mut buffer = [0; 2048];
+        for packet in &packets {
+            server.send(packet)?;
+            let count = received.len();
+            while received.len() == count {
+                let len = socket.recv(&mut buffer)?;
+                depacketizer.push(&buffer[..len])?;
+                received.extend(depacketizer.drain());
+            }
+        }
+        assert_eq!(received.len(), packets.len());
+        assert_eq!(received[0].data, packets[0].data);
+
+        // Closing the connection ends the session.
+        drop(reader);
+        for _ in 0..100 {
+            if server.viewers() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.viewers(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_request() -> Result<(), Box<dyn Error>> {
+        let server = RtspServer::bind("127.0.0.1:0", FourCC(*b"H264"))?;
+        let mut stream = TcpStream::connect(server.local_addr())?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.write_all(&vec![b'A'; MAX_REQUEST_SIZE as usize])?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert_eq!(response, "RTSP/1.0 400 Bad Request\r\n\r\n");
+
+        let mut reader = BufReader::new(TcpStream::connect(server.local_addr())?);
+        let url = format!("rtsp://{}/stream", server.local_addr());
+        let (response, _) = request(&mut reader, "OPTIONS", &url, "")?;
+        assert!(response.starts_with("RTSP/1.0 200 OK"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_viewer() -> Result<(), Box<dyn Error>> {
+        let server = RtspServer::bind("127.0.0.1:0", FourCC(*b"H264"))?;
+        let packets = pcm_packets(1, 1)?;
+        let url = format!("rtsp://{}/stream", server.local_addr());
+
+        // The viewer plays the stream but never reads the packets.
+        let mut reader = BufReader::new(TcpStream::connect(server.local_addr())?);
+        let (response, _) = request(
+            &mut reader,
+            "SETUP",
+            &url,
+            "Transport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n",
+        )?;
+        let session = header(&response, "Session")
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned();
+        request(
+            &mut reader,
+            "PLAY",
+            &url,
+            &format!("Session: {}\r\n", session),
+        )?;
+        assert_eq!(server.viewers(), 1);
+
+        let mut packet = packets[0].clone();
+        packet.data.resize(packet.data.len() + 256 * 1024, 0x55);
+        for _ in 0..1000 {
+            if server.viewers() == 0 {
+                break;
+            }
+            let start = Instant::now();
+            server.send(&packet)?;
+            assert!(start.elapsed() < WRITE_TIMEOUT / 2);
+        }
+        assert_eq!(server.viewers(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    decoder::{DecodeReturnCode, VideoDecoder},
+    encoder::{EncodedPacket, VSLRect, VideoEncoder},
+    frame::Frame,
+    nal::{h264_nal_type, split_annexb, H264_NAL_IDR, H264_NAL_SLICE},
+};
+use openh264::{
+    decoder::Decoder as H264Decoder,
+    encoder::{BitRate, Encoder as H264Encoder, EncoderConfig, FrameRate, FrameType},
+    formats::{YUVBuffer, YUVSource},
+    OpenH264API,
+};
+use std::{error::Error, io};
+
+/// Default target bitrate of the [`SoftwareEncoder`] in bits per second.
+const DEFAULT_BITRATE: u32 = 5_000_000;
+
+/// The SoftwareEncoder encodes frames to H.264 with the openh264 library, for
+/// hosts without a hardware encoder.  Source frames in any format supported
+/// by [`crate::fourcc::Layout`] are converted to I420 before encoding.
+///
+/// The encoder is created with the resolution of the first frame and
+/// reconfigured when the resolution changes.  Frames dropped by the rate
+/// control produce packets without data.
+pub struct SoftwareEncoder {
+    bitrate: u32,
+    frame_rate: f32,
+    gop: u32,
+    frames: u64,
+    encoder: Option<H264Encoder>,
+}
+
+impl Default for SoftwareEncoder {
+    fn default() -> Self {
+        SoftwareEncoder {
+            bitrate: DEFAULT_BITRATE,
+            frame_rate: 30.0,
+            gop: 30,
+            frames: 0,
+            encoder: None,
+        }
+    }
+}
+
+impl SoftwareEncoder {
+    pub fn new() -> Self {
+        SoftwareEncoder::default()
+    }
+
+    /// Sets the target bitrate in bits per second.
+    pub fn with_bitrate(mut self, bitrate: u32) -> Self {
+        self.bitrate = bitrate;
+        self
+    }
+
+    /// Sets the frame rate used by the rate control.
+    pub fn with_frame_rate(mut self, fps: f32) -> Self {
+        self.frame_rate = fps;
+        self
+    }
+
+    /// Sets the number of frames between IDR pictures, every frame being an
+    /// IDR picture when one.  Zero leaves the keyframes to the encoder.
+    pub fn with_gop(mut self, gop: u32) -> Self {
+        self.gop = gop;
+        self
+    }
+}
+
+impl VideoEncoder for SoftwareEncoder {
+    fn encode(&mut self, source: &Frame) -> Result<EncodedPacket, Box<dyn Error>> {
+        let yuv = read_i420(source)?;
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => {
+                let config = EncoderConfig::new()
+                    .bitrate(BitRate::from_bps(self.bitrate))
+                    .max_frame_rate(FrameRate::from_hz(self.frame_rate));
+                let encoder = H264Encoder::with_api_config(OpenH264API::from_source(), config)?;
+                self.encoder.insert(encoder)
+            }
+        };
+
+        if self.gop > 0 && self.frames.is_multiple_of(self.gop as u64) {
+            encoder.force_intra_frame();
+        }
+        self.frames += 1;
+
+        let bitstream = encoder.encode(&yuv)?;
+        Ok(EncodedPacket {
+            data: bitstream.to_vec(),
+            pts: source.pts(),
+            dts: source.dts(),
+            duration: source.duration(),
+            keyframe: matches!(bitstream.frame_type(), FrameType::IDR),
+        })
+    }
+}
+
+/// The SoftwareDecoder decodes H.264 streams into NV12 frames with the
+/// openh264 library, for hosts without a hardware decoder.  Constrained
+/// Baseline, Main and High profile progressive streams are supported.
+///
+/// The library decodes whole access units so the NAL units are gathered until
+/// the first NAL unit of the following access unit is received, or the
+/// decoder is flushed.
+pub struct SoftwareDecoder {
+    decoder: H264Decoder,
+    access_unit: Vec<u8>,
+    has_slice: bool,
+    width: i32,
+    height: i32,
+}
+
+impl SoftwareDecoder {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        Ok(SoftwareDecoder {
+            decoder: H264Decoder::new()?,
+            access_unit: Vec::new(),
+            has_slice: false,
+            width: 0,
+            height: 0,
+        })
+    }
+
+    fn decode_access_unit(&mut self, data: &[u8]) -> Result<Option<Frame>, Box<dyn Error>> {
+        Ok(match self.decoder.decode(data)? {
+            Some(yuv) => Some(write_nv12(&yuv)?),
+            None => None,
+        })
+    }
+
+    /// Records the resolution of the decoded frame, returning whether it
+    /// differs from that of the previous frame.
+    fn resize(&mut self, frame: &Frame) -> bool {
+        let resolution = (frame.width(), frame.height());
+        if resolution == (self.width, self.height) {
+            return false;
+        }
+        (self.width, self.height) = resolution;
+        true
+    }
+}
+
+impl VideoDecoder for SoftwareDecoder {
+    fn decode_frame(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(DecodeReturnCode, usize, Option<Frame>), Box<dyn Error>> {
+        let mut start = 0;
+        for (nal, end) in split_annexb(data) {
+            if !self.has_slice || !starts_access_unit(nal) {
+                self.access_unit.extend_from_slice(&data[start..end]);
+                self.has_slice |= is_slice(nal);
+                start = end;
+                continue;
+            }
+
+            // Return the bytes gathered so far so that the NAL unit starting
+            // the next access unit is always the first of the data, which is
+            // the unit skipped by the stream decoder should decoding fail.
+            if start > 0 {
+                return Ok((DecodeReturnCode::Success, start, None));
+            }
+            let access_unit = std::mem::replace(&mut self.access_unit, data[..end].to_vec());
+            self.has_slice = is_slice(nal);
+            let frame = self.decode_access_unit(&access_unit)?;
+            let code = match &frame {
+                Some(frame) if self.resize(frame) => DecodeReturnCode::Initialized,
+                Some(_) => DecodeReturnCode::FrameDecoded,
+                None => DecodeReturnCode::Success,
+            };
+            return Ok((code, end, frame));
+        }
+        Ok((DecodeReturnCode::Success, data.len(), None))
+    }
+
+    fn flush(&mut self) -> Result<Vec<Frame>, Box<dyn Error>> {
+        let mut frames = Vec::new();
+        let access_unit = std::mem::take(&mut self.access_unit);
+        if std::mem::take(&mut self.has_slice) {
+            frames.extend(self.decode_access_unit(&access_unit)?);
+        }
+        for yuv in self.decoder.flush_remaining()? {
+            frames.push(write_nv12(&yuv)?);
+        }
+        if let Some(frame) = frames.last() {
+            self.resize(frame);
+        }
+        Ok(frames)
+    }
+
+    fn width(&self) -> i32 {
+        self.width
+    }
+
+    fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn crop(&self) -> VSLRect {
+        VSLRect::new(0, 0, self.width, self.height)
+    }
+}
+
+fn is_slice(nal: &[u8]) -> bool {
+    matches!(h264_nal_type(nal), H264_NAL_SLICE | H264_NAL_IDR)
+}
+
+/// Returns whether the NAL unit begins an access unit when following a
+/// slice, that is a parameter set, SEI or delimiter or the first slice of a
+/// picture.
+fn starts_access_unit(nal: &[u8]) -> bool {
+    match h264_nal_type(nal) {
+        // A first_mb_in_slice of zero is coded as a single set bit.
+        H264_NAL_SLICE | H264_NAL_IDR => nal.get(1).is_some_and(|byte| byte & 0x80 != 0),
+        6..=9 | 14..=18 => true,
+        _ => false,
+    }
+}
+
+/// Converts the frame to a contiguous I420 picture, taking the chroma of the
+/// top left pixel of each block for formats with full resolution chroma.
+fn read_i420(frame: &Frame) -> Result<YUVBuffer, Box<dyn Error>> {
+    let layout = frame.layout()?;
+    let (width, height) = (layout.width, layout.height);
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        return Err("software encoder requires even frame dimensions".into());
+    }
+    let data = frame
+        .mmap()
+        .map_err(|_| io::Error::other("failed to map source frame"))?;
+    layout.check(data)?;
+
+    let mut yuv = vec![0; width * height * 3 / 2];
+    let (luma, chroma) = yuv.split_at_mut(width * height);
+    let (u, v) = chroma.split_at_mut(width * height / 4);
+    for y in 0..height {
+        for x in 0..width {
+            let [l, cb, cr] = layout.yuv(data, x, y);
+            luma[y * width + x] = l;
+            if x.is_multiple_of(2) && y.is_multiple_of(2) {
+                let offset = y / 2 * (width / 2) + x / 2;
+                u[offset] = cb;
+                v[offset] = cr;
+            }
+        }
+    }
+
+    Ok(YUVBuffer::from_vec(yuv, width, height))
+}
+
+/// Writes the decoded planes into a newly allocated NV12 frame.
+fn write_nv12(yuv: &impl YUVSource) -> Result<Frame, Box<dyn Error>> {
+    let (width, height) = yuv.dimensions();
+    let (y_stride, u_stride, v_stride) = yuv.strides();
+    let frame = Frame::new(width.try_into()?, height.try_into()?, 0, "NV12")?;
+    frame.alloc(None)?;
+    let layout = frame.layout()?;
+    let data = frame
+        .mmap_mut()
+        .map_err(|_| io::Error::other("failed to map decoded frame"))?;
+    layout.check(data)?;
+
+    let (luma, chroma) = data.split_at_mut(layout.stride * height);
+    for row in 0..height {
+        luma[row * layout.stride..][..width].copy_from_slice(&yuv.y()[row * y_stride..][..width]);
+    }
+    let (u, v) = (yuv.u(), yuv.v());
+    for row in 0..height.div_ceil(2) {
+        let dst = &mut chroma[row * layout.chroma_stride..];
+        for col in 0..width.div_ceil(2) {
+            dst[col * 2] = u[row * u_stride + col];
+            dst[col * 2 + 1] = v[row * v_stride + col];
+        }
+    }
+
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decoder::{DecodeEvent, StreamDecoder},
+        fourcc::FourCC,
+        nal::{
+            tests::{baseline_pps, baseline_sps, write_nal},
+            BitWriter,
+        },
+        source::{FrameSource, Pattern, SyntheticSource},
+    };
+
+    /// Builds a 32x32 CAVLC stream of an IDR picture coded as two slices of
+    /// I_16x16 macroblocks followed by a P picture of skipped macroblocks.
+    ///
+    /// Every macroblock uses DC prediction with a single luma DC coefficient
+    /// of 6 at QP 38, adding 20 to each luma sample.  Intra prediction does
+    /// not cross slices so both rows decode with 148 on the left and 168 on
+    /// the right, the chroma remaining at 128.
+    fn fixture() -> Vec<u8> {
+        let mut stream = Vec::new();
+        write_nal(&mut stream, 0x67, &baseline_sps(32, 32));
+        write_nal(&mut stream, 0x68, &baseline_pps());
+
+        for first_mb in [0, 2] {
+            let mut w = BitWriter::new();
+            w.write_ue(first_mb);
+            w.write_ue(7); // slice_type: I, all slices of the picture
+            w.write_ue(0); // pic_parameter_set_id
+            w.write_bits(0, 4); // frame_num
+            w.write_ue(0); // idr_pic_id
+            w.write_bit(false); // no_output_of_prior_pics_flag
+            w.write_bit(false); // long_term_reference_flag
+            w.write_se(12); // slice_qp_delta
+            w.write_ue(1); // disable_deblocking_filter_idc
+            for _ in 0..2 {
+                w.write_ue(3); // mb_type: I_16x16_2_0_0, DC prediction
+                w.write_ue(0); // intra_chroma_pred_mode: DC
+                w.write_se(0); // mb_qp_delta
+                w.write_bits(0b000101, 6); // coeff_token: TotalCoeff 1, TrailingOnes 0
+                w.write_bits(1, 9); // level_prefix 8: level 6
+                w.write_bit(true); // total_zeros 0
+            }
+            write_nal(&mut stream, 0x65, &w.finish());
+        }
+
+        let mut w = BitWriter::new();
+        w.write_ue(0); // first_mb_in_slice
+        w.write_ue(5); // slice_type: P, all slices of the picture
+        w.write_ue(0); // pic_parameter_set_id
+        w.write_bits(1, 4); // frame_num
+        w.write_bit(false); // num_ref_idx_active_override_flag
+        w.write_bit(false); // ref_pic_list_modification_flag_l0
+        w.write_bit(false); // adaptive_ref_pic_marking_mode_flag
+        w.write_se(12); // slice_qp_delta
+        w.write_ue(1); // disable_deblocking_filter_idc
+        w.write_ue(4); // mb_skip_run
+        write_nal(&mut stream, 0x41, &w.finish());
+
+        stream
+    }
+
+    fn decode(stream: &[u8], chunk: usize) -> Result<Vec<DecodeEvent>, Box<dyn Error>> {
+        let mut decoder = StreamDecoder::new(SoftwareDecoder::new()?);
+        let mut events = Vec::new();
+        for data in stream.chunks(chunk) {
+            decoder.push(data);
+            for event in decoder.drain() {
+                events.push(event?);
+            }
+        }
+        for event in decoder.flush() {
+            events.push(event?);
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn test_fixture() -> Result<(), Box<dyn Error>> {
+        let events = decode(&fixture(), 7)?;
+        assert!(matches!(
+            events[0],
+            DecodeEvent::Initialized {
+                width: 32,
+                height: 32,
+                ..
+            }
+        ));
+
+        let frames: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                DecodeEvent::Frame(frame) => Some(frame),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(frames.len(), 2);
+        for frame in frames {
+            let layout = frame.layout()?;
+            let data = frame.mmap().unwrap();
+            for y in 0..32 {
+                assert_eq!(layout.yuv(data, 0, y), [148, 128, 128]);
+                assert_eq!(layout.yuv(data, 15, y), [148, 128, 128]);
+                assert_eq!(layout.yuv(data, 16, y), [168, 128, 128]);
+                assert_eq!(layout.yuv(data, 31, y), [168, 128, 128]);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), Box<dyn Error>> {
+        let mut source = SyntheticSource::new(FourCC(*b"NV12"))?
+            .with_resolution(64, 48)?
+            .with_pattern(Pattern::ColorBars);
+        let mut encoder = SoftwareEncoder::new().with_gop(4);
+
+        let mut stream = Vec::new();
+        let mut sources = Vec::new();
+        for i in 0..8 {
+            let frame = source.read_frame()?.unwrap().frame;
+            let packet = encoder.encode(&frame)?;
+            assert_eq!(packet.keyframe, i % 4 == 0);
+            stream.extend_from_slice(&packet.data);
+            sources.push(frame);
+        }
+
+        let events = decode(&stream, 100)?;
+        assert!(matches!(
+            events[0],
+            DecodeEvent::Initialized {
+                width: 64,
+                height: 48,
+                ..
+            }
+        ));
+        assert_eq!(events.len(), 9);
+        for (event, source) in events[1..].iter().zip(&sources) {
+            let DecodeEvent::Frame(decoded) = event else {
+                panic!("expected a decoded frame");
+            };
+            let (layout, expected) = (source.layout()?, source.mmap().unwrap());
+            let (decoded_layout, data) = (decoded.layout()?, decoded.mmap().unwrap());
+            let error: u32 = (0..48)
+                .flat_map(|y| (0..64).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let a = layout.yuv(expected, x, y)[0];
+                    let b = decoded_layout.yuv(data, x, y)[0];
+                    a.abs_diff(b) as u32
+                })
+                .sum();
+            assert!(
+                error / (64 * 48) < 4,
+                "mean luma error {}",
+                error / (64 * 48)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_formats() -> Result<(), Box<dyn Error>> {
+        let mut encoder = SoftwareEncoder::new();
+        for format in [b"YUYV", b"RGB3", b"GREY"] {
+            let mut source = SyntheticSource::new(FourCC(*format))?.with_resolution(32, 16)?;
+            let frame = source.read_frame()?.unwrap().frame;
+            assert!(!encoder.encode(&frame)?.data.is_empty());
+        }
+
+        let frame = Frame::new(31, 16, 0, "NV12")?;
+        frame.alloc(None)?;
+        assert!(encoder.encode(&frame).is_err());
+
+        Ok(())
+    }
+}
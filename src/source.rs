@@ -0,0 +1,656 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{
+    camera::CameraReader,
+    fourcc::{FourCC, Layout},
+    frame::Frame,
+    y4m::{Colorspace, Y4mReader},
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A frame read from a [`FrameSource`] along with its capture timestamp and
+/// duration in nanoseconds.
+pub struct SourceFrame {
+    pub frame: Frame,
+    pub timestamp: i64,
+    pub duration: i64,
+}
+
+/// The FrameSource trait abstracts where frames come from so that code built
+/// on the camera can run with a [`CameraReader`], a [`SyntheticSource`] or a
+/// [`FileSource`], such as in tests without camera hardware.
+pub trait FrameSource {
+    fn width(&self) -> i32;
+
+    fn height(&self) -> i32;
+
+    fn format(&self) -> FourCC;
+
+    /// Reads the next frame, returning None once a finite source has ended.
+    fn read_frame(&mut self) -> Result<Option<SourceFrame>, Box<dyn Error>>;
+}
+
+/// Camera frames are copied out of the camera buffer into a newly allocated
+/// frame, so the buffer is returned to the camera immediately.  Use
+/// [`CameraReader::read`] directly for zero-copy access.
+impl FrameSource for CameraReader {
+    fn width(&self) -> i32 {
+        CameraReader::width(self)
+    }
+
+    fn height(&self) -> i32 {
+        CameraReader::height(self)
+    }
+
+    fn format(&self) -> FourCC {
+        CameraReader::format(self)
+    }
+
+    fn read_frame(&mut self) -> Result<Option<SourceFrame>, Box<dyn Error>> {
+        let buffer = self.read()?;
+        let frame = Frame::new(
+            buffer.width() as u32,
+            buffer.height() as u32,
+            0,
+            &buffer.format().to_string(),
+        )?;
+        frame.alloc(None)?;
+        match buffer.mmap() {
+            // The camera does not report its row stride so the buffer is
+            // read as packed rows, which the buffer must be large enough for.
+            Some(data) if buffer.format().bytes_per_pixel().is_some() => {
+                let layout = Layout::new(
+                    buffer.format(),
+                    buffer.width() as usize,
+                    buffer.height() as usize,
+                    0,
+                )?;
+                let target = frame.layout()?;
+                let output = frame.mmap_mut().map_err(|_| "failed to map frame")?;
+                layout.copy_to(data, &target, output)?;
+            }
+            _ => {
+                frame.copy_from(&Frame::try_from(&buffer)?, None)?;
+            }
+        }
+
+        let timestamp = buffer.timestamp();
+        Ok(Some(SourceFrame {
+            frame,
+            timestamp: timestamp.seconds() * 1_000_000_000 + timestamp.subsec(9) as i64,
+            duration: self.interval().as_nanos() as i64,
+        }))
+    }
+}
+
+/// The test patterns drawn by a [`SyntheticSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Eight vertical bars of white, yellow, cyan, green, magenta, red, blue
+    /// and black.
+    ColorBars,
+    /// A white box moving across a gray background.
+    MovingBox,
+}
+
+/// The SyntheticSource generates test pattern frames at a fixed rate, with
+/// the frame number burned into the top left corner.  RGB3, BGR3, RGBA, BGRA,
+/// RGBX, BGRX, GREY, YUYV, NV12 and I420 frames can be generated.
+pub struct SyntheticSource {
+    width: i32,
+    height: i32,
+    format: FourCC,
+    pattern: Pattern,
+    interval: Duration,
+    counter: bool,
+    realtime: bool,
+    index: u64,
+    start: Option<(Instant, i64)>,
+}
+
+impl SyntheticSource {
+    /// Creates a 640x480 color bars source at 30 frames per second.
+    pub fn new(format: FourCC) -> Result<Self, Box<dyn Error>> {
+        if format.bytes_per_pixel().is_none() {
+            return Err(format!("unsupported format {} for synthetic frames", format).into());
+        }
+        Ok(SyntheticSource {
+            width: 640,
+            height: 480,
+            format,
+            pattern: Pattern::ColorBars,
+            interval: Duration::from_secs(1) / 30,
+            counter: true,
+            realtime: true,
+            index: 0,
+            start: None,
+        })
+    }
+
+    /// Sets the frame size, which must be positive.
+    pub fn with_resolution(mut self, width: i32, height: i32) -> Result<Self, Box<dyn Error>> {
+        if width <= 0 || height <= 0 {
+            return Err(format!("invalid resolution {}x{}", width, height).into());
+        }
+        self.width = width;
+        self.height = height;
+        Ok(self)
+    }
+
+    pub fn with_pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Sets the frame rate, which must be positive and finite.
+    pub fn with_frame_rate(mut self, fps: f64) -> Result<Self, Box<dyn Error>> {
+        self.interval = frame_interval(fps)?;
+        Ok(self)
+    }
+
+    /// Enables the frame number burned into the frames, the default.
+    pub fn with_counter(mut self, counter: bool) -> Self {
+        self.counter = counter;
+        self
+    }
+
+    /// Paces the frames at the frame rate, the default, otherwise frames are
+    /// generated as fast as they are read with timestamps at the frame rate.
+    pub fn with_realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Draws the pattern of the frame as packed RGB.
+    fn draw(&self) -> Vec<u8> {
+        const BARS: [[u8; 3]; 8] = [
+            [191, 191, 191],
+            [191, 191, 0],
+            [0, 191, 191],
+            [0, 191, 0],
+            [191, 0, 191],
+            [191, 0, 0],
+            [0, 0, 191],
+            [0, 0, 0],
+        ];
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        match self.pattern {
+            Pattern::ColorBars => {
+                for _ in 0..height {
+                    for x in 0..width {
+                        rgb.extend_from_slice(&BARS[x * BARS.len() / width]);
+                    }
+                }
+            }
+            Pattern::MovingBox => {
+                let size = (height / 4).max(1);
+                let travel = width.saturating_sub(size).max(1);
+                let left = (self.index as usize * 4) % travel;
+                let top = (height - size) / 2;
+                for y in 0..height {
+                    for x in 0..width {
+                        let inside =
+                            (left..left + size).contains(&x) && (top..top + size).contains(&y);
+                        rgb.extend_from_slice(if inside { &[235; 3] } else { &[64; 3] });
+                    }
+                }
+            }
+        }
+        if self.counter {
+            burn_counter(&mut rgb, width, height, self.index);
+        }
+        rgb
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn width(&self) -> i32 {
+        self.width
+    }
+
+    fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn format(&self) -> FourCC {
+        self.format
+    }
+
+    fn read_frame(&mut self) -> Result<Option<SourceFrame>, Box<dyn Error>> {
+        let (start, first) = *self
+            .start
+            .get_or_insert_with(|| (Instant::now(), crate::timestamp()));
+        let offset = self.interval * self.index as u32;
+        if self.realtime {
+            thread::sleep((start + offset).saturating_duration_since(Instant::now()));
+        }
+
+        let frame = Frame::new(
+            self.width as u32,
+            self.height as u32,
+            0,
+            &self.format.to_string(),
+        )?;
+        frame.alloc(None)?;
+        pack(&frame, &self.draw())?;
+        self.index += 1;
+        Ok(Some(SourceFrame {
+            frame,
+            timestamp: first + offset.as_nanos() as i64,
+            duration: self.interval.as_nanos() as i64,
+        }))
+    }
+}
+
+/// The FileSource reads the frames of a YUV4MPEG2 file at the file's frame
+/// rate, or 30 frames per second when the file has none, optionally
+/// restarting from the beginning once it ends.
+pub struct FileSource {
+    path: PathBuf,
+    reader: Y4mReader<BufReader<File>>,
+    duration: i64,
+    realtime: bool,
+    looping: bool,
+    index: u64,
+    start: Option<(Instant, i64)>,
+}
+
+impl FileSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let reader = Y4mReader::open(&path)?;
+        let duration = match reader.frame_duration() {
+            0 => (Duration::from_secs(1) / 30).as_nanos() as i64,
+            duration => duration,
+        };
+        Ok(FileSource {
+            path: path.as_ref().to_owned(),
+            reader,
+            duration,
+            realtime: true,
+            looping: false,
+            index: 0,
+            start: None,
+        })
+    }
+
+    /// Overrides the frame rate of the file, which must be positive and
+    /// finite.
+    pub fn with_frame_rate(mut self, fps: f64) -> Result<Self, Box<dyn Error>> {
+        self.duration = frame_interval(fps)?.as_nanos() as i64;
+        Ok(self)
+    }
+
+    /// Restarts the file once it ends, providing frames indefinitely.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Paces the frames at the file's frame rate, the default.
+    pub fn with_realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+}
+
+impl FrameSource for FileSource {
+    fn width(&self) -> i32 {
+        self.reader.width() as i32
+    }
+
+    fn height(&self) -> i32 {
+        self.reader.height() as i32
+    }
+
+    fn format(&self) -> FourCC {
+        match self.reader.colorspace() {
+            Colorspace::C420 => FourCC(*b"NV12"),
+            Colorspace::C422 => FourCC(*b"YUYV"),
+            Colorspace::Mono => FourCC(*b"GREY"),
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<SourceFrame>, Box<dyn Error>> {
+        let frame = match self.reader.read_frame()? {
+            Some(frame) => frame,
+            None if self.looping && self.index > 0 => {
+                self.reader = Y4mReader::open(&self.path)?;
+                match self.reader.read_frame()? {
+                    Some(frame) => frame,
+                    None => return Ok(None),
+                }
+            }
+            None => return Ok(None),
+        };
+
+        let (start, first) = *self
+            .start
+            .get_or_insert_with(|| (Instant::now(), crate::timestamp()));
+        let duration = self.duration;
+        let offset = self.index as i64 * duration;
+        if self.realtime {
+            let deadline = start + Duration::from_nanos(offset as u64);
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+        self.index += 1;
+        Ok(Some(SourceFrame {
+            frame,
+            timestamp: first + offset,
+            duration,
+        }))
+    }
+}
+
+/// The ImageSource reads the PNG and JPEG images of a directory in name order
+/// as RGB3 frames at 30 frames per second, optionally restarting from the
+/// first image once the last one is read.  The width and height are those of
+/// the first image.
+#[cfg(feature = "image")]
+pub struct ImageSource {
+    paths: Vec<PathBuf>,
+    width: i32,
+    height: i32,
+    interval: Duration,
+    realtime: bool,
+    looping: bool,
+    index: u64,
+    start: Option<(Instant, i64)>,
+}
+
+#[cfg(feature = "image")]
+impl ImageSource {
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<Self, Box<dyn Error>> {
+        let directory = directory.as_ref();
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| {
+                        matches!(ext.to_lowercase().as_str(), "png" | "jpg" | "jpeg")
+                    })
+            })
+            .collect();
+        paths.sort();
+        let first = paths
+            .first()
+            .ok_or_else(|| format!("no png or jpeg images in {}", directory.display()))?;
+        let (width, height) = image::image_dimensions(first)
+            .map_err(|err| format!("{}: {}", first.display(), err))?;
+        Ok(ImageSource {
+            paths,
+            width: width as i32,
+            height: height as i32,
+            interval: Duration::from_secs(1) / 30,
+            realtime: true,
+            looping: false,
+            index: 0,
+            start: None,
+        })
+    }
+
+    /// Sets the frame rate, which must be positive and finite.
+    pub fn with_frame_rate(mut self, fps: f64) -> Result<Self, Box<dyn Error>> {
+        self.interval = frame_interval(fps)?;
+        Ok(self)
+    }
+
+    /// Restarts from the first image once the last one is read, providing
+    /// frames indefinitely.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Paces the frames at the frame rate, the default.
+    pub fn with_realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+}
+
+#[cfg(feature = "image")]
+impl FrameSource for ImageSource {
+    fn width(&self) -> i32 {
+        self.width
+    }
+
+    fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn format(&self) -> FourCC {
+        FourCC(*b"RGB3")
+    }
+
+    fn read_frame(&mut self) -> Result<Option<SourceFrame>, Box<dyn Error>> {
+        let count = self.paths.len() as u64;
+        if self.index >= count && !self.looping {
+            return Ok(None);
+        }
+        let path = &self.paths[(self.index % count) as usize];
+        let frame = image::open(path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|image| Frame::from_image(&image.to_rgb8().into()))
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        let (start, first) = *self
+            .start
+            .get_or_insert_with(|| (Instant::now(), crate::timestamp()));
+        let offset = self.interval * self.index as u32;
+        if self.realtime {
+            thread::sleep((start + offset).saturating_duration_since(Instant::now()));
+        }
+        self.index += 1;
+        Ok(Some(SourceFrame {
+            frame,
+            timestamp: first + offset.as_nanos() as i64,
+            duration: self.interval.as_nanos() as i64,
+        }))
+    }
+}
+
+/// Returns the interval between frames at the frame rate, which must be
+/// positive and finite.
+fn frame_interval(fps: f64) -> Result<Duration, Box<dyn Error>> {
+    Duration::try_from_secs_f64(1.0 / fps)
+        .ok()
+        .filter(|interval| fps > 0.0 && !interval.is_zero())
+        .ok_or_else(|| format!("invalid frame rate {}", fps).into())
+}
+
+/// Packs the RGB pixels into the frame in its format.
+fn pack(frame: &Frame, rgb: &[u8]) -> Result<(), Box<dyn Error>> {
+    let layout = frame.layout()?;
+    let data = frame.mmap_mut().map_err(|_| "failed to map frame")?;
+    layout.check(data)?;
+    for (index, pixel) in rgb.chunks_exact(3).enumerate() {
+        let (x, y) = (index % layout.width, index / layout.width);
+        layout.set_rgb(data, x, y, [pixel[0], pixel[1], pixel[2]]);
+    }
+    Ok(())
+}
+
+/// Draws the number in the top left corner with a 3x5 pixel font scaled to
+/// the frame height, as black digits on a white background.
+fn burn_counter(rgb: &mut [u8], width: usize, height: usize, number: u64) {
+    const DIGITS: [[u8; 5]; 10] = [
+        [0b111, 0b101, 0b101, 0b101, 0b111],
+        [0b010, 0b110, 0b010, 0b010, 0b111],
+        [0b111, 0b001, 0b111, 0b100, 0b111],
+        [0b111, 0b001, 0b111, 0b001, 0b111],
+        [0b101, 0b101, 0b111, 0b001, 0b001],
+        [0b111, 0b100, 0b111, 0b001, 0b111],
+        [0b111, 0b100, 0b111, 0b101, 0b111],
+        [0b111, 0b001, 0b001, 0b001, 0b001],
+        [0b111, 0b101, 0b111, 0b101, 0b111],
+        [0b111, 0b101, 0b111, 0b001, 0b111],
+    ];
+    let scale = (height / 60).max(1);
+    let digits = number.to_string();
+    // Each digit is three columns wide plus a column of spacing, all on a
+    // border of one column and row.
+    let box_width = ((digits.len() * 4 + 1) * scale).min(width);
+    let box_height = (7 * scale).min(height);
+
+    for y in 0..box_height {
+        for x in 0..box_width {
+            let (column, row) = (x / scale, y / scale);
+            let lit = (1..6).contains(&row)
+                && column >= 1
+                && (column - 1) % 4 < 3
+                && digits
+                    .as_bytes()
+                    .get((column - 1) / 4)
+                    .is_some_and(|digit| {
+                        let glyph = DIGITS[(digit - b'0') as usize][row - 1];
+                        glyph & (0b100 >> ((column - 1) % 4)) != 0
+                    });
+            let value = if lit { 0 } else { 255 };
+            rgb[(y * width + x) * 3..(y * width + x) * 3 + 3].fill(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::y4m::Y4mWriter;
+
+    #[test]
+    fn test_synthetic() -> Result<(), Box<dyn Error>> {
+        assert!(SyntheticSource::new(FourCC(*b"H264")).is_err());
+        let source = || SyntheticSource::new(FourCC(*b"RGB3"));
+        assert!(source()?.with_resolution(0, 48).is_err());
+        assert!(source()?.with_resolution(64, -1).is_err());
+        for fps in [0.0, -30.0, f64::NAN, f64::INFINITY, 1e-300] {
+            assert!(source()?.with_frame_rate(fps).is_err(), "{}", fps);
+        }
+
+        let mut source = SyntheticSource::new(FourCC(*b"RGB3"))?
+            .with_resolution(64, 48)?
+            .with_frame_rate(25.0)?
+            .with_realtime(false);
+        let first = source.read_frame()?.unwrap();
+        let second = source.read_frame()?.unwrap();
+        assert_eq!(second.timestamp - first.timestamp, 40_000_000);
+        assert_eq!(first.duration, 40_000_000);
+
+        let first = first.frame.mmap().unwrap();
+        let second = second.frame.mmap().unwrap();
+        assert_eq!(first.len(), 64 * 48 * 3);
+        // The yellow second bar, below the counter.
+        assert_eq!(&first[(40 * 64 + 12) * 3..][..3], &[191, 191, 0]);
+        // The counter differs between frames while the bars do not.
+        assert_ne!(&first[..64 * 8 * 3], &second[..64 * 8 * 3]);
+        assert_eq!(&first[64 * 8 * 3..], &second[64 * 8 * 3..]);
+
+        for format in [b"BGRA", b"GREY", b"YUYV", b"NV12", b"I420"] {
+            let mut source = SyntheticSource::new(FourCC(*format))?
+                .with_resolution(16, 8)?
+                .with_pattern(Pattern::MovingBox)
+                .with_counter(false)
+                .with_realtime(false);
+            let frame = source.read_frame()?.unwrap().frame;
+            assert_eq!(FourCC::from(frame.fourcc()), FourCC(*format));
+            // The gray background is 64 in every channel, Y 71 once converted.
+            let expect = if format == b"BGRA" { 64 } else { 71 };
+            assert_eq!(frame.mmap().unwrap()[0], expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_file() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("source-{}.y4m", std::process::id()));
+        let mut source = SyntheticSource::new(FourCC(*b"NV12"))?
+            .with_resolution(32, 16)?
+            .with_realtime(false);
+        let mut writer = Y4mWriter::create(&path)?.with_frame_rate(50, 1);
+        let mut frames = Vec::new();
+        for _ in 0..3 {
+            let frame = source.read_frame()?.unwrap().frame;
+            writer.write_frame(&frame)?;
+            frames.push(frame.mmap().unwrap().to_vec());
+        }
+        writer.into_inner()?;
+
+        let mut file = FileSource::open(&path)?
+            .with_looping(true)
+            .with_realtime(false);
+        assert_eq!((file.width(), file.height()), (32, 16));
+        assert_eq!(file.format(), FourCC(*b"NV12"));
+        for index in 0..5 {
+            let read = file.read_frame()?.unwrap();
+            assert_eq!(read.duration, 20_000_000);
+            assert_eq!(read.frame.mmap().unwrap(), frames[index % 3]);
+        }
+
+        assert!(FileSource::open(&path)?.with_frame_rate(0.0).is_err());
+        let mut file = FileSource::open(&path)?
+            .with_frame_rate(25.0)?
+            .with_realtime(false);
+        assert_eq!(file.read_frame()?.unwrap().duration, 40_000_000);
+
+        let mut file = FileSource::open(&path)?.with_realtime(false);
+        let mut count = 0;
+        while file.read_frame()?.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_images() -> Result<(), Box<dyn Error>> {
+        let directory = std::env::temp_dir().join(format!("source-images-{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        for (name, value) in [("b.png", 20), ("a.png", 10), ("c.jpg", 30)] {
+            image::RgbImage::from_pixel(8, 4, image::Rgb([value; 3])).save(directory.join(name))?;
+        }
+        std::fs::write(directory.join("notes.txt"), "not an image")?;
+
+        let mut source = ImageSource::open(&directory)?
+            .with_frame_rate(10.0)?
+            .with_looping(true)
+            .with_realtime(false);
+        assert_eq!((source.width(), source.height()), (8, 4));
+        assert_eq!(source.format(), FourCC(*b"RGB3"));
+        let mut values = Vec::new();
+        for index in 0..4 {
+            let read = source.read_frame()?.unwrap();
+            assert_eq!(read.duration, 100_000_000);
+            assert_eq!(FourCC::from(read.frame.fourcc()), FourCC(*b"RGB3"));
+            if index == 0 {
+                assert_eq!(read.frame.mmap().unwrap().len(), 8 * 4 * 3);
+            }
+            values.push(read.frame.mmap().unwrap()[0]);
+        }
+        // The JPEG is lossy but a flat image stays close to its value.
+        assert_eq!(&values[..2], &[10, 20]);
+        assert!(values[2].abs_diff(30) <= 2);
+        assert_eq!(values[3], 10);
+
+        let mut source = ImageSource::open(&directory)?.with_realtime(false);
+        let mut count = 0;
+        while source.read_frame()?.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+
+        std::fs::remove_dir_all(&directory)?;
+        assert!(ImageSource::open(&directory).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+//! Minimal V4L2 ioctl definitions for the parts of the camera API which
+//! libvideostream does not wrap.
+
+use std::{io, mem, os::fd::RawFd};
+
+pub(crate) const BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+pub(crate) const BUF_TYPE_VIDEO_CAPTURE_MPLANE: u32 = 9;
+
+pub(crate) const CAP_TIMEPERFRAME: u32 = 0x1000;
+
+pub(crate) const CAP_VIDEO_CAPTURE: u32 = 0x0000_0001;
+pub(crate) const CAP_VIDEO_CAPTURE_MPLANE: u32 = 0x0000_1000;
+pub(crate) const CAP_DEVICE_CAPS: u32 = 0x8000_0000;
+
+pub(crate) const CTRL_FLAG_NEXT_CTRL: u32 = 0x8000_0000;
+pub(crate) const CTRL_TYPE_CTRL_CLASS: u32 = 6;
+
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct Capability {
+    pub driver: [u8; 16],
+    pub card: [u8; 32],
+    pub bus_info: [u8; 32],
+    pub version: u32,
+    pub capabilities: u32,
+    pub device_caps: u32,
+    pub reserved: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct QueryCtrl {
+    pub id: u32,
+    pub kind: u32,
+    pub name: [u8; 32],
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default_value: i32,
+    pub flags: u32,
+    pub reserved: [u32; 2],
+}
+
+#[repr(C, packed)]
+#[derive(Default)]
+pub(crate) struct QueryMenu {
+    pub id: u32,
+    pub index: u32,
+    /// The name of a menu item, or the value of an integer menu item in the
+    /// first eight bytes.
+    pub name: [u8; 32],
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct Control {
+    pub id: u32,
+    pub value: i32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Fract {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct CaptureParm {
+    pub capability: u32,
+    pub capturemode: u32,
+    pub timeperframe: Fract,
+    pub extendedmode: u32,
+    pub readbuffers: u32,
+    pub reserved: [u32; 4],
+}
+
+#[repr(C)]
+pub(crate) struct StreamParm {
+    pub kind: u32,
+    pub capture: CaptureParm,
+    pub reserved: [u8; 160],
+}
+
+impl Default for StreamParm {
+    fn default() -> Self {
+        StreamParm {
+            kind: 0,
+            capture: CaptureParm::default(),
+            reserved: [0; 160],
+        }
+    }
+}
+
+pub(crate) const FRMSIZE_TYPE_DISCRETE: u32 = 1;
+pub(crate) const FRMIVAL_TYPE_DISCRETE: u32 = 1;
+
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct FrmSizeEnum {
+    pub index: u32,
+    pub pixel_format: u32,
+    pub kind: u32,
+    /// The width and height of discrete sizes, otherwise the minimum,
+    /// maximum and step of the width followed by those of the height.
+    pub size: [u32; 6],
+    pub reserved: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct FrmIvalEnum {
+    pub index: u32,
+    pub pixel_format: u32,
+    pub width: u32,
+    pub height: u32,
+    pub kind: u32,
+    /// The discrete interval, otherwise the minimum, maximum and step
+    /// intervals, each as a numerator and denominator.
+    pub interval: [u32; 6],
+    pub reserved: [u32; 2],
+}
+
+const fn ior<T>(nr: u32) -> u64 {
+    (2 << 30) | ((mem::size_of::<T>() as u64) << 16) | ((b'V' as u64) << 8) | nr as u64
+}
+
+const fn iowr<T>(nr: u32) -> u64 {
+    (3 << 30) | ((mem::size_of::<T>() as u64) << 16) | ((b'V' as u64) << 8) | nr as u64
+}
+
+pub(crate) const VIDIOC_QUERYCAP: u64 = ior::<Capability>(0);
+pub(crate) const VIDIOC_G_PARM: u64 = iowr::<StreamParm>(21);
+pub(crate) const VIDIOC_S_PARM: u64 = iowr::<StreamParm>(22);
+pub(crate) const VIDIOC_G_CTRL: u64 = iowr::<Control>(27);
+pub(crate) const VIDIOC_S_CTRL: u64 = iowr::<Control>(28);
+pub(crate) const VIDIOC_QUERYCTRL: u64 = iowr::<QueryCtrl>(36);
+pub(crate) const VIDIOC_QUERYMENU: u64 = iowr::<QueryMenu>(37);
+pub(crate) const VIDIOC_ENUM_FRAMESIZES: u64 = iowr::<FrmSizeEnum>(74);
+pub(crate) const VIDIOC_ENUM_FRAMEINTERVALS: u64 = iowr::<FrmIvalEnum>(75);
+
+/// Issues the ioctl, restarting it when interrupted by a signal.
+pub(crate) fn ioctl<T>(fd: RawFd, request: u64, arg: &mut T) -> io::Result<()> {
+    loop {
+        if unsafe { libc::ioctl(fd, request as _, arg as *mut T) } != -1 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Returns the NUL terminated string of a fixed size V4L2 name field.
+pub(crate) fn name(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(mem::size_of::<Capability>(), 104);
+        assert_eq!(mem::size_of::<QueryCtrl>(), 68);
+        assert_eq!(mem::size_of::<QueryMenu>(), 44);
+        assert_eq!(mem::size_of::<StreamParm>(), 204);
+        assert_eq!(mem::size_of::<FrmSizeEnum>(), 44);
+        assert_eq!(mem::size_of::<FrmIvalEnum>(), 52);
+        assert_eq!(VIDIOC_QUERYCAP, 0x80685600);
+        assert_eq!(VIDIOC_ENUM_FRAMEINTERVALS, 0xc034564b);
+        assert_eq!(VIDIOC_QUERYCTRL, 0xc0445624);
+        assert_eq!(VIDIOC_S_PARM, 0xc0cc5616);
+        assert_eq!(name(b"vivid\0\0\0"), "vivid");
+    }
+}
@@ -0,0 +1,474 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Au-Zone Technologies
+
+use crate::{fourcc::FourCC, frame::Frame};
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// The largest width or height of a stream which is read.
+const MAX_DIMENSION: usize = 16384;
+
+/// The chroma subsampling of a Y4M stream, as given by its C tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colorspace {
+    /// 4:2:0 planar with JPEG chroma siting, the default of the format.
+    C420,
+    /// 4:2:2 planar.
+    C422,
+    /// Luma only.
+    Mono,
+}
+
+impl Colorspace {
+    fn tag(&self) -> &'static str {
+        match self {
+            Colorspace::C420 => "420jpeg",
+            Colorspace::C422 => "422",
+            Colorspace::Mono => "mono",
+        }
+    }
+
+    fn parse(tag: &str) -> Result<Self, Box<dyn Error>> {
+        match tag {
+            "420" | "420jpeg" | "420mpeg2" | "420paldv" => Ok(Colorspace::C420),
+            "422" => Ok(Colorspace::C422),
+            "mono" => Ok(Colorspace::Mono),
+            tag => Err(format!("unsupported y4m colorspace {}", tag).into()),
+        }
+    }
+
+    /// Returns the colorspace stored for frames of the fourcc.
+    fn from_fourcc(fourcc: FourCC) -> Result<Self, Box<dyn Error>> {
+        match &fourcc.0 {
+            b"NV12" | b"I420" | b"YU12" => Ok(Colorspace::C420),
+            b"YUYV" => Ok(Colorspace::C422),
+            b"GREY" => Ok(Colorspace::Mono),
+            _ => Err(format!("unsupported format {} for y4m", fourcc).into()),
+        }
+    }
+
+    /// Returns the size of a frame in bytes.
+    fn frame_size(&self, width: usize, height: usize) -> usize {
+        match self {
+            Colorspace::C420 => width * height + 2 * width.div_ceil(2) * height.div_ceil(2),
+            Colorspace::C422 => width * height + 2 * width.div_ceil(2) * height,
+            Colorspace::Mono => width * height,
+        }
+    }
+}
+
+/// The Y4mWriter writes frames to a YUV4MPEG2 stream, the interchange format
+/// of ffmpeg and codec test suites.
+///
+/// The stream header is written with the first frame, which sets the
+/// resolution and colorspace of the stream.  NV12 and I420 frames are stored
+/// as 4:2:0, YUYV frames as 4:2:2 and GREY frames as mono, converting the
+/// interleaved formats to planar.
+pub struct Y4mWriter<W: Write = BufWriter<File>> {
+    writer: W,
+    rate: (u32, u32),
+    header: Option<(usize, usize, Colorspace)>,
+}
+
+impl Y4mWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Creates a writer at 30 frames per second.
+    pub fn new(writer: W) -> Self {
+        Y4mWriter {
+            writer,
+            rate: (30, 1),
+            header: None,
+        }
+    }
+
+    /// Sets the frame rate as a fraction, such as 30000/1001.
+    pub fn with_frame_rate(mut self, numerator: u32, denominator: u32) -> Self {
+        self.rate = (numerator, denominator.max(1));
+        self
+    }
+
+    /// Writes the frame, which must be locked if received from a client.
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<(), Box<dyn Error>> {
+        let fourcc = FourCC::from(frame.fourcc());
+        let colorspace = Colorspace::from_fourcc(fourcc)?;
+        let data = frame.mmap().map_err(|_| "failed to map frame")?;
+        let layout = frame.layout()?;
+        layout.check(data)?;
+        let (width, height, stride) = (layout.width, layout.height, layout.stride);
+
+        match self.header {
+            Some(header) if header != (width, height, colorspace) => {
+                return Err(format!(
+                    "{}x{} {} frame does not match the y4m stream",
+                    width, height, fourcc
+                )
+                .into())
+            }
+            Some(_) => (),
+            None => {
+                writeln!(
+                    self.writer,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C{}",
+                    width,
+                    height,
+                    self.rate.0,
+                    self.rate.1,
+                    colorspace.tag()
+                )?;
+                self.header = Some((width, height, colorspace));
+            }
+        }
+
+        let row = |y: usize, len: usize| &data[y * stride..y * stride + len];
+        let chroma_width = width.div_ceil(2);
+        let chroma_height = height.div_ceil(2);
+        self.writer.write_all(b"FRAME\n")?;
+        match &fourcc.0 {
+            b"NV12" => {
+                for y in 0..height {
+                    self.writer.write_all(row(y, width))?;
+                }
+                for plane in 0..2 {
+                    for y in 0..chroma_height {
+                        let uv = row(height + y, chroma_width * 2);
+                        let samples: Vec<u8> = uv.iter().skip(plane).step_by(2).copied().collect();
+                        self.writer.write_all(&samples)?;
+                    }
+                }
+            }
+            b"I420" | b"YU12" => {
+                for y in 0..height {
+                    self.writer.write_all(row(y, width))?;
+                }
+                let chroma_stride = layout.chroma_stride;
+                let planes = &data[stride * height..];
+                for plane in 0..2 {
+                    let start = plane * chroma_stride * chroma_height;
+                    for y in 0..chroma_height {
+                        let offset = start + y * chroma_stride;
+                        self.writer
+                            .write_all(&planes[offset..offset + chroma_width])?;
+                    }
+                }
+            }
+            b"YUYV" => {
+                for (offset, step) in [(0, 2), (1, 4), (3, 4)] {
+                    for y in 0..height {
+                        let samples: Vec<u8> = row(y, width * 2)
+                            .iter()
+                            .skip(offset)
+                            .step_by(step)
+                            .copied()
+                            .collect();
+                        self.writer.write_all(&samples)?;
+                    }
+                }
+            }
+            _ => {
+                for y in 0..height {
+                    self.writer.write_all(row(y, width))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(mut self) -> Result<W, Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// The Y4mReader reads the frames of a YUV4MPEG2 stream into newly allocated
+/// frames.
+///
+/// Frames are NV12 for 4:2:0 streams unless another fourcc is requested
+/// through [`Y4mReader::with_fourcc`], YUYV for 4:2:2 streams and GREY for
+/// mono streams.
+pub struct Y4mReader<R: BufRead = BufReader<File>> {
+    reader: R,
+    width: usize,
+    height: usize,
+    rate: (u32, u32),
+    colorspace: Colorspace,
+    fourcc: FourCC,
+    buffer: Vec<u8>,
+}
+
+impl Y4mReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> Y4mReader<R> {
+    /// Creates the reader, reading the stream header.
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let mut params = header.split_whitespace();
+        if params.next() != Some("YUV4MPEG2") {
+            return Err("missing YUV4MPEG2 stream header".into());
+        }
+
+        let (mut width, mut height) = (0, 0);
+        let mut rate = (30, 1);
+        let mut colorspace = Colorspace::C420;
+        for param in params {
+            let mut chars = param.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            match tag {
+                Some('W') => width = value.parse()?,
+                Some('H') => height = value.parse()?,
+                Some('F') => {
+                    let (num, den) = value.split_once(':').ok_or("invalid y4m frame rate")?;
+                    rate = (num.parse()?, den.parse()?);
+                }
+                Some('C') => colorspace = Colorspace::parse(value)?,
+                Some('I') if value != "p" && value != "?" => {
+                    return Err("interlaced y4m streams are not supported".into())
+                }
+                _ => (),
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err("y4m header is missing the resolution".into());
+        }
+        // The frame buffer is sized from the header, which is untrusted.
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(format!("y4m resolution {}x{} is too large", width, height).into());
+        }
+
+        let fourcc = match colorspace {
+            Colorspace::C420 => FourCC(*b"NV12"),
+            Colorspace::C422 => FourCC(*b"YUYV"),
+            Colorspace::Mono => FourCC(*b"GREY"),
+        };
+        Ok(Y4mReader {
+            reader,
+            width,
+            height,
+            rate,
+            colorspace,
+            fourcc,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Reads 4:2:0 streams into frames of the fourcc, NV12 or I420.
+    pub fn with_fourcc(mut self, fourcc: FourCC) -> Result<Self, Box<dyn Error>> {
+        if Colorspace::from_fourcc(fourcc)? != self.colorspace {
+            return Err(
+                format!("cannot read {:?} y4m frames as {}", self.colorspace, fourcc).into(),
+            );
+        }
+        self.fourcc = fourcc;
+        Ok(self)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn colorspace(&self) -> Colorspace {
+        self.colorspace
+    }
+
+    /// Returns the frame rate as a fraction.
+    pub fn frame_rate(&self) -> (u32, u32) {
+        self.rate
+    }
+
+    /// Returns the duration of a frame in nanoseconds.
+    pub fn frame_duration(&self) -> i64 {
+        match self.rate {
+            (0, _) => 0,
+            (num, den) => den as i64 * 1_000_000_000 / num as i64,
+        }
+    }
+
+    /// Reads the next frame, returns None at the end of the stream.
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, Box<dyn Error>> {
+        let mut marker = String::new();
+        if self.reader.read_line(&mut marker)? == 0 {
+            return Ok(None);
+        }
+        if !marker.starts_with("FRAME") {
+            return Err("invalid y4m frame header".into());
+        }
+
+        let (width, height) = (self.width, self.height);
+        self.buffer
+            .resize(self.colorspace.frame_size(width, height), 0);
+        self.reader.read_exact(&mut self.buffer)?;
+
+        let frame = Frame::new(width as u32, height as u32, 0, &self.fourcc.to_string())?;
+        frame.alloc(None)?;
+        let data = frame.mmap_mut().map_err(|_| "failed to map frame")?;
+        let layout = frame.layout()?;
+        layout.check(data)?;
+        let stride = layout.stride;
+
+        let (luma, chroma) = self.buffer.split_at(width * height);
+        let chroma_width = width.div_ceil(2);
+        let chroma_height = height.div_ceil(2);
+        match &self.fourcc.0 {
+            b"NV12" => {
+                copy_plane(data, stride, luma, width, height);
+                let (u, v) = chroma.split_at(chroma_width * chroma_height);
+                for y in 0..chroma_height {
+                    let row = &mut data[(height + y) * stride..];
+                    for x in 0..chroma_width {
+                        row[x * 2] = u[y * chroma_width + x];
+                        row[x * 2 + 1] = v[y * chroma_width + x];
+                    }
+                }
+            }
+            b"I420" | b"YU12" => {
+                copy_plane(data, stride, luma, width, height);
+                let chroma_stride = layout.chroma_stride;
+                let planes = &mut data[stride * height..];
+                for (plane, samples) in chroma.chunks(chroma_width * chroma_height).enumerate() {
+                    let start = plane * chroma_stride * chroma_height;
+                    copy_plane(
+                        &mut planes[start..],
+                        chroma_stride,
+                        samples,
+                        chroma_width,
+                        chroma_height,
+                    );
+                }
+            }
+            b"YUYV" => {
+                let (u, v) = chroma.split_at(chroma_width * height);
+                for y in 0..height {
+                    let row = &mut data[y * stride..];
+                    for x in 0..width {
+                        row[x * 2] = luma[y * width + x];
+                        let c = y * chroma_width + x / 2;
+                        row[x * 2 + 1] = if x % 2 == 0 { u[c] } else { v[c] };
+                    }
+                }
+            }
+            _ => copy_plane(data, stride, luma, width, height),
+        }
+        Ok(Some(frame))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn copy_plane(dst: &mut [u8], stride: usize, src: &[u8], width: usize, height: usize) {
+    for y in 0..height {
+        dst[y * stride..y * stride + width].copy_from_slice(&src[y * width..(y + 1) * width]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frame(fourcc: &str, width: u32, height: u32) -> Result<Frame, Box<dyn Error>> {
+        let frame = Frame::new(width, height, 0, fourcc)?;
+        frame.alloc(None)?;
+        for (i, byte) in frame.mmap_mut().unwrap().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        Ok(frame)
+    }
+
+    #[test]
+    fn test_nv12() -> Result<(), Box<dyn Error>> {
+        let source = frame("NV12", 4, 2)?;
+        let mut writer = Y4mWriter::new(Vec::new()).with_frame_rate(30000, 1001);
+        writer.write_frame(&source)?;
+        writer.write_frame(&source)?;
+        let stream = writer.into_inner()?;
+
+        let header = b"YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C420jpeg\n";
+        assert_eq!(&stream[..header.len()], header);
+        assert_eq!(
+            &stream[header.len()..header.len() + 18],
+            b"FRAME\n\x00\x01\x02\x03\x04\x05\x06\x07\x08\x0a\x09\x0b"
+        );
+
+        let mut reader = Y4mReader::new(Cursor::new(&stream))?;
+        assert_eq!((reader.width(), reader.height()), (4, 2));
+        assert_eq!(reader.frame_rate(), (30000, 1001));
+        assert_eq!(reader.frame_duration(), 33_366_666);
+        let mut count = 0;
+        while let Some(frame) = reader.read_frame()? {
+            assert_eq!(frame.fourcc(), source.fourcc());
+            assert_eq!(frame.mmap().unwrap(), source.mmap().unwrap());
+            count += 1;
+        }
+        assert_eq!(count, 2);
+
+        // The same stream read as I420 keeps the planes in order.
+        let mut reader = Y4mReader::new(Cursor::new(&stream))?.with_fourcc(FourCC(*b"I420"))?;
+        let planar = reader.read_frame()?.unwrap();
+        assert_eq!(&planar.mmap().unwrap()[8..12], &[8, 10, 9, 11]);
+        let mut writer = Y4mWriter::new(Vec::new()).with_frame_rate(30000, 1001);
+        writer.write_frame(&planar)?;
+        assert_eq!(writer.into_inner()?, &stream[..header.len() + 18]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_yuyv() -> Result<(), Box<dyn Error>> {
+        let source = frame("YUYV", 4, 2)?;
+        let mut writer = Y4mWriter::new(Vec::new());
+        writer.write_frame(&source)?;
+        assert!(writer.write_frame(&frame("GREY", 4, 2)?).is_err());
+        let stream = writer.into_inner()?;
+        assert!(stream.starts_with(b"YUV4MPEG2 W4 H2 F30:1 Ip A1:1 C422\nFRAME\n"));
+
+        let mut reader = Y4mReader::new(Cursor::new(&stream))?;
+        assert_eq!(reader.colorspace(), Colorspace::C422);
+        assert!(Y4mReader::new(Cursor::new(&stream))?
+            .with_fourcc(FourCC(*b"NV12"))
+            .is_err());
+        let frame = reader.read_frame()?.unwrap();
+        assert_eq!(frame.mmap().unwrap(), source.mmap().unwrap());
+        assert!(reader.read_frame()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_header() {
+        for header in [
+            "YUV4MPEG2 H2\n",
+            "YUV4MPEG2 W4 H2 Ii\n",
+            "YUV4MPEG2 W4 H2 C444\n",
+            "YUV4MPEG2 W16385 H2\n",
+            "YUV4MPEG2 W4294967296 H4294967296\n",
+        ] {
+            assert!(Y4mReader::new(Cursor::new(header)).is_err(), "{}", header);
+        }
+        // Unknown parameters are skipped, whatever their leading character.
+        let reader = Y4mReader::new(Cursor::new("YUV4MPEG2 \u{e9}t\u{e9} W4 H2 X\n")).unwrap();
+        assert_eq!((reader.width(), reader.height()), (4, 2));
+    }
+}
@@ -115,6 +115,9 @@ pub const vsl_encode_profile_VSL_ENCODE_PROFILE_50000_KBPS: vsl_encode_profile =
 pub const vsl_encode_profile_VSL_ENCODE_PROFILE_100000_KBPS: vsl_encode_profile = 4;
 pub type vsl_encode_profile = ::std::os::raw::c_uint;
 pub use self::vsl_encode_profile as VSLEncoderProfile;
+pub const VSLDecoderCodec_VSL_DEC_H264: VSLDecoderCodec = 0;
+pub const VSLDecoderCodec_VSL_DEC_HEVC: VSLDecoderCodec = 1;
+pub type VSLDecoderCodec = ::std::os::raw::c_uint;
 #[doc = " Function pointer definition which will be called as part of\n @ref vsl_frame_unregister.  This is typically used to free resources\n associated with the frame on either client or host side."]
 pub type vsl_frame_cleanup = ::std::option::Option<unsafe extern "C" fn(frame: *mut VSLFrame)>;
 extern "C" {
@@ -509,7 +512,9 @@ extern "C" {
     ) -> ::std::os::raw::c_int;
 }
 extern "C" {
-    pub fn vsl_decoder_create(outputFourcc: u32, fps: ::std::os::raw::c_int) -> *mut VSLDecoder;
+    #[doc = " Creates a decoder for the @param codec bitstream, frames are decoded to\n NV12."]
+    pub fn vsl_decoder_create(codec: VSLDecoderCodec, fps: ::std::os::raw::c_int)
+        -> *mut VSLDecoder;
 }
 pub const VSLDecoderRetCode_VSL_DEC_SUCCESS: VSLDecoderRetCode = 0;
 pub const VSLDecoderRetCode_VSL_DEC_ERR: VSLDecoderRetCode = 1;
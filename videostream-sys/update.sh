@@ -1,3 +1,13 @@
 #!/bin/sh
 
-bindgen --allowlist-function 'vsl_.*' videostream.h > src/ffi.rs
\ No newline at end of file
+# videostream.h differs from the header shipped with libvideostream:
+# vsl_decoder_create takes the VSLDecoderCodec of the bitstream rather than
+# an output fourcc.  src/ffi.rs was edited by hand to the same declaration.
+# Keep this change when copying a newer header from the library, the check
+# below refuses to regenerate the bindings without it.
+if ! grep -q 'vsl_decoder_create(VSLDecoderCodec codec, int fps);' videostream.h; then
+    echo "videostream.h lost the VSLDecoderCodec vsl_decoder_create declaration" >&2
+    exit 1
+fi
+
+bindgen --allowlist-function 'vsl_.*' --allowlist-type 'VSLDecoderCodec' videostream.h > src/ffi.rs
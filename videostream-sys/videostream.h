@@ -1236,10 +1236,14 @@ VSL_API
 int
 vsl_camera_enum_mplane_fmts(const vsl_camera* ctx, uint32_t* codes, int size);
 
+/**
+ * Creates a decoder for the @param codec bitstream, frames are decoded to
+ * NV12.
+ */
 VSL_AVAILABLE_SINCE_1_4
 VSL_API
 VSLDecoder*
-vsl_decoder_create(uint32_t outputFourcc, int fps);
+vsl_decoder_create(VSLDecoderCodec codec, int fps);
 
 typedef enum {
     VSL_DEC_SUCCESS   = 0x0,